@@ -0,0 +1,313 @@
+use crate::ast::Expr;
+use crate::parser::MAX_DEPTH;
+use crate::record;
+use crate::symbol;
+use crate::symbol::Symbol;
+
+use std::rc::Rc;
+
+/// A program after syntax analysis. Special forms are recognised and
+/// checked once, so running a procedure body only dispatches on these.
+pub enum Node {
+    Const(Expr),
+    /// A variable not bound by an enclosing `lambda` or `let`, looked up
+    /// through the whole environment chain.
+    Global(Symbol),
+    /// A variable in a slot of the environment `depth` out from where it
+    /// is used.
+    Local(usize, usize, Symbol),
+    Set(Option<(usize, usize)>, Symbol, Rc<Node>),
+    Define(Symbol, Rc<Node>),
+    Lambda(Rc<Procedure>),
+    If(Rc<Node>, Rc<Node>, Option<Rc<Node>>),
+    Cond(Rc<[(Rc<Node>, Rc<Node>)]>, Option<Rc<Node>>),
+    Begin(Body),
+    Let(Rc<[(Symbol, Rc<Node>)]>, Rc<[Symbol]>, Body),
+    Call(Rc<Node>, Body),
+    /// Record and library forms, which are checked as they run.
+    Syntax(Symbol, Vec<Expr>),
+}
+
+/// A sequence of nodes. Nodes are shared so the evaluator can hold on to
+/// the rest of a sequence while it runs one part.
+pub type Body = Rc<[Rc<Node>]>;
+
+/// The analyzed parameters and body of a `lambda`. `names` are the
+/// parameters followed by the body's definitions, one for each slot of the
+/// environment the body runs in.
+pub struct Procedure {
+    pub params: Vec<Symbol>,
+    pub names: Rc<[Symbol]>,
+    pub body: Body,
+}
+
+/// The names bound in an environment the analyzer knows about, in slot
+/// order. A scope is open when its body has forms, like `import`, that bind
+/// names only known when they run, and variables are never resolved past
+/// one.
+struct Scope {
+    names: Rc<[Symbol]>,
+    open: bool,
+}
+
+#[derive(Default)]
+struct Analyzer {
+    scopes: Vec<Scope>,
+    depth: usize,
+}
+
+pub fn analyze(expr: &Expr) -> Result<Node, &'static str> {
+    Analyzer::default().expr(expr)
+}
+
+/// Collects the names a body defines, so they can be bound in its frame.
+/// Nested `lambda`s and `let` bodies have frames of their own. Returns
+/// false if the body has forms that bind other names as they run.
+pub fn body_defines(expr: &Expr, names: &mut Vec<Symbol>) -> bool {
+    let list = match expr {
+        Expr::List(list) => list,
+        _ => return true,
+    };
+
+    match list.first() {
+        Some(Expr::Var(symbol::DEFINE)) => match list.get(1) {
+            Some(Expr::Var(name)) => {
+                names.push(*name);
+                list.get(2).is_none_or(|value| body_defines(value, names))
+            },
+            Some(Expr::List(vars)) => {
+                if let Some(Expr::Var(name)) = vars.first() { names.push(*name) }
+                true
+            },
+            _ => true,
+        },
+        Some(Expr::Var(symbol::QUOTE)) | Some(Expr::Var(symbol::LAMBDA)) => true,
+        Some(Expr::Var(symbol::LET)) => {
+            let mut closed = true;
+            if let Some(Expr::List(decs)) = list.get(1) {
+                for dec in decs {
+                    if let Expr::List(dec) = dec && let Some(value) = dec.get(1) {
+                        closed &= body_defines(value, names);
+                    }
+                }
+            }
+            closed
+        },
+        Some(Expr::Var(symbol::DEFINE_RECORD_TYPE)) => match record::define(list) {
+            Ok(bindings) => {
+                names.extend(bindings.into_iter().map(|(name, _)| name));
+                true
+            },
+            Err(_) => false,
+        },
+        Some(Expr::Var(symbol::DEFINE_LIBRARY)) |
+        Some(Expr::Var(symbol::IMPORT)) |
+        Some(Expr::Var(symbol::INCLUDE)) |
+        Some(Expr::Var(symbol::INCLUDE_CI)) |
+        Some(Expr::Var(symbol::COND_EXPAND)) => false,
+        _ => {
+            let mut closed = true;
+            for expr in list {
+                closed &= body_defines(expr, names);
+            }
+            closed
+        },
+    }
+}
+
+impl Analyzer {
+    /// The depth and slot of a variable.
+    fn resolve(&self, name: Symbol) -> Option<(usize, usize)> {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(slot) = scope.names.iter().position(|n| *n == name) { return Some((depth, slot)) }
+            if scope.open { return None }
+        }
+        None
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Node, &'static str> {
+        if self.depth == MAX_DEPTH { return Err("nested too deeply") }
+        self.depth += 1;
+        let node = self.form(expr);
+        self.depth -= 1;
+        node
+    }
+
+    fn form(&mut self, expr: &Expr) -> Result<Node, &'static str> {
+        match expr {
+            Expr::List(list) => {
+                if list.is_empty() {
+                    return Ok(Node::Const(expr.clone()))
+                }
+
+                let head = &list[0];
+                match head {
+                    Expr::Var(atom) => {
+                        match *atom {
+                            symbol::LAMBDA => self.lambda(list),
+                            symbol::DEFINE => self.define(list),
+                            symbol::IF     => self.ifexpr(list),
+                            symbol::QUOTE  => {
+                                if list.len() != 2 { return Err("invalid quote syntax") }
+                                Ok(Node::Const(list[1].to_datum()))
+                            },
+                            symbol::SET    => self.set(list),
+                            symbol::BEGIN  => Ok(Node::Begin(self.exprs(&list[1..list.len()])?)),
+                            symbol::LET    => self.let_(list),
+                            symbol::COND   => self.cond(list),
+                            symbol::DEFINE_RECORD_TYPE |
+                            symbol::DEFINE_LIBRARY |
+                            symbol::IMPORT |
+                            symbol::INCLUDE |
+                            symbol::INCLUDE_CI |
+                            symbol::COND_EXPAND => Ok(Node::Syntax(*atom, list.to_vec())),
+                            _ => self.application(list),
+                        }
+                    },
+                    Expr::Literal(_) => Err("not applicable"),
+                    Expr::List(_) => self.application(list),
+                    _ => Err("not implemented"),
+                }
+            },
+
+            Expr::Var(atom) => {
+                match self.resolve(*atom) {
+                    Some((depth, slot)) => Ok(Node::Local(depth, slot, *atom)),
+                    None => Ok(Node::Global(*atom)),
+                }
+            },
+
+            Expr::Literal(_) |
+            Expr::Symbol(_) |
+            Expr::Bytevector(_) |
+            Expr::Vector(_) |
+            Expr::HashTable(_) |
+            Expr::Mapping(_) |
+            Expr::CharSet(_) |
+            Expr::Regexp(_) |
+            Expr::RegexpMatch(_) |
+            Expr::RecordType(_) |
+            Expr::Record(_) |
+            Expr::RecordProc(_) |
+            Expr::Port(_) |
+            Expr::Environment(_) |
+            Expr::Eof => Ok(Node::Const(expr.clone())),
+            _ => Err("not implemented"),
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) -> Result<Body, &'static str> {
+        exprs.iter().map(|expr| self.expr(expr).map(Rc::new)).collect()
+    }
+
+    /// Analyzes a body that runs in a new environment binding `names`,
+    /// returning every name the environment has a slot for.
+    fn body(&mut self, mut names: Vec<Symbol>, body: &[Expr]) -> Result<(Rc<[Symbol]>, Body), &'static str> {
+        let mut open = false;
+        for expr in body {
+            open |= !body_defines(expr, &mut names);
+        }
+
+        let names: Rc<[Symbol]> = names.into();
+        self.scopes.push(Scope { names: names.clone(), open });
+        let body = self.exprs(body);
+        self.scopes.pop();
+        Ok((names, body?))
+    }
+
+    fn procedure(&mut self, params: &[Expr], body: &[Expr]) -> Result<Node, &'static str> {
+        let mut names = Vec::new();
+        for param in params {
+            names.push(param.from_var().ok_or("lambda parameters must be identifiers")?);
+        }
+
+        let (slots, body) = self.body(names.clone(), body)?;
+        Ok(Node::Lambda(Rc::new(Procedure { params: names, names: slots, body })))
+    }
+
+    fn lambda(&mut self, list: &[Expr]) -> Result<Node, &'static str> {
+        match list.get(1) {
+            Some(Expr::List(params)) => self.procedure(params, &list[2..list.len()]),
+            _ => Err("not implemented"),
+        }
+    }
+
+    fn define(&mut self, list: &[Expr]) -> Result<Node, &'static str> {
+        if list.len() < 3 { return Err("invalid define statement"); }
+
+        match &list[1] {
+            Expr::List(vars) => {
+                if vars.is_empty() { return Err("define vars cannot be empty") }
+
+                let name = vars[0].from_var().ok_or("define name must be an identifier")?;
+                let proc = self.procedure(&vars[1..vars.len()], &list[2..list.len()])?;
+                Ok(Node::Define(name, Rc::new(proc)))
+            },
+            Expr::Var(atom) => Ok(Node::Define(*atom, Rc::new(self.expr(&list[2])?))),
+            _ => Err("invalid define statement"),
+        }
+    }
+
+    fn ifexpr(&mut self, list: &[Expr]) -> Result<Node, &'static str> {
+        if list.len() != 3 && list.len() != 4 { return Err("invalid if syntax") }
+
+        let alternate = match list.get(3) {
+            Some(expr) => Some(Rc::new(self.expr(expr)?)),
+            None => None,
+        };
+        Ok(Node::If(Rc::new(self.expr(&list[1])?), Rc::new(self.expr(&list[2])?), alternate))
+    }
+
+    fn set(&mut self, list: &[Expr]) -> Result<Node, &'static str> {
+        if list.len() != 3 { return Err("invalid set syntax") }
+
+        let name = list[1].from_var().ok_or("first parameter must be an atom")?;
+        Ok(Node::Set(self.resolve(name), name, Rc::new(self.expr(&list[2])?)))
+    }
+
+    fn let_(&mut self, list: &[Expr]) -> Result<Node, &'static str> {
+        let decs = match list.get(1) {
+            Some(Expr::List(decs)) => decs,
+            _ => return Err("expecting list of declarations"),
+        };
+
+        let mut bindings = Vec::new();
+        for dec in decs {
+            let dec = match dec {
+                Expr::List(dec) if dec.len() == 2 => dec,
+                _ => return Err("expecting a pair"),
+            };
+            let var = dec[0].from_var().ok_or("expecting an atom in let declaration pair")?;
+            bindings.push((var, Rc::new(self.expr(&dec[1])?)));
+        }
+
+        let names = bindings.iter().map(|(var, _)| *var).collect();
+        let (names, body) = self.body(names, &list[2..list.len()])?;
+        Ok(Node::Let(bindings.into(), names, body))
+    }
+
+    fn cond(&mut self, list: &[Expr]) -> Result<Node, &'static str> {
+        let mut clauses = Vec::new();
+        for clause in &list[1..list.len()] {
+            let clause = match clause {
+                Expr::List(clause) => clause,
+                _ => return Err("expecting a list in cond"),
+            };
+            if clause.len() != 2 { return Err("invalid format in cond") }
+
+            if let Expr::Var(pred) = &clause[0] {
+                if *pred != symbol::ELSE { return Err("expecting else in cond") }
+                return Ok(Node::Cond(clauses.into(), Some(Rc::new(self.expr(&clause[1])?))));
+            }
+
+            clauses.push((Rc::new(self.expr(&clause[0])?), Rc::new(self.expr(&clause[1])?)));
+        }
+
+        Ok(Node::Cond(clauses.into(), None))
+    }
+
+    fn application(&mut self, list: &[Expr]) -> Result<Node, &'static str> {
+        let proc = self.expr(&list[0])?;
+        Ok(Node::Call(Rc::new(proc), self.exprs(&list[1..list.len()])?))
+    }
+}
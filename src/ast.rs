@@ -1,9 +1,21 @@
 use std::fmt;
 use std::cmp::PartialEq;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::analyze::Procedure;
+use crate::charset::CharSet;
 use crate::env::Env;
+use crate::hashtable::HashTable;
+use crate::mapping::Mapping;
+use crate::port::Port;
+use crate::printer;
+use crate::record::{Record, RecordProc, RecordType};
+use crate::regexp::{Regexp, RegexpMatch};
+use crate::symbol::Symbol;
+use crate::vm::Closure;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TokenType {
@@ -18,8 +30,13 @@ pub enum TokenType {
     Number,
     Float,
     String,
+    Char,
     Identifier,
     Quote,
+    Bytevector,
+    Vector,
+    LabelDef,
+    LabelRef,
     EOF,
 }
 
@@ -29,14 +46,12 @@ pub enum Literal {
     Number(i32),
     Bool(bool),
     String(String),
+    Char(char),
 }
 
 impl Literal {
     pub fn is_string(&self) -> bool {
-        match self {
-            Literal::String(_) => true,
-            _ => false,
-        }
+        matches!(self, Literal::String(_))
     }
 
     pub fn to_string(&self) -> Option<String> {
@@ -50,7 +65,19 @@ impl Literal {
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Literal::Float(d) => write!(f, "{}", d),
+            Literal::Float(d) => {
+                if d.is_nan() {
+                    write!(f, "+nan.0")
+                } else if d.is_infinite() {
+                    write!(f, "{}inf.0", if *d > 0.0 { "+" } else { "-" })
+                } else if d.fract() == 0.0 {
+                    // Keep a decimal point or exponent so the number reads
+                    // back inexact.
+                    if d.abs() < 1e16 { write!(f, "{:.1}", d) } else { write!(f, "{:e}", d) }
+                } else {
+                    write!(f, "{}", d)
+                }
+            },
             Literal::Number(d) => write!(f, "{}", d),
             Literal::Bool(b) => {
                 if *b {
@@ -59,11 +86,34 @@ impl fmt::Display for Literal {
                     write!(f, "#f")
                 }
             },
-            Literal::String(s) => write!(f, "{:?}", s),
+            Literal::String(s) => write!(f, "{}", printer::write_string(s)),
+            Literal::Char(c) => write!(f, "{}", printer::write_char(*c)),
         }
     }
 }
 
+/// Names for characters that are written as `#\name`.
+const CHAR_NAMES: &[(char, &str)] = &[
+    ('\x07', "alarm"),
+    ('\x08', "backspace"),
+    ('\x7f', "delete"),
+    ('\x1b', "escape"),
+    ('\n', "newline"),
+    ('\0', "null"),
+    ('\r', "return"),
+    (' ', "space"),
+    ('\t', "tab"),
+];
+
+pub fn char_name(c: char) -> Option<&'static str> {
+    CHAR_NAMES.iter().find(|(ch, _)| *ch == c).map(|(_, name)| *name)
+}
+
+pub fn char_from_name(name: &str) -> Option<char> {
+    if name == "nul" { return Some('\0') }
+    CHAR_NAMES.iter().find(|(_, n)| *n == name).map(|(ch, _)| *ch)
+}
+
 #[derive(Clone)]
 pub struct Token {
     pub ttype: TokenType,
@@ -93,23 +143,46 @@ impl fmt::Debug for Token {
     }
 }
 
+pub type Builtin = fn(&[Expr], Rc<RefCell<Env>>) -> Result<Expr, &'static str>;
+
+/// A procedure created by `lambda` or `define`. It closes over the
+/// environment it was created in, so free variables in the body are
+/// resolved lexically rather than in the caller's environment.
+pub struct Lambda {
+    pub procedure: Rc<Procedure>,
+    pub env: Rc<RefCell<Env>>,
+}
+
 #[derive(Clone)]
 pub enum Expr {
     DottedPair(Vec<Expr>, Box<Expr>),
     List(Vec<Expr>),
-    Lambda(Vec<Expr>, Vec<Expr>),
-    Var(String),
+    Lambda(Rc<Lambda>),
+    Closure(Rc<Closure>),
+    Var(Symbol),
+    Symbol(Symbol),
     Literal(Literal),
-    Builtin(fn(&[Expr], Rc<RefCell<Env>>) -> Result<Expr, &'static str>),
+    Bytevector(Rc<RefCell<Vec<u8>>>),
+    Vector(Rc<RefCell<Vec<Expr>>>),
+    HashTable(Rc<RefCell<HashTable>>),
+    Mapping(Rc<Mapping>),
+    CharSet(Rc<CharSet>),
+    Regexp(Rc<Regexp>),
+    RegexpMatch(Rc<RegexpMatch>),
+    RecordType(Rc<RecordType>),
+    Record(Rc<Record>),
+    RecordProc(Rc<RecordProc>),
+    Values(Vec<Expr>),
+    Port(Rc<Port>),
+    Environment(Rc<RefCell<Env>>),
+    Eof,
+    Builtin(Builtin),
     Unspecified,
 }
 
 impl Expr {
     pub fn is_list(&self) -> bool {
-        match self {
-            Expr::List(_) => true,
-            _ => false,
-        }
+        matches!(self, Expr::List(_))
     }
 
     pub fn to_vec(&self) -> Option<Vec<Expr>> {
@@ -120,10 +193,7 @@ impl Expr {
     }
 
     pub fn is_literal(&self) -> bool {
-        match self {
-            Expr::Literal(_) => true,
-            _ => false,
-        }
+        matches!(self, Expr::Literal(_))
     }
 
     pub fn to_literal(&self) -> Option<Literal> {
@@ -134,40 +204,67 @@ impl Expr {
     }
 
     pub fn is_true(&self) -> bool {
-        match self {
-            Expr::Literal(Literal::Bool(true)) => true,
-            _ => false,
-        }
+        matches!(self, Expr::Literal(Literal::Bool(true)))
     }
 
     pub fn is_false(&self) -> bool {
-        match self {
-            Expr::Literal(Literal::Bool(false)) => true,
-            _ => false,
-        }
+        matches!(self, Expr::Literal(Literal::Bool(false)))
     }
 
     pub fn is_var(&self) -> bool {
+        matches!(self, Expr::Var(_))
+    }
+
+    pub fn from_var(&self) -> Option<Symbol> {
         match self {
-            Expr::Var(_) => true,
-            _ => false,
+            Expr::Var(v) => Some(*v),
+            _ => None,
         }
     }
 
-    pub fn from_var(&self) -> Option<String> {
+    pub fn is_symbol(&self) -> bool {
+        matches!(self, Expr::Symbol(_))
+    }
+
+    pub fn to_symbol(&self) -> Option<Symbol> {
         match self {
-            Expr::Var(v) => Some(v.clone()),
+            Expr::Symbol(s) => Some(*s),
             _ => None,
         }
     }
 
-    pub fn is_dotted_pair(&self) -> bool {
+    /// Converts parsed code into quoted data, turning variable references
+    /// into symbols.
+    pub fn to_datum(&self) -> Expr {
         match self {
-            Expr::DottedPair(_, _) => true,
-            _ => false,
+            Expr::Var(v) => Expr::Symbol(*v),
+            Expr::List(l) => Expr::List(l.iter().map(|e| e.to_datum()).collect()),
+            Expr::DottedPair(car, cdr) => {
+                Expr::DottedPair(car.iter().map(|e| e.to_datum()).collect(),
+                                 Box::new(cdr.to_datum()))
+            },
+            _ => self.clone(),
         }
     }
 
+    /// The inverse of `to_datum`: turns symbols in a datum back into
+    /// variable references so it can be evaluated.
+    pub fn to_code(&self) -> Expr {
+        match self {
+            Expr::Symbol(s) => Expr::Var(*s),
+            Expr::List(l) => Expr::List(l.iter().map(|e| e.to_code()).collect()),
+            Expr::DottedPair(car, cdr) => {
+                Expr::DottedPair(car.iter().map(|e| e.to_code()).collect(),
+                                 Box::new(cdr.to_code()))
+            },
+            _ => self.clone(),
+        }
+    }
+
+    pub fn is_dotted_pair(&self) -> bool {
+        matches!(self, Expr::DottedPair(_, _))
+    }
+
     pub fn from_dotted_pair(&self) -> Option<(Vec<Expr>, Box<Expr>)> {
         match self {
             Expr::DottedPair(car, cdr) => Some((car.clone(), cdr.clone())),
@@ -175,140 +272,197 @@ impl Expr {
         }
     }
 
-    pub fn is_unspecified(&self) -> bool {
+    pub fn is_bytevector(&self) -> bool {
+        matches!(self, Expr::Bytevector(_))
+    }
+
+    pub fn to_bytevector(&self) -> Option<Rc<RefCell<Vec<u8>>>> {
         match self {
-            Expr::Unspecified => true,
-            _ => false,
+            Expr::Bytevector(b) => Some(b.clone()),
+            _ => None,
         }
     }
+
+    pub fn to_vector(&self) -> Option<Rc<RefCell<Vec<Expr>>>> {
+        match self {
+            Expr::Vector(v) => Some(v.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn to_hash_table(&self) -> Option<Rc<RefCell<HashTable>>> {
+        match self {
+            Expr::HashTable(h) => Some(h.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_unspecified(&self) -> bool {
+        matches!(self, Expr::Unspecified)
+    }
 }
 
 impl PartialEq for Expr {
     fn eq(&self, other: &Self) -> bool {
-        if self.is_literal() && other.is_literal() {
-            let lval = self.to_literal().unwrap();
-            let rval = other.to_literal().unwrap();
-            return lval == rval;
-        } else if self.is_var() && other.is_var() {
-            let lval = self.from_var().unwrap();
-            let rval = other.from_var().unwrap();
-            return lval == rval;
-        } else if self.is_list() && other.is_list() {
-            let lval = self.to_vec().unwrap();
-            let rval = self.to_vec().unwrap();
-
-            if lval.len() != rval.len() {
-                return false;
-            }
-
-            for (l, r) in lval.iter().zip(rval) {
-                if l.eq(&r) {
-                    continue
-                } else {
-                    return false;
-                }
-            }
+        equal(self, other, &mut HashSet::new())
+    }
+}
 
-            return true;
-        } else if self.is_dotted_pair() && other.is_dotted_pair() {
-            let (lcar, lcdr) = self.from_dotted_pair().unwrap();
-            let (rcar, rcdr) = other.from_dotted_pair().unwrap();
+/// Pairs of vectors or records being compared. A pair met again while it
+/// is still being compared is taken as equal, so `equal?` terminates on
+/// circular data.
+type Seen = HashSet<(usize, usize)>;
 
-            if lcar.len() != rcar.len() {
-                return false;
-            }
+fn address<T: ?Sized>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
 
-            if !lcdr.eq(&rcdr) {
-                return false
-            }
+fn equal_all(lval: &[Expr], rval: &[Expr], seen: &mut Seen) -> bool {
+    lval.len() == rval.len() && lval.iter().zip(rval).all(|(l, r)| equal(l, r, seen))
+}
 
-            for (l, r) in lcar.iter().zip(rcar) {
-                if l.eq(&r) {
-                    continue
-                } else {
-                    return false;
-                }
-            }
+fn equal(this: &Expr, other: &Expr, seen: &mut Seen) -> bool {
+    if this.is_literal() && other.is_literal() {
+        let lval = this.to_literal().unwrap();
+        let rval = other.to_literal().unwrap();
+        return lval == rval;
+    } else if this.is_var() && other.is_var() {
+        let lval = this.from_var().unwrap();
+        let rval = other.from_var().unwrap();
+        return lval == rval;
+    } else if this.is_symbol() && other.is_symbol() {
+        return this.to_symbol() == other.to_symbol();
+    } else if let (Expr::List(lval), Expr::List(rval)) = (this, other) {
+        return equal_all(lval, rval, seen);
+    } else if let (Expr::DottedPair(lcar, lcdr), Expr::DottedPair(rcar, rcdr)) = (this, other) {
+        return equal(lcdr, rcdr, seen) && equal_all(lcar, rcar, seen);
+    } else if this.is_bytevector() && other.is_bytevector() {
+        let lval = this.to_bytevector().unwrap();
+        let rval = other.to_bytevector().unwrap();
+        return *lval.borrow() == *rval.borrow();
+    } else if let (Expr::Vector(l), Expr::Vector(r)) = (this, other) {
+        if Rc::ptr_eq(l, r) || !seen.insert((address(l), address(r))) { return true }
+        return equal_all(&l.borrow(), &r.borrow(), seen);
+    } else if let (Expr::Values(l), Expr::Values(r)) = (this, other) {
+        return equal_all(l, r, seen);
+    } else if let (Expr::HashTable(l), Expr::HashTable(r)) = (this, other) {
+        return Rc::ptr_eq(l, r);
+    } else if let (Expr::Mapping(l), Expr::Mapping(r)) = (this, other) {
+        return Rc::ptr_eq(l, r);
+    } else if let (Expr::CharSet(l), Expr::CharSet(r)) = (this, other) {
+        return l == r;
+    } else if let (Expr::Regexp(l), Expr::Regexp(r)) = (this, other) {
+        return Rc::ptr_eq(l, r);
+    } else if let (Expr::RegexpMatch(l), Expr::RegexpMatch(r)) = (this, other) {
+        return Rc::ptr_eq(l, r);
+    } else if let (Expr::Port(l), Expr::Port(r)) = (this, other) {
+        return Rc::ptr_eq(l, r);
+    } else if let (Expr::Environment(l), Expr::Environment(r)) = (this, other) {
+        return Rc::ptr_eq(l, r);
+    } else if let (Expr::Eof, Expr::Eof) = (this, other) {
+        return true;
+    } else if let (Expr::RecordType(l), Expr::RecordType(r)) = (this, other) {
+        return Rc::ptr_eq(l, r);
+    } else if let (Expr::Record(l), Expr::Record(r)) = (this, other) {
+        if !Rc::ptr_eq(&l.rtype, &r.rtype) { return false }
+        if Rc::ptr_eq(l, r) || !seen.insert((address(l), address(r))) { return true }
+        return equal_all(&l.fields.borrow(), &r.fields.borrow(), seen);
+    } else if let (Expr::Lambda(l), Expr::Lambda(r)) = (this, other) {
+        return Rc::ptr_eq(l, r);
+    } else if let (Expr::Closure(l), Expr::Closure(r)) = (this, other) {
+        return Rc::ptr_eq(l, r);
+    } else if let (Expr::RecordProc(l), Expr::RecordProc(r)) = (this, other) {
+        return Rc::ptr_eq(l, r);
+    } else if let (Expr::Builtin(l), Expr::Builtin(r)) = (this, other) {
+        return std::ptr::fn_addr_eq(*l, *r);
+    }
 
-            return true;
+    false
+}
+
+impl Hash for Literal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            // 0.0 and -0.0 are equal, so they must hash alike.
+            Literal::Float(f) => {
+                if *f == 0.0 { 0u64.hash(state) } else { f.to_bits().hash(state) }
+            },
+            Literal::Number(n) => n.hash(state),
+            Literal::Bool(b) => b.hash(state),
+            Literal::String(s) => s.hash(state),
+            Literal::Char(c) => c.hash(state),
         }
+    }
+}
 
-        false
+/// How many vectors or records deep hashing looks. Deeper contents are
+/// left out, which keeps equal data hashing alike and stops circular data
+/// from hashing forever.
+const HASH_DEPTH: usize = 4;
+
+/// Hashes consistently with `PartialEq`, i.e. `equal?`: structurally for
+/// data, records and char-sets, by identity for hash tables, mappings,
+/// regexps, ports, environments, record types and procedures.
+impl Hash for Expr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash_to(state, HASH_DEPTH);
     }
 }
 
-impl fmt::Display for Expr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Expr {
+    fn hash_to<H: Hasher>(&self, state: &mut H, depth: usize) {
+        std::mem::discriminant(self).hash(state);
         match self {
-            Expr::DottedPair(lexpr, rexpr) => {
-                write!(f, "(").unwrap();
-                for (i, l) in lexpr.iter().enumerate() {
-                    if i < lexpr.len() - 1 {
-                        write!(f, "{} ", l).unwrap();
-                    } else {
-                        write!(f, "{}", l).unwrap();
-                    }
-                }
-                write!(f, " . {})", rexpr)
-            },
-            Expr::List(lexpr) => {
-                write!(f, "(").unwrap();
-                for (i, l) in lexpr.iter().enumerate() {
-                    if i < lexpr.len() - 1 {
-                        write!(f, "{} ", l).unwrap();
-                    } else {
-                        write!(f, "{}", l).unwrap();
-                    }
-                }
-                write!(f, ")")
+            Expr::DottedPair(car, cdr) => {
+                hash_all(car, state, depth);
+                cdr.hash_to(state, depth);
             },
-            Expr::Lambda(_, _) => {
-                write!(f, "#<procedure>")
+            Expr::List(l) | Expr::Values(l) => hash_all(l, state, depth),
+            Expr::Var(v) | Expr::Symbol(v) => v.hash(state),
+            Expr::Literal(l) => l.hash(state),
+            Expr::Bytevector(b) => b.borrow().hash(state),
+            Expr::Vector(v) => {
+                let v = v.borrow();
+                v.len().hash(state);
+                if depth > 0 { hash_all(&v, state, depth - 1) }
             },
-            Expr::Var(t) => write!(f, "{}", t),
-            Expr::Literal(t) => write!(f, "{}", t),
-            Expr::Builtin(_) => {
-                write!(f, "#<built-in procedure>")
+            Expr::HashTable(h) => Rc::as_ptr(h).hash(state),
+            Expr::Mapping(m) => Rc::as_ptr(m).hash(state),
+            Expr::CharSet(c) => c.hash(state),
+            Expr::Regexp(r) => Rc::as_ptr(r).hash(state),
+            Expr::RegexpMatch(m) => Rc::as_ptr(m).hash(state),
+            Expr::Port(p) => Rc::as_ptr(p).hash(state),
+            Expr::Environment(e) => Rc::as_ptr(e).hash(state),
+            Expr::RecordType(t) => Rc::as_ptr(t).hash(state),
+            Expr::Record(r) => {
+                Rc::as_ptr(&r.rtype).hash(state);
+                if depth > 0 { hash_all(&r.fields.borrow(), state, depth - 1) }
             },
-            Expr::Unspecified => write!(f, "#unspecified"),
+            Expr::Lambda(l) => Rc::as_ptr(l).hash(state),
+            Expr::Closure(c) => Rc::as_ptr(c).hash(state),
+            Expr::RecordProc(p) => Rc::as_ptr(p).hash(state),
+            Expr::Builtin(b) => (*b as usize).hash(state),
+            Expr::Eof | Expr::Unspecified => (),
         }
     }
 }
 
+fn hash_all<H: Hasher>(exprs: &[Expr], state: &mut H, depth: usize) {
+    exprs.len().hash(state);
+    for expr in exprs {
+        expr.hash_to(state, depth);
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", printer::to_string(self, printer::Mode::Write))
+    }
+}
+
 impl fmt::Debug for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expr::DottedPair(lexpr, rexpr) => {
-                write!(f, "(").unwrap();
-                for (i, l) in lexpr.iter().enumerate() {
-                    if i < lexpr.len() - 1 {
-                        write!(f, "{} ", l).unwrap();
-                    } else {
-                        write!(f, "{}", l).unwrap();
-                    }
-                }
-                write!(f, " . {})", rexpr)
-            },
-            Expr::List(lexpr) => {
-                write!(f, "(").unwrap();
-                for (i, l) in lexpr.iter().enumerate() {
-                    if i < lexpr.len() - 1 {
-                        write!(f, "{} ", l).unwrap();
-                    } else {
-                        write!(f, "{}", l).unwrap();
-                    }
-                }
-                write!(f, ")")
-            },
-            Expr::Lambda(_, _) => {
-                write!(f, "#<procedure>")
-            },
-            Expr::Var(t) => write!(f, "{}", t),
-            Expr::Literal(t) => write!(f, "{}", t),
-            Expr::Builtin(_) => {
-                write!(f, "#<built-in procedure>")
-            },
-            Expr::Unspecified => write!(f, "#unspecified"),
-        }
+        write!(f, "{}", printer::to_string(self, printer::Mode::Write))
     }
 }
@@ -1,8 +1,8 @@
+use std::cmp::Ordering;
 use std::ops::Neg;
-use std::fs::File;
-use std::io;
-use std::io::prelude::*;
 use std::path::Path;
+use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -10,9 +10,23 @@ use std::rc::Rc;
 use crate::eval;
 use crate::eval::eval;
 use crate::env::Env;
-use crate::ast::{Expr, Literal};
-use crate::lexer::Lexer;
-use crate::parser::Parser;
+use crate::gc;
+use crate::ast::{Builtin, Expr, Literal};
+use crate::charset::CharSet;
+use crate::hashtable;
+use crate::hashtable::HashTable;
+use crate::json;
+use crate::lexer;
+use crate::library;
+use crate::mapping;
+use crate::mapping::Mapping;
+use crate::port;
+use crate::port::Port;
+use crate::printer;
+use crate::reader;
+use crate::sandbox;
+use crate::regexp::{Regexp, RegexpMatch};
+use crate::symbol::Symbol;
 
 /*
  * Numerical built-ins
@@ -53,7 +67,7 @@ pub fn lt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
             _ => return Err("must be a number"),
         };
 
-        if !(previous < current) {
+        if previous >= current {
             return Ok(Expr::Literal(Literal::Bool(false)))
         }
     }
@@ -74,7 +88,7 @@ pub fn lte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str>
             _ => return Err("must be a number"),
         };
 
-        if !(previous <= current) {
+        if previous > current {
             return Ok(Expr::Literal(Literal::Bool(false)))
         }
     }
@@ -95,7 +109,7 @@ pub fn gt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
             _ => return Err("must be a number"),
         };
 
-        if !(previous > current) {
+        if previous <= current {
             return Ok(Expr::Literal(Literal::Bool(false)))
         }
     }
@@ -116,7 +130,7 @@ pub fn gte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str>
             _ => return Err("must be a number"),
         };
 
-        if !(previous >= current) {
+        if previous < current {
             return Ok(Expr::Literal(Literal::Bool(false)))
         }
     }
@@ -126,9 +140,7 @@ pub fn gte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str>
 pub fn add(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
     let mut result: i32 = 0;
     for val in list.iter() {
-        let val = eval(val, env.clone());
-        if val.is_err() { return val }
-        let val = val.unwrap();
+        let val = eval(val, env.clone())?;
         match val {
             Expr::Literal(Literal::Number(n)) => result += n,
             Expr::Literal(Literal::Float(f)) => result += f as i32,
@@ -142,9 +154,7 @@ pub fn add(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
 pub fn mul(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
     let mut result: i32 = 1;
     for val in list.iter() {
-        let val = eval(val, env.clone());
-        if val.is_err() { return val }
-        let val = val.unwrap();
+        let val = eval(val, env.clone())?;
         match val {
             Expr::Literal(Literal::Number(n)) => result *= n,
             Expr::Literal(Literal::Float(f)) => result *= f as i32,
@@ -159,9 +169,7 @@ pub fn sub(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
     if list.is_empty() { return Err("- requires at least one argument") }
 
     let val = &list[0];
-    let val = eval(val, env.clone());
-    if val.is_err() { return val }
-    let val = val.unwrap();
+    let val = eval(val, env.clone())?;
     let mut result = match val {
         Expr::Literal(Literal::Number(n)) => n,
         Expr::Literal(Literal::Float(f)) => f as i32,
@@ -173,9 +181,7 @@ pub fn sub(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
     }
 
     for val in list.iter().skip(1) {
-        let val = eval(val, env.clone());
-        if val.is_err() { return val }
-        let val = val.unwrap();
+        let val = eval(val, env.clone())?;
         match val {
             Expr::Literal(Literal::Number(n)) => result -= n,
             Expr::Literal(Literal::Float(f)) => result -= f as i32,
@@ -186,6 +192,352 @@ pub fn sub(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
     Ok(Expr::Literal(Literal::Number(result)))
 }
 
+fn radix_arg(list: &[Expr], i: usize) -> Result<u32, &'static str> {
+    match list.get(i) {
+        None => Ok(10),
+        Some(Expr::Literal(Literal::Number(n))) if [2, 8, 10, 16].contains(n) => Ok(*n as u32),
+        Some(_) => Err("radix must be 2, 8, 10 or 16"),
+    }
+}
+
+pub fn number_to_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let radix = radix_arg(list, 1)?;
+    let string = match &list[0] {
+        Expr::Literal(Literal::Number(n)) => {
+            let digits = match radix {
+                2 => format!("{:b}", n.unsigned_abs()),
+                8 => format!("{:o}", n.unsigned_abs()),
+                16 => format!("{:x}", n.unsigned_abs()),
+                _ => n.unsigned_abs().to_string(),
+            };
+            if *n < 0 { format!("-{}", digits) } else { digits }
+        },
+        Expr::Literal(l @ Literal::Float(_)) => {
+            if radix != 10 { return Err("inexact numbers can only be written in radix 10") }
+            format!("{}", l)
+        },
+        _ => return Err("expecting a number"),
+    };
+
+    Ok(Expr::Literal(Literal::String(string)))
+}
+
+pub fn string_to_number(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let string = string_arg(&list[0])?;
+    let radix = radix_arg(list, 1)?;
+    match lexer::parse_number(&string, radix) {
+        Some(n) => Ok(Expr::Literal(n)),
+        None => Ok(Expr::Literal(Literal::Bool(false))),
+    }
+}
+
+fn int_arg(expr: &Expr) -> Result<i32, &'static str> {
+    match expr {
+        Expr::Literal(Literal::Number(n)) => Ok(*n),
+        _ => Err("expecting an exact integer"),
+    }
+}
+
+fn float_expr(f: f64) -> Expr {
+    Expr::Literal(Literal::Float(f))
+}
+
+pub fn div(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let (mut result, rest) = if list.len() == 1 {
+        (Expr::Literal(Literal::Number(1)), list)
+    } else {
+        (list[0].clone(), &list[1..])
+    };
+
+    for divisor in rest {
+        result = match (&result, divisor) {
+            (Expr::Literal(Literal::Number(a)), Expr::Literal(Literal::Number(b))) => {
+                if *b == 0 { return Err("division by zero") }
+                if a % b == 0 {
+                    Expr::Literal(Literal::Number(a / b))
+                } else {
+                    float_expr(*a as f64 / *b as f64)
+                }
+            },
+            (a, b) => float_expr(float_arg(a)? / float_arg(b)?),
+        };
+    }
+    Ok(result)
+}
+
+/// Applies an integer division operator, checking for a zero divisor.
+fn int_div(list: &[Expr], op: fn(i32, i32) -> i32) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let (a, b) = (int_arg(&list[0])?, int_arg(&list[1])?);
+    if b == 0 { return Err("division by zero") }
+    Ok(Expr::Literal(Literal::Number(op(a, b))))
+}
+
+pub fn quotient(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_div(list, |a, b| a.wrapping_div(b))
+}
+
+pub fn remainder(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_div(list, |a, b| a.wrapping_rem(b))
+}
+
+/// Like `remainder`, but the result has the sign of the divisor.
+pub fn modulo(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_div(list, |a, b| {
+        let r = a.wrapping_rem(b);
+        if r != 0 && (r < 0) != (b < 0) { r + b } else { r }
+    })
+}
+
+pub fn abs(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::Literal(Literal::Number(n)) => Ok(Expr::Literal(Literal::Number(n.wrapping_abs()))),
+        Expr::Literal(Literal::Float(f)) => Ok(float_expr(f.abs())),
+        _ => Err("expecting a number"),
+    }
+}
+
+/// Returns the extreme argument by `pick`. The result is inexact if any
+/// argument is.
+fn extremum(list: &[Expr], pick: fn(f64, f64) -> bool) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let mut best = &list[0];
+    let mut inexact = false;
+    for n in list {
+        inexact |= matches!(n, Expr::Literal(Literal::Float(_)));
+        if pick(float_arg(n)?, float_arg(best)?) {
+            best = n;
+        }
+    }
+    if inexact { Ok(float_expr(float_arg(best)?)) } else { Ok(best.clone()) }
+}
+
+pub fn min(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    extremum(list, |a, b| a < b)
+}
+
+pub fn max(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    extremum(list, |a, b| a > b)
+}
+
+fn number_test(list: &[Expr], test: fn(f64) -> bool) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(test(float_arg(&list[0])?))))
+}
+
+pub fn zerop(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    number_test(list, |n| n == 0.0)
+}
+
+pub fn positivep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    number_test(list, |n| n > 0.0)
+}
+
+pub fn negativep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    number_test(list, |n| n < 0.0)
+}
+
+pub fn oddp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(int_arg(&list[0])? % 2 != 0)))
+}
+
+pub fn evenp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(int_arg(&list[0])? % 2 == 0)))
+}
+
+pub fn integerp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let integer = match &list[0] {
+        Expr::Literal(Literal::Number(_)) => true,
+        Expr::Literal(Literal::Float(f)) => f.fract() == 0.0,
+        _ => false,
+    };
+    Ok(Expr::Literal(Literal::Bool(integer)))
+}
+
+pub fn exactp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    float_arg(&list[0])?;
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Literal(Literal::Number(_))))))
+}
+
+pub fn inexactp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    float_arg(&list[0])?;
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Literal(Literal::Float(_))))))
+}
+
+pub fn exact_integerp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Literal(Literal::Number(_))))))
+}
+
+pub fn exact(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::Literal(Literal::Number(n)) => Ok(Expr::Literal(Literal::Number(*n))),
+        Expr::Literal(Literal::Float(f)) => {
+            if f.fract() != 0.0 || *f < i32::MIN as f64 || *f > i32::MAX as f64 {
+                return Err("no exact representation");
+            }
+            Ok(Expr::Literal(Literal::Number(*f as i32)))
+        },
+        _ => Err("expecting a number"),
+    }
+}
+
+pub fn inexact(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(float_expr(float_arg(&list[0])?))
+}
+
+/// Rounds inexact numbers with `op`. Exact integers are already whole.
+fn round_with(list: &[Expr], op: fn(f64) -> f64) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::Literal(Literal::Number(n)) => Ok(Expr::Literal(Literal::Number(*n))),
+        Expr::Literal(Literal::Float(f)) => Ok(float_expr(op(*f))),
+        _ => Err("expecting a number"),
+    }
+}
+
+pub fn floor(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    round_with(list, f64::floor)
+}
+
+pub fn ceiling(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    round_with(list, f64::ceil)
+}
+
+/// Rounds to even on ties, as R7RS requires.
+pub fn round(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    round_with(list, f64::round_ties_even)
+}
+
+pub fn truncate(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    round_with(list, f64::trunc)
+}
+
+pub fn square(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::Literal(Literal::Number(n)) => match n.checked_mul(*n) {
+            Some(sq) => Ok(Expr::Literal(Literal::Number(sq))),
+            None => Ok(float_expr(*n as f64 * *n as f64)),
+        },
+        n => Ok(float_expr(float_arg(n)?.powi(2))),
+    }
+}
+
+fn inexact_op(list: &[Expr], op: fn(f64) -> f64) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(float_expr(op(float_arg(&list[0])?)))
+}
+
+pub fn exp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    inexact_op(list, f64::exp)
+}
+
+/// `(log z)` is the natural logarithm and `(log z base)` the logarithm in
+/// the given base.
+pub fn log(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    match list {
+        [z] => Ok(float_expr(float_arg(z)?.ln())),
+        [z, base] => Ok(float_expr(float_arg(z)?.log(float_arg(base)?))),
+        _ => Err("called with incorrect number of arguments"),
+    }
+}
+
+pub fn sin(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    inexact_op(list, f64::sin)
+}
+
+pub fn cos(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    inexact_op(list, f64::cos)
+}
+
+pub fn tan(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    inexact_op(list, f64::tan)
+}
+
+pub fn asin(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    inexact_op(list, f64::asin)
+}
+
+pub fn acos(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    inexact_op(list, f64::acos)
+}
+
+pub fn atan(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    match list {
+        [z] => Ok(float_expr(float_arg(z)?.atan())),
+        [y, x] => Ok(float_expr(float_arg(y)?.atan2(float_arg(x)?))),
+        _ => Err("called with incorrect number of arguments"),
+    }
+}
+
+/// The square root is exact for exact perfect squares.
+pub fn sqrt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let root = float_arg(&list[0])?.sqrt();
+    if let Expr::Literal(Literal::Number(n)) = list[0] && root.fract() == 0.0 && (root as i32).checked_mul(root as i32) == Some(n) {
+        return Ok(Expr::Literal(Literal::Number(root as i32)));
+    }
+    Ok(float_expr(root))
+}
+
+/// Returns the integer square root and the remainder as two values.
+pub fn exact_integer_sqrt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let n = int_arg(&list[0])?;
+    if n < 0 { return Err("expecting a non-negative integer") }
+    let mut s = (n as f64).sqrt() as i64;
+    while s * s > n as i64 { s -= 1 }
+    while (s + 1) * (s + 1) <= n as i64 { s += 1 }
+    Ok(Expr::Values(vec![
+        Expr::Literal(Literal::Number(s as i32)),
+        Expr::Literal(Literal::Number((n as i64 - s * s) as i32)),
+    ]))
+}
+
+pub fn finitep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    number_test(list, f64::is_finite)
+}
+
+pub fn infinitep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    number_test(list, f64::is_infinite)
+}
+
+pub fn nanp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    number_test(list, f64::is_nan)
+}
+
 
 /*
  * List built-ins
@@ -307,189 +659,3377 @@ pub fn reverse(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static s
 
 
 /*
- * Tests
+ * SRFI-1 list built-ins
  */
 
-pub fn equalp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+fn list_arg(expr: &Expr) -> Result<Vec<Expr>, &'static str> {
+    expr.to_vec().ok_or("expecting a list")
+}
 
-    let lval = &list[0];
-    let rval = &list[1];
+fn list_args(list: &[Expr]) -> Result<Vec<Vec<Expr>>, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
 
-    Ok(Expr::Literal(Literal::Bool(lval == rval)))
+    list.iter().map(list_arg).collect()
 }
 
-pub fn listp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+/// Transposes lists into the argument vectors for each step of a
+/// multi-list traversal, stopping at the shortest list.
+fn zip_lists(lists: &[Vec<Expr>]) -> Vec<Vec<Expr>> {
+    let len = lists.iter().map(|l| l.len()).min().unwrap_or(0);
+    (0..len).map(|i| lists.iter().map(|l| l[i].clone()).collect()).collect()
+}
 
-    match &list[0] {
-        Expr::List(_) => Ok(Expr::Literal(Literal::Bool(true))),
-        _             => Ok(Expr::Literal(Literal::Bool(false))),
+fn count_arg(expr: &Expr) -> Result<usize, &'static str> {
+    match expr {
+        Expr::Literal(Literal::Number(n)) if *n >= 0 => Ok(*n as usize),
+        _ => Err("expecting a non-negative integer"),
     }
 }
 
-pub fn nullp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 1 { return Err("called with incorrect number of arguments") }
-
-    match &list[0] {
-        Expr::List(l) => {
-            if l.is_empty() {
-                Ok(Expr::Literal(Literal::Bool(true)))
-            } else {
-                Ok(Expr::Literal(Literal::Bool(false)))
-            }
-        },
-        _ => Ok(Expr::Literal(Literal::Bool(false))),
+/// Applies the optional equivalence procedure at `list[i]`, defaulting to
+/// `equal?`.
+fn same(list: &[Expr], i: usize, a: &Expr, b: &Expr, env: Rc<RefCell<Env>>) -> Result<bool, &'static str> {
+    eval::poll()?;
+    match list.get(i) {
+        Some(proc) => Ok(truthy(&eval::apply(proc, vec![a.clone(), b.clone()], env)?)),
+        None => Ok(a == b),
     }
 }
 
-pub fn procedurep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+fn cxr(list: &[Expr], path: &str, env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
     if list.len() != 1 { return Err("called with incorrect number of arguments") }
 
-    match &list[0] {
-        Expr::Lambda(_, _) => {
-            Ok(Expr::Literal(Literal::Bool(true)))
-        },
-        Expr::Builtin(_) => {
-            Ok(Expr::Literal(Literal::Bool(true)))
-        },
-        _ => Ok(Expr::Literal(Literal::Bool(false))),
+    let mut val = list[0].clone();
+    for op in path.chars().rev() {
+        val = match op {
+            'a' => car(&[val], env.clone())?,
+            _ => cdr(&[val], env.clone())?,
+        };
     }
+    Ok(val)
 }
 
-pub fn numberp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+pub fn caar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "aa", env)
+}
 
-    match &list[0] {
-        Expr::Literal(Literal::Number(_)) => Ok(Expr::Literal(Literal::Bool(true))),
-        Expr::Literal(Literal::Float(_)) => Ok(Expr::Literal(Literal::Bool(true))),
-        _ => Ok(Expr::Literal(Literal::Bool(false))),
-    }
+pub fn cadr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "ad", env)
 }
 
-pub fn symbolp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+pub fn cdar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "da", env)
+}
 
-    match &list[0] {
-        Expr::Var(_) => Ok(Expr::Literal(Literal::Bool(true))),
-        _ => Ok(Expr::Literal(Literal::Bool(false))),
-    }
+pub fn cddr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "dd", env)
 }
 
-pub fn pairp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+pub fn caaar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "aaa", env)
+}
 
-    match &list[0] {
-        Expr::DottedPair(_, _) => Ok(Expr::Literal(Literal::Bool(true))),
-        Expr::List(l) => {
-            if l.is_empty() {
-                Ok(Expr::Literal(Literal::Bool(false)))
-            } else {
-                Ok(Expr::Literal(Literal::Bool(true)))
-            }
-        },
-        _ => Ok(Expr::Literal(Literal::Bool(false))),
-    }
+pub fn caadr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "aad", env)
 }
 
+pub fn cadar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "ada", env)
+}
 
-/*
- * Other
- */
+pub fn caddr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "add", env)
+}
 
-pub fn apply(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() < 2 { return Err("called with incorrect number of arguments") }
+pub fn cdaar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "daa", env)
+}
 
-    let proc = &list[0];
-    let objs = &list[1..list.len() - 1];
-    let arg = &list[list.len() - 1];
+pub fn cdadr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "dad", env)
+}
 
-    let mut objlist = Vec::new();
-    for obj in objs.iter() {
-        objlist.push(obj.clone());
-    }
+pub fn cddar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "dda", env)
+}
 
-    if let Expr::List(l) = arg {
-        objlist.append(&mut l.clone());
-        eval::apply(proc, objlist, env)
-    } else {
-        Err("apply expecting a list")
-    }
+pub fn cdddr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "ddd", env)
 }
 
-pub fn load(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+pub fn caaaar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "aaaa", env)
+}
 
-    let val = &list[0];
-    if !val.is_literal() {
-        return Err("load called with incorrect type");
-    }
+pub fn caaadr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "aaad", env)
+}
 
-    let val = val.to_literal().unwrap();
-    if !val.is_string() {
-        return Err("load called with incorrect type");
-    }
+pub fn caadar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "aada", env)
+}
 
-    let file = val.to_string().unwrap();
+pub fn caaddr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "aadd", env)
+}
 
-    let path = Path::new(&file);
-    let mut file = File::open(&path)
-        .expect("Failed to open file");
+pub fn cadaar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "adaa", env)
+}
 
-    let mut source = String::new();
-    file.read_to_string(&mut source)
-        .expect("Failed to read file");
+pub fn cadadr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "adad", env)
+}
 
-    let mut lexer = Lexer::new(&source);
-    lexer.scan();
-    let mut parser = Parser::new(lexer.tokens);
-    let result = parser.parse();
-    if result.is_ok() {
-        let exprs = result.unwrap();
-        for expr in exprs.iter() {
-            let eval_result = eval(expr, env.clone());
-            if eval_result.is_err() {
-                println!("{}", eval_result.unwrap_err());
-            }
-        }
-    } else {
-        println!("{}", result.unwrap_err());
-        println!("pos: {}", parser.current);
+pub fn caddar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "adda", env)
+}
+
+pub fn cadddr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "addd", env)
+}
+
+pub fn cdaaar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "daaa", env)
+}
+
+pub fn cdaadr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "daad", env)
+}
+
+pub fn cdadar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "dada", env)
+}
+
+pub fn cdaddr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "dadd", env)
+}
+
+pub fn cddaar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "ddaa", env)
+}
+
+pub fn cddadr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "ddad", env)
+}
+
+pub fn cdddar(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "ddda", env)
+}
+
+pub fn cddddr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    cxr(list, "dddd", env)
+}
+
+pub fn list_tail(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let mut val = list[0].clone();
+    for _ in 0..count_arg(&list[1])? {
+        eval::poll()?;
+        val = cdr(&[val], env.clone())?;
+    }
+    Ok(val)
+}
+
+pub fn list_ref(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let tail = list_tail(list, env.clone())?;
+    car(&[tail], env)
+}
+
+pub fn map(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let lists = list_args(&list[1.min(list.len())..list.len()])?;
+
+    let mut result = Vec::new();
+    for args in zip_lists(&lists) {
+        result.push(eval::apply(&list[0], args, env.clone())?);
+    }
+    Ok(Expr::List(result))
+}
+
+pub fn for_each(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let lists = list_args(&list[1.min(list.len())..list.len()])?;
+
+    for args in zip_lists(&lists) {
+        eval::apply(&list[0], args, env.clone())?;
+    }
+    Ok(Expr::Unspecified)
+}
+
+pub fn append_map(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let lists = list_args(&list[1.min(list.len())..list.len()])?;
+
+    let mut result = Vec::new();
+    for args in zip_lists(&lists) {
+        let val = eval::apply(&list[0], args, env.clone())?;
+        result.append(&mut list_arg(&val)?);
+    }
+    Ok(Expr::List(result))
+}
+
+pub fn filter_map(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let lists = list_args(&list[1.min(list.len())..list.len()])?;
+
+    let mut result = Vec::new();
+    for args in zip_lists(&lists) {
+        let val = eval::apply(&list[0], args, env.clone())?;
+        if truthy(&val) { result.push(val) }
+    }
+    Ok(Expr::List(result))
+}
+
+pub fn filter(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let mut result = Vec::new();
+    for val in list_arg(&list[1])? {
+        if truthy(&eval::apply(&list[0], vec![val.clone()], env.clone())?) {
+            result.push(val);
+        }
+    }
+    Ok(Expr::List(result))
+}
+
+pub fn remove(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let mut result = Vec::new();
+    for val in list_arg(&list[1])? {
+        if !truthy(&eval::apply(&list[0], vec![val.clone()], env.clone())?) {
+            result.push(val);
+        }
+    }
+    Ok(Expr::List(result))
+}
+
+pub fn partition(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let mut matching = Vec::new();
+    let mut rest = Vec::new();
+    for val in list_arg(&list[1])? {
+        if truthy(&eval::apply(&list[0], vec![val.clone()], env.clone())?) {
+            matching.push(val);
+        } else {
+            rest.push(val);
+        }
+    }
+    Ok(Expr::Values(vec![Expr::List(matching), Expr::List(rest)]))
+}
+
+pub fn fold(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 3 { return Err("called with incorrect number of arguments") }
+
+    let lists = list_args(&list[2..list.len()])?;
+    let mut acc = list[1].clone();
+    for mut args in zip_lists(&lists) {
+        args.push(acc);
+        acc = eval::apply(&list[0], args, env.clone())?;
+    }
+    Ok(acc)
+}
+
+pub fn fold_right(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 3 { return Err("called with incorrect number of arguments") }
+
+    let lists = list_args(&list[2..list.len()])?;
+    let mut acc = list[1].clone();
+    for mut args in zip_lists(&lists).into_iter().rev() {
+        args.push(acc);
+        acc = eval::apply(&list[0], args, env.clone())?;
+    }
+    Ok(acc)
+}
+
+pub fn reduce(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 3 { return Err("called with incorrect number of arguments") }
+
+    let vals = list_arg(&list[2])?;
+    let mut vals = vals.into_iter();
+    let mut acc = match vals.next() {
+        Some(val) => val,
+        None => return Ok(list[1].clone()),
+    };
+    for val in vals {
+        acc = eval::apply(&list[0], vec![val, acc], env.clone())?;
+    }
+    Ok(acc)
+}
+
+pub fn find(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    for val in list_arg(&list[1])? {
+        if truthy(&eval::apply(&list[0], vec![val.clone()], env.clone())?) {
+            return Ok(val);
+        }
+    }
+    Ok(Expr::Literal(Literal::Bool(false)))
+}
+
+pub fn find_tail(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let vals = list_arg(&list[1])?;
+    for (i, val) in vals.iter().enumerate() {
+        if truthy(&eval::apply(&list[0], vec![val.clone()], env.clone())?) {
+            return Ok(Expr::List(vals[i..vals.len()].to_vec()));
+        }
+    }
+    Ok(Expr::Literal(Literal::Bool(false)))
+}
+
+pub fn any(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let lists = list_args(&list[1.min(list.len())..list.len()])?;
+
+    for args in zip_lists(&lists) {
+        let val = eval::apply(&list[0], args, env.clone())?;
+        if truthy(&val) { return Ok(val) }
+    }
+    Ok(Expr::Literal(Literal::Bool(false)))
+}
+
+pub fn every(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let lists = list_args(&list[1.min(list.len())..list.len()])?;
+
+    let mut result = Expr::Literal(Literal::Bool(true));
+    for args in zip_lists(&lists) {
+        result = eval::apply(&list[0], args, env.clone())?;
+        if !truthy(&result) { return Ok(result) }
+    }
+    Ok(result)
+}
+
+pub fn count(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let lists = list_args(&list[1.min(list.len())..list.len()])?;
+
+    let mut n = 0;
+    for args in zip_lists(&lists) {
+        if truthy(&eval::apply(&list[0], args, env.clone())?) { n += 1 }
+    }
+    Ok(Expr::Literal(Literal::Number(n)))
+}
+
+pub fn list_index(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let lists = list_args(&list[1.min(list.len())..list.len()])?;
+
+    for (i, args) in zip_lists(&lists).into_iter().enumerate() {
+        if truthy(&eval::apply(&list[0], args, env.clone())?) {
+            return Ok(Expr::Literal(Literal::Number(i as i32)));
+        }
+    }
+    Ok(Expr::Literal(Literal::Bool(false)))
+}
+
+pub fn delete(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let mut result = Vec::new();
+    for val in list_arg(&list[1])? {
+        if !same(list, 2, &list[0], &val, env.clone())? {
+            result.push(val);
+        }
+    }
+    Ok(Expr::List(result))
+}
+
+pub fn delete_duplicates(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let mut result: Vec<Expr> = Vec::new();
+    'outer: for val in list_arg(&list[0])? {
+        for seen in result.iter() {
+            if same(list, 1, seen, &val, env.clone())? { continue 'outer }
+        }
+        result.push(val);
+    }
+    Ok(Expr::List(result))
+}
+
+pub fn iota(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let n = count_arg(&list[0])?;
+    sandbox::reserve(n.saturating_mul(size_of::<Expr>()))?;
+    let start = list.get(1).cloned().unwrap_or(Expr::Literal(Literal::Number(0)));
+    let step = list.get(2).cloned().unwrap_or(Expr::Literal(Literal::Number(1)));
+
+    let result = match (start, step) {
+        (Expr::Literal(Literal::Number(start)), Expr::Literal(Literal::Number(step))) => {
+            (0..n).map(|i| {
+                if i % eval::POLL_INTERVAL == 0 { eval::poll()? }
+                let n = step.checked_mul(i as i32).and_then(|offset| start.checked_add(offset));
+                n.map(|n| Expr::Literal(Literal::Number(n))).ok_or("integer out of range")
+            }).collect::<Result<_, _>>()?
+        },
+        (start, step) => {
+            let start = float_arg(&start)?;
+            let step = float_arg(&step)?;
+            (0..n).map(|i| {
+                if i % eval::POLL_INTERVAL == 0 { eval::poll()? }
+                Ok(Expr::Literal(Literal::Float(start + step * i as f64)))
+            }).collect::<Result<_, _>>()?
+        },
+    };
+    Ok(Expr::List(result))
+}
+
+pub fn last(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::List(l) if !l.is_empty() => Ok(l[l.len() - 1].clone()),
+        Expr::DottedPair(l, _) => Ok(l[l.len() - 1].clone()),
+        _ => Err("expecting a pair"),
+    }
+}
+
+pub fn take(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let k = count_arg(&list[1])?;
+    match &list[0] {
+        Expr::List(l) if k <= l.len() => Ok(Expr::List(l[0..k].to_vec())),
+        Expr::DottedPair(l, _) if k <= l.len() => Ok(Expr::List(l[0..k].to_vec())),
+        _ => Err("index out of range"),
+    }
+}
+
+pub fn drop(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    list_tail(list, env)
+}
+
+pub fn split_at(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let prefix = take(list, env.clone())?;
+    let suffix = drop(list, env)?;
+    Ok(Expr::Values(vec![prefix, suffix]))
+}
+
+pub fn member(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let vals = list_arg(&list[1])?;
+    for (i, val) in vals.iter().enumerate() {
+        if same(list, 2, &list[0], val, env.clone())? {
+            return Ok(Expr::List(vals[i..vals.len()].to_vec()));
+        }
+    }
+    Ok(Expr::Literal(Literal::Bool(false)))
+}
+
+pub fn memv(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let vals = list_arg(&list[1])?;
+    for (i, val) in vals.iter().enumerate() {
+        if is_eqv(&list[0], val) {
+            return Ok(Expr::List(vals[i..vals.len()].to_vec()));
+        }
+    }
+    Ok(Expr::Literal(Literal::Bool(false)))
+}
+
+pub fn assoc(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    for pair in list_arg(&list[1])? {
+        let key = car(std::slice::from_ref(&pair), env.clone())?;
+        if same(list, 2, &list[0], &key, env.clone())? {
+            return Ok(pair);
+        }
+    }
+    Ok(Expr::Literal(Literal::Bool(false)))
+}
+
+pub fn assv(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    for pair in list_arg(&list[1])? {
+        let key = car(std::slice::from_ref(&pair), env.clone())?;
+        if is_eqv(&list[0], &key) {
+            return Ok(pair);
+        }
+    }
+    Ok(Expr::Literal(Literal::Bool(false)))
+}
+
+pub fn alist_cons(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 3 { return Err("called with incorrect number of arguments") }
+
+    let pair = cons(&list[0..2], env.clone())?;
+    cons(&[pair, list[2].clone()], env)
+}
+
+pub fn alist_copy(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::List(list_arg(&list[0])?))
+}
+
+pub fn alist_delete(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let mut result = Vec::new();
+    for pair in list_arg(&list[1])? {
+        let key = car(std::slice::from_ref(&pair), env.clone())?;
+        if !same(list, 2, &list[0], &key, env.clone())? {
+            result.push(pair);
+        }
+    }
+    Ok(Expr::List(result))
+}
+
+pub fn list_copy(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(list[0].clone())
+}
+
+pub fn make_list(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let n = count_arg(&list[0])?;
+    sandbox::reserve(n.saturating_mul(size_of::<Expr>()))?;
+    let fill = list.get(1).cloned().unwrap_or(Expr::Unspecified);
+    Ok(Expr::List(filled(fill, n)?))
+}
+
+/// `n` copies of `fill`, made a stretch at a time so that making a large
+/// list or vector can be interrupted.
+fn filled<T: Clone>(fill: T, n: usize) -> Result<Vec<T>, &'static str> {
+    let mut items = Vec::with_capacity(n);
+    while items.len() < n {
+        eval::poll()?;
+        let stretch = eval::POLL_INTERVAL.min(n - items.len());
+        items.extend(std::iter::repeat_n(fill.clone(), stretch));
+    }
+    Ok(items)
+}
+
+
+/*
+ * Bytevector built-ins
+ */
+
+fn bytevector_arg(expr: &Expr) -> Result<Rc<RefCell<Vec<u8>>>, &'static str> {
+    expr.to_bytevector().ok_or("expecting a bytevector")
+}
+
+fn index_arg(expr: &Expr) -> Result<usize, &'static str> {
+    match expr {
+        Expr::Literal(Literal::Number(n)) if *n >= 0 => Ok(*n as usize),
+        _ => Err("expecting a non-negative integer"),
+    }
+}
+
+fn byte_arg(expr: &Expr) -> Result<u8, &'static str> {
+    match expr {
+        Expr::Literal(Literal::Number(n)) if (0..=255).contains(n) => Ok(*n as u8),
+        _ => Err("expecting a byte"),
+    }
+}
+
+/// Reads the optional `start` and `end` arguments found at `list[from..]`,
+/// defaulting to the whole `len`.
+fn range_args(list: &[Expr], from: usize, len: usize) -> Result<(usize, usize), &'static str> {
+    let start = match list.get(from) {
+        Some(expr) => index_arg(expr)?,
+        None => 0,
+    };
+    let end = match list.get(from + 1) {
+        Some(expr) => index_arg(expr)?,
+        None => len,
+    };
+
+    if start > end || end > len { return Err("index out of range") }
+    Ok((start, end))
+}
+
+/// Reads the optional endianness symbol (`big` or `little`) at `list[i]`,
+/// returning true for big-endian. Defaults to the native byte order.
+fn big_endian_arg(list: &[Expr], i: usize) -> Result<bool, &'static str> {
+    match list.get(i) {
+        None => Ok(cfg!(target_endian = "big")),
+        Some(Expr::Symbol(v)) if *v == Symbol::intern("big") => Ok(true),
+        Some(Expr::Symbol(v)) if *v == Symbol::intern("little") => Ok(false),
+        Some(_) => Err("endianness must be big or little"),
+    }
+}
+
+/// Returns a copy of the `size` bytes at index `list[1]` of the bytevector
+/// in `list[0]`, in big-endian order.
+fn bytes_at(list: &[Expr], size: usize) -> Result<Vec<u8>, &'static str> {
+    if list.len() < 2 || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let bv = bytevector_arg(&list[0])?;
+    let k = index_arg(&list[1])?;
+    let big = big_endian_arg(list, 2)?;
+
+    let bv = bv.borrow();
+    if k + size > bv.len() { return Err("index out of range") }
+    let mut bytes = bv[k..k + size].to_vec();
+    if !big { bytes.reverse() }
+    Ok(bytes)
+}
+
+/// Stores big-endian `bytes` at index `list[1]` of the bytevector in
+/// `list[0]`, honoring the endianness argument at `list[3]`.
+fn set_bytes_at(list: &[Expr], mut bytes: Vec<u8>) -> Result<Expr, &'static str> {
+    let bv = bytevector_arg(&list[0])?;
+    let k = index_arg(&list[1])?;
+    if !big_endian_arg(list, 3)? { bytes.reverse() }
+
+    let mut bv = bv.borrow_mut();
+    if k + bytes.len() > bv.len() { return Err("index out of range") }
+    bv[k..k + bytes.len()].copy_from_slice(&bytes);
+    Ok(Expr::Unspecified)
+}
+
+fn int_ref(list: &[Expr], size: usize, signed: bool) -> Result<Expr, &'static str> {
+    let bytes = bytes_at(list, size)?;
+    let mut n: i64 = 0;
+    for b in bytes.iter() {
+        n = (n << 8) | *b as i64;
+    }
+    if signed && bytes[0] & 0x80 != 0 {
+        n -= 1 << (size * 8);
+    }
+
+    match i32::try_from(n) {
+        Ok(n) => Ok(Expr::Literal(Literal::Number(n))),
+        Err(_) => Err("value does not fit in an integer"),
+    }
+}
+
+fn int_set(list: &[Expr], size: usize, signed: bool) -> Result<Expr, &'static str> {
+    if list.len() < 3 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let n = match &list[2] {
+        Expr::Literal(Literal::Number(n)) => *n as i64,
+        _ => return Err("expecting an integer"),
+    };
+    let bits = size * 8;
+    let (min, max) = if signed {
+        (-(1i64 << (bits - 1)), (1i64 << (bits - 1)) - 1)
+    } else {
+        (0, (1i64 << bits) - 1)
+    };
+    if n < min || n > max { return Err("value out of range") }
+
+    let bytes = (n as u64).to_be_bytes()[8 - size..].to_vec();
+    set_bytes_at(list, bytes)
+}
+
+fn float_arg(expr: &Expr) -> Result<f64, &'static str> {
+    match expr {
+        Expr::Literal(Literal::Number(n)) => Ok(*n as f64),
+        Expr::Literal(Literal::Float(f)) => Ok(*f),
+        _ => Err("expecting a number"),
+    }
+}
+
+pub fn bytevector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let mut bytes = Vec::new();
+    for val in list.iter() {
+        bytes.push(byte_arg(val)?);
+    }
+
+    Ok(Expr::Bytevector(Rc::new(RefCell::new(bytes))))
+}
+
+pub fn make_bytevector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let k = index_arg(&list[0])?;
+    let fill = match list.get(1) {
+        Some(expr) => byte_arg(expr)?,
+        None => 0,
+    };
+
+    sandbox::reserve(k)?;
+    Ok(Expr::Bytevector(Rc::new(RefCell::new(filled(fill, k)?))))
+}
+
+pub fn bytevector_length(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let bv = bytevector_arg(&list[0])?;
+    let size = bv.borrow().len() as i32;
+    Ok(Expr::Literal(Literal::Number(size)))
+}
+
+pub fn bytevector_u8_ref(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let bv = bytevector_arg(&list[0])?;
+    let k = index_arg(&list[1])?;
+    match bv.borrow().get(k) {
+        Some(b) => Ok(Expr::Literal(Literal::Number(*b as i32))),
+        None => Err("index out of range"),
+    }
+}
+
+pub fn bytevector_u8_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 3 { return Err("called with incorrect number of arguments") }
+
+    let bv = bytevector_arg(&list[0])?;
+    let k = index_arg(&list[1])?;
+    let b = byte_arg(&list[2])?;
+    match bv.borrow_mut().get_mut(k) {
+        Some(slot) => *slot = b,
+        None => return Err("index out of range"),
+    }
+
+    Ok(Expr::Unspecified)
+}
+
+pub fn bytevector_copy(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let bv = bytevector_arg(&list[0])?;
+    let bv = bv.borrow();
+    let (start, end) = range_args(list, 1, bv.len())?;
+
+    Ok(Expr::Bytevector(Rc::new(RefCell::new(bv[start..end].to_vec()))))
+}
+
+pub fn bytevector_copy_mut(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 3 || list.len() > 5 { return Err("called with incorrect number of arguments") }
+
+    let to = bytevector_arg(&list[0])?;
+    let at = index_arg(&list[1])?;
+    let from = bytevector_arg(&list[2])?;
+
+    // Copy out first, as `to` and `from` may be the same bytevector.
+    let bytes = {
+        let from = from.borrow();
+        let (start, end) = range_args(list, 3, from.len())?;
+        from[start..end].to_vec()
+    };
+
+    let mut to = to.borrow_mut();
+    if at + bytes.len() > to.len() { return Err("index out of range") }
+    to[at..at + bytes.len()].copy_from_slice(&bytes);
+
+    Ok(Expr::Unspecified)
+}
+
+pub fn bytevector_append(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let mut bytes = Vec::new();
+    for val in list.iter() {
+        let bv = bytevector_arg(val)?;
+        bytes.extend_from_slice(&bv.borrow());
+    }
+
+    Ok(Expr::Bytevector(Rc::new(RefCell::new(bytes))))
+}
+
+pub fn utf8_to_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let bv = bytevector_arg(&list[0])?;
+    let bv = bv.borrow();
+    let (start, end) = range_args(list, 1, bv.len())?;
+
+    match String::from_utf8(bv[start..end].to_vec()) {
+        Ok(s) => Ok(Expr::Literal(Literal::String(s))),
+        Err(_) => Err("bytevector is not valid utf-8"),
+    }
+}
+
+pub fn string_to_utf8(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let s = match &list[0] {
+        Expr::Literal(Literal::String(s)) => s,
+        _ => return Err("expecting a string"),
+    };
+    let chars: Vec<char> = s.chars().collect();
+    let (start, end) = range_args(list, 1, chars.len())?;
+    let s: String = chars[start..end].iter().collect();
+
+    Ok(Expr::Bytevector(Rc::new(RefCell::new(s.into_bytes()))))
+}
+
+pub fn native_endianness(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    if cfg!(target_endian = "big") {
+        Ok(Expr::Symbol(Symbol::intern("big")))
+    } else {
+        Ok(Expr::Symbol(Symbol::intern("little")))
+    }
+}
+
+pub fn bytevector_s8_ref(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_ref(list, 1, true)
+}
+
+pub fn bytevector_s8_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_set(list, 1, true)
+}
+
+pub fn bytevector_u16_ref(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_ref(list, 2, false)
+}
+
+pub fn bytevector_u16_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_set(list, 2, false)
+}
+
+pub fn bytevector_s16_ref(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_ref(list, 2, true)
+}
+
+pub fn bytevector_s16_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_set(list, 2, true)
+}
+
+pub fn bytevector_u32_ref(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_ref(list, 4, false)
+}
+
+pub fn bytevector_u32_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_set(list, 4, false)
+}
+
+pub fn bytevector_s32_ref(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_ref(list, 4, true)
+}
+
+pub fn bytevector_s32_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    int_set(list, 4, true)
+}
+
+pub fn bytevector_ieee_single_ref(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let bytes = bytes_at(list, 4)?;
+    let f = f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Ok(Expr::Literal(Literal::Float(f as f64)))
+}
+
+pub fn bytevector_ieee_single_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 3 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let f = float_arg(&list[2])? as f32;
+    set_bytes_at(list, f.to_be_bytes().to_vec())
+}
+
+pub fn bytevector_ieee_double_ref(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let bytes = bytes_at(list, 8)?;
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes);
+    Ok(Expr::Literal(Literal::Float(f64::from_be_bytes(buf))))
+}
+
+pub fn bytevector_ieee_double_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 3 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let f = float_arg(&list[2])?;
+    set_bytes_at(list, f.to_be_bytes().to_vec())
+}
+
+
+/*
+ * Vector built-ins
+ */
+
+fn vector_arg(expr: &Expr) -> Result<Rc<RefCell<Vec<Expr>>>, &'static str> {
+    expr.to_vector().ok_or("expecting a vector")
+}
+
+fn new_vector(elements: Vec<Expr>) -> Expr {
+    gc::vector(elements)
+}
+
+pub fn vector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    Ok(new_vector(list.to_vec()))
+}
+
+pub fn make_vector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let k = index_arg(&list[0])?;
+    sandbox::reserve(k.saturating_mul(size_of::<Expr>()))?;
+    let fill = list.get(1).cloned().unwrap_or(Expr::Unspecified);
+    Ok(new_vector(filled(fill, k)?))
+}
+
+pub fn vector_length(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let v = vector_arg(&list[0])?;
+    let size = v.borrow().len() as i32;
+    Ok(Expr::Literal(Literal::Number(size)))
+}
+
+pub fn vector_ref(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let v = vector_arg(&list[0])?;
+    let k = index_arg(&list[1])?;
+    let v = v.borrow();
+    v.get(k).cloned().ok_or("index out of range")
+}
+
+pub fn vector_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 3 { return Err("called with incorrect number of arguments") }
+
+    let v = vector_arg(&list[0])?;
+    let k = index_arg(&list[1])?;
+    let mut v = v.borrow_mut();
+    match v.get_mut(k) {
+        Some(slot) => *slot = list[2].clone(),
+        None => return Err("index out of range"),
+    }
+    Ok(Expr::Unspecified)
+}
+
+pub fn vector_to_list(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let v = vector_arg(&list[0])?;
+    let v = v.borrow();
+    let (start, end) = range_args(list, 1, v.len())?;
+    Ok(Expr::List(v[start..end].to_vec()))
+}
+
+pub fn list_to_vector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(new_vector(list_arg(&list[0])?))
+}
+
+pub fn vector_fill(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let v = vector_arg(&list[0])?;
+    let mut v = v.borrow_mut();
+    let (start, end) = range_args(list, 2, v.len())?;
+    for slot in v[start..end].iter_mut() {
+        *slot = list[1].clone();
+    }
+    Ok(Expr::Unspecified)
+}
+
+
+/*
+ * SRFI-132 sorting built-ins
+ */
+
+/// Calls the comparison procedure `less` on `a` and `b`.
+fn less(less: &Expr, a: &Expr, b: &Expr, env: Rc<RefCell<Env>>) -> Result<bool, &'static str> {
+    eval::poll()?;
+    Ok(truthy(&eval::apply(less, vec![a.clone(), b.clone()], env)?))
+}
+
+/// Merges two sorted runs. Elements of `left` win ties, which keeps the
+/// merge stable.
+fn merge(left: Vec<Expr>, right: Vec<Expr>, proc: &Expr, env: Rc<RefCell<Env>>) -> Result<Vec<Expr>, &'static str> {
+    let mut result = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        if less(proc, r, l, env.clone())? {
+            result.push(right.next().unwrap());
+        } else {
+            result.push(left.next().unwrap());
+        }
+    }
+    result.extend(left);
+    result.extend(right);
+    Ok(result)
+}
+
+/// A stable merge sort. The standard library's sorts cannot stop on an
+/// error raised by the comparator, so slippy has its own.
+fn merge_sort(mut items: Vec<Expr>, proc: &Expr, env: Rc<RefCell<Env>>) -> Result<Vec<Expr>, &'static str> {
+    if items.len() <= 1 { return Ok(items) }
+
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, proc, env.clone())?;
+    let right = merge_sort(right, proc, env.clone())?;
+    merge(left, right, proc, env)
+}
+
+fn is_sorted(items: &[Expr], proc: &Expr, env: Rc<RefCell<Env>>) -> Result<bool, &'static str> {
+    for pair in items.windows(2) {
+        if less(proc, &pair[1], &pair[0], env.clone())? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// `(sort sequence less?)` from SRFI-95: sorts a list or vector into a new
+/// sequence of the same kind.
+pub fn sort(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::Vector(v) => {
+            let items = v.borrow().clone();
+            Ok(new_vector(merge_sort(items, &list[1], env)?))
+        },
+        seq => Ok(Expr::List(merge_sort(list_arg(seq)?, &list[1], env)?)),
+    }
+}
+
+pub fn list_sort(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::List(merge_sort(list_arg(&list[1])?, &list[0], env)?))
+}
+
+pub fn list_merge(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 3 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::List(merge(list_arg(&list[1])?, list_arg(&list[2])?, &list[0], env)?))
+}
+
+pub fn list_sortedp(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(is_sorted(&list_arg(&list[1])?, &list[0], env)?)))
+}
+
+pub fn vector_sort(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let v = vector_arg(&list[1])?;
+    let items = v.borrow().clone();
+    let (start, end) = range_args(list, 2, items.len())?;
+    Ok(new_vector(merge_sort(items[start..end].to_vec(), &list[0], env)?))
+}
+
+/// `(vector-sort! v less? [start end])` sorts in place. The vector is not
+/// borrowed while the comparator runs, so it may inspect the vector.
+pub fn vector_sort_mut(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let v = vector_arg(&list[0])?;
+    let items = v.borrow().clone();
+    let (start, end) = range_args(list, 2, items.len())?;
+    let sorted = merge_sort(items[start..end].to_vec(), &list[1], env)?;
+
+    let mut v = v.borrow_mut();
+    if end > v.len() { return Err("vector was resized while sorting") }
+    v[start..end].clone_from_slice(&sorted);
+    Ok(Expr::Unspecified)
+}
+
+pub fn vector_sortedp(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let v = vector_arg(&list[1])?;
+    let items = v.borrow().clone();
+    let (start, end) = range_args(list, 2, items.len())?;
+    Ok(Expr::Literal(Literal::Bool(is_sorted(&items[start..end], &list[0], env)?)))
+}
+
+/*
+ * Hash table built-ins
+ */
+
+fn hash_table_arg(expr: &Expr) -> Result<Rc<RefCell<HashTable>>, &'static str> {
+    expr.to_hash_table().ok_or("expecting a hash table")
+}
+
+fn new_hash_table(table: HashTable) -> Expr {
+    Expr::HashTable(Rc::new(RefCell::new(table)))
+}
+
+/// Looks up `key`, returning its hash and entry position. User equivalence
+/// and hash procedures run while the table is only borrowed immutably.
+fn hash_table_lookup(table: &Rc<RefCell<HashTable>>,
+                     key: &Expr,
+                     env: Rc<RefCell<Env>>) -> Result<(u64, Option<usize>), &'static str> {
+    let table = table.borrow();
+    let hash = table.hash_of(key, env.clone())?;
+    let pos = table.position(key, hash, env)?;
+    Ok((hash, pos))
+}
+
+fn hash_table_store(table: &Rc<RefCell<HashTable>>,
+                    key: &Expr,
+                    value: Expr,
+                    env: Rc<RefCell<Env>>) -> Result<(), &'static str> {
+    let (hash, pos) = hash_table_lookup(table, key, env)?;
+    let mut table = table.try_borrow_mut().map_err(|_| "hash table modified while in use")?;
+    table.insert_at(pos, hash, key.clone(), value);
+    Ok(())
+}
+
+fn truthy(expr: &Expr) -> bool {
+    !expr.is_false()
+}
+
+pub fn make_hash_table(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let equiv = match list.first() {
+        // The default equivalence hashes natively rather than calling back.
+        Some(Expr::Builtin(b)) if std::ptr::fn_addr_eq(*b, equalp as Builtin) => None,
+        Some(proc) => Some(proc.clone()),
+        None => None,
+    };
+    let identity = matches!(list.first(), Some(Expr::Builtin(b)) if std::ptr::fn_addr_eq(*b, eqp as Builtin));
+    let hash = match list.get(1) {
+        Some(Expr::Builtin(b)) if std::ptr::fn_addr_eq(*b, hash as Builtin) => None,
+        Some(proc) => Some(proc.clone()),
+        // Keys of eq? and eqv? tables may change after they are stored, so
+        // their contents must not decide where they are found.
+        None if identity => Some(Expr::Builtin(hash_by_identity)),
+        None => None,
+    };
+
+    Ok(new_hash_table(HashTable::new(equiv, hash)))
+}
+
+pub fn alist_to_hash_table(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let alist = list[0].to_vec().ok_or("expecting an association list")?;
+    let table = make_hash_table(&list[1..list.len()], env.clone())?;
+    let rc = table.to_hash_table().unwrap();
+    for pair in alist.iter() {
+        let key = car(std::slice::from_ref(pair), env.clone())?;
+        let value = cdr(std::slice::from_ref(pair), env.clone())?;
+        // Earlier associations take precedence, as with assoc.
+        if hash_table_lookup(&rc, &key, env.clone())?.1.is_none() {
+            hash_table_store(&rc, &key, value, env.clone())?;
+        }
+    }
+
+    Ok(table)
+}
+
+pub fn hash_table_ref(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let table = hash_table_arg(&list[0])?;
+    let value = table.borrow().get(&list[1], env.clone())?;
+    match value {
+        Some(value) => match list.get(3) {
+            Some(success) => eval::apply(success, vec![value], env),
+            None => Ok(value),
+        },
+        None => match list.get(2) {
+            Some(failure) => eval::apply(failure, Vec::new(), env),
+            None => Err("key not found in hash table"),
+        },
+    }
+}
+
+pub fn hash_table_ref_default(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 3 { return Err("called with incorrect number of arguments") }
+
+    let table = hash_table_arg(&list[0])?;
+    let value = table.borrow().get(&list[1], env)?;
+    Ok(value.unwrap_or_else(|| list[2].clone()))
+}
+
+pub fn hash_table_set(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 3 || list.len().is_multiple_of(2) { return Err("called with incorrect number of arguments") }
+
+    let table = hash_table_arg(&list[0])?;
+    for kv in list[1..list.len()].chunks(2) {
+        hash_table_store(&table, &kv[0], kv[1].clone(), env.clone())?;
+    }
+
+    Ok(Expr::Unspecified)
+}
+
+pub fn hash_table_delete(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let table = hash_table_arg(&list[0])?;
+    let mut count = 0;
+    for key in list.iter().skip(1) {
+        if let (_, Some(i)) = hash_table_lookup(&table, key, env.clone())? {
+            let mut table = table.try_borrow_mut().map_err(|_| "hash table modified while in use")?;
+            table.remove_at(i);
+            count += 1;
+        }
+    }
+
+    Ok(Expr::Literal(Literal::Number(count)))
+}
+
+pub fn hash_table_contains(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let table = hash_table_arg(&list[0])?;
+    let (_, pos) = hash_table_lookup(&table, &list[1], env)?;
+    Ok(Expr::Literal(Literal::Bool(pos.is_some())))
+}
+
+pub fn hash_table_update(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 3 || list.len() > 5 { return Err("called with incorrect number of arguments") }
+
+    let table = hash_table_arg(&list[0])?;
+    let key = &list[1];
+    let current = table.borrow().get(key, env.clone())?;
+    let current = match current {
+        Some(value) => match list.get(4) {
+            Some(success) => eval::apply(success, vec![value], env.clone())?,
+            None => value,
+        },
+        None => match list.get(3) {
+            Some(failure) => eval::apply(failure, Vec::new(), env.clone())?,
+            None => return Err("key not found in hash table"),
+        },
+    };
+
+    let value = eval::apply(&list[2], vec![current], env.clone())?;
+    hash_table_store(&table, key, value, env)?;
+    Ok(Expr::Unspecified)
+}
+
+pub fn hash_table_update_default(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 4 { return Err("called with incorrect number of arguments") }
+
+    let table = hash_table_arg(&list[0])?;
+    let key = &list[1];
+    let current = table.borrow().get(key, env.clone())?;
+    let current = current.unwrap_or_else(|| list[3].clone());
+
+    let value = eval::apply(&list[2], vec![current], env.clone())?;
+    hash_table_store(&table, key, value, env)?;
+    Ok(Expr::Unspecified)
+}
+
+pub fn hash_table_count(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    match list {
+        [table] => {
+            let size = hash_table_arg(table)?.borrow().len() as i32;
+            Ok(Expr::Literal(Literal::Number(size)))
+        },
+        [pred, table] => {
+            let entries = hash_table_arg(table)?.borrow().entries();
+            let mut count = 0;
+            for (k, v) in entries {
+                if truthy(&eval::apply(pred, vec![k, v], env.clone())?) {
+                    count += 1;
+                }
+            }
+            Ok(Expr::Literal(Literal::Number(count)))
+        },
+        _ => Err("called with incorrect number of arguments"),
+    }
+}
+
+pub fn hash_table_size(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let size = hash_table_arg(&list[0])?.borrow().len() as i32;
+    Ok(Expr::Literal(Literal::Number(size)))
+}
+
+pub fn hash_table_keys(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let entries = hash_table_arg(&list[0])?.borrow().entries();
+    Ok(Expr::List(entries.into_iter().map(|(k, _)| k).collect()))
+}
+
+pub fn hash_table_values(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let entries = hash_table_arg(&list[0])?.borrow().entries();
+    Ok(Expr::List(entries.into_iter().map(|(_, v)| v).collect()))
+}
+
+pub fn hash_table_to_alist(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let entries = hash_table_arg(&list[0])?.borrow().entries();
+    let mut alist = Vec::new();
+    for (k, v) in entries {
+        alist.push(cons(&[k, v], env.clone())?);
+    }
+    Ok(Expr::List(alist))
+}
+
+pub fn hash_table_walk(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    // Walk a snapshot so the procedure may modify the table.
+    let entries = hash_table_arg(&list[0])?.borrow().entries();
+    for (k, v) in entries {
+        eval::apply(&list[1], vec![k, v], env.clone())?;
+    }
+
+    Ok(Expr::Unspecified)
+}
+
+pub fn hash_table_clear(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let table = hash_table_arg(&list[0])?;
+    table.try_borrow_mut().map_err(|_| "hash table modified while in use")?.clear();
+    Ok(Expr::Unspecified)
+}
+
+pub fn hash_table_copy(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let table = hash_table_arg(&list[0])?.borrow().clone();
+    Ok(new_hash_table(table))
+}
+
+pub fn hash(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    bounded_hash(list, hashtable::hash_expr)
+}
+
+pub fn hash_by_identity(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    bounded_hash(list, hashtable::hash_eqv)
+}
+
+/// Hashes `list[0]` with `hash_of` into a fixnum, below the optional bound
+/// in `list[1]`.
+fn bounded_hash(list: &[Expr], hash_of: fn(&Expr) -> u64) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let mut h = hash_of(&list[0]) & 0x7fff_ffff;
+    if let Some(bound) = list.get(1) {
+        match bound {
+            Expr::Literal(Literal::Number(n)) if *n > 0 => h %= *n as u64,
+            _ => return Err("hash bound must be a positive integer"),
+        }
+    }
+
+    Ok(Expr::Literal(Literal::Number(h as i32)))
+}
+
+pub fn string_hash(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    if !list[0].to_literal().is_some_and(|l| l.is_string()) {
+        return Err("expecting a string");
+    }
+    hash(list, env)
+}
+
+
+/*
+ * SRFI-146 mapping built-ins
+ */
+
+fn mapping_arg(expr: &Expr) -> Result<Rc<Mapping>, &'static str> {
+    match expr {
+        Expr::Mapping(m) => Ok(m.clone()),
+        _ => Err("expecting a mapping"),
+    }
+}
+
+fn new_mapping(mapping: Mapping) -> Expr {
+    Expr::Mapping(Rc::new(mapping))
+}
+
+/// The default comparator is recognised so mappings can order keys
+/// natively rather than calling back.
+fn comparator_arg(expr: &Expr) -> Result<Option<Expr>, &'static str> {
+    match expr {
+        Expr::Builtin(b) if std::ptr::fn_addr_eq(*b, default_less as Builtin) => Ok(None),
+        Expr::Builtin(_) | Expr::Lambda(_) | Expr::Closure(_) | Expr::RecordProc(_) => Ok(Some(expr.clone())),
+        _ => Err("expecting a comparator"),
+    }
+}
+
+/// Binds the key and value pairs in `list` into `mapping`.
+fn mapping_bind(mapping: &Mapping,
+                list: &[Expr],
+                replace: bool,
+                env: Rc<RefCell<Env>>) -> Result<Mapping, &'static str> {
+    if !list.len().is_multiple_of(2) { return Err("expecting keys and values in pairs") }
+
+    let mut mapping = mapping.clone();
+    for pair in list.chunks(2) {
+        mapping = mapping.insert(pair[0].clone(), pair[1].clone(), replace, env.clone())?;
+    }
+    Ok(mapping)
+}
+
+fn default_less(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let ordering = mapping::default_compare(&list[0], &list[1])?;
+    Ok(Expr::Literal(Literal::Bool(ordering == Ordering::Less)))
+}
+
+/// Returns the ordering procedure slippy uses as its default comparator.
+pub fn make_default_comparator(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Builtin(default_less))
+}
+
+pub fn mapping(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let mapping = Mapping::new(comparator_arg(&list[0])?);
+    Ok(new_mapping(mapping_bind(&mapping, &list[1..], false, env)?))
+}
+
+pub fn mappingp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Mapping(_)))))
+}
+
+pub fn mapping_emptyp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(mapping_arg(&list[0])?.is_empty())))
+}
+
+pub fn mapping_size(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Number(mapping_arg(&list[0])?.len() as i32)))
+}
+
+pub fn mapping_containsp(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let found = mapping_arg(&list[0])?.get(&list[1], env)?.is_some();
+    Ok(Expr::Literal(Literal::Bool(found)))
+}
+
+/// `(mapping-ref mapping key [failure [success]])`. Without a failure
+/// thunk a missing key is an error.
+pub fn mapping_ref(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    match mapping_arg(&list[0])?.get(&list[1], env.clone())? {
+        Some(value) => match list.get(3) {
+            Some(success) => eval::apply(success, vec![value], env),
+            None => Ok(value),
+        },
+        None => match list.get(2) {
+            Some(failure) => eval::apply(failure, vec![], env),
+            None => Err("key not found in mapping"),
+        },
+    }
+}
+
+pub fn mapping_ref_default(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 3 { return Err("called with incorrect number of arguments") }
+
+    let value = mapping_arg(&list[0])?.get(&list[1], env)?;
+    Ok(value.unwrap_or_else(|| list[2].clone()))
+}
+
+pub fn mapping_set(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let mapping = mapping_arg(&list[0])?;
+    Ok(new_mapping(mapping_bind(&mapping, &list[1..], true, env)?))
+}
+
+/// Like `mapping-set`, but keys already present keep their values.
+pub fn mapping_adjoin(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let mapping = mapping_arg(&list[0])?;
+    Ok(new_mapping(mapping_bind(&mapping, &list[1..], false, env)?))
+}
+
+pub fn mapping_delete(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let mut mapping = (*mapping_arg(&list[0])?).clone();
+    for key in list[1..].iter() {
+        mapping = mapping.remove(key, env.clone())?;
+    }
+    Ok(new_mapping(mapping))
+}
+
+/// `(mapping-fold kons knil mapping)` calls `(kons key value acc)` for each
+/// entry in key order.
+pub fn mapping_fold(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 3 { return Err("called with incorrect number of arguments") }
+
+    let mut acc = list[1].clone();
+    for (key, value) in mapping_arg(&list[2])?.entries() {
+        acc = eval::apply(&list[0], vec![key, value, acc], env.clone())?;
+    }
+    Ok(acc)
+}
+
+pub fn mapping_for_each(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    for (key, value) in mapping_arg(&list[1])?.entries() {
+        eval::apply(&list[0], vec![key, value], env.clone())?;
+    }
+    Ok(Expr::Unspecified)
+}
+
+pub fn mapping_keys(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let entries = mapping_arg(&list[0])?.entries();
+    Ok(Expr::List(entries.into_iter().map(|(k, _)| k).collect()))
+}
+
+pub fn mapping_values(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let entries = mapping_arg(&list[0])?.entries();
+    Ok(Expr::List(entries.into_iter().map(|(_, v)| v).collect()))
+}
+
+pub fn mapping_to_alist(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let mut alist = Vec::new();
+    for (key, value) in mapping_arg(&list[0])?.entries() {
+        alist.push(cons(&[key, value], env.clone())?);
+    }
+    Ok(Expr::List(alist))
+}
+
+/// `(alist->mapping comparator alist)`. The first association for a key
+/// wins, as with `assoc`.
+pub fn alist_to_mapping(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let mut mapping = Mapping::new(comparator_arg(&list[0])?);
+    for pair in list_arg(&list[1])? {
+        let key = car(std::slice::from_ref(&pair), env.clone())?;
+        let value = cdr(std::slice::from_ref(&pair), env.clone())?;
+        mapping = mapping.insert(key, value, false, env.clone())?;
+    }
+    Ok(new_mapping(mapping))
+}
+
+pub fn mapping_comparator(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(mapping_arg(&list[0])?.comparator().unwrap_or(Expr::Builtin(default_less)))
+}
+
+/*
+ * SRFI-14 char-set built-ins
+ */
+
+fn char_set_arg(expr: &Expr) -> Result<Rc<CharSet>, &'static str> {
+    match expr {
+        Expr::CharSet(c) => Ok(c.clone()),
+        _ => Err("expecting a char-set"),
+    }
+}
+
+fn char_arg(expr: &Expr) -> Result<char, &'static str> {
+    match expr {
+        Expr::Literal(Literal::Char(c)) => Ok(*c),
+        _ => Err("expecting a character"),
+    }
+}
+
+fn new_char_set(set: CharSet) -> Expr {
+    Expr::CharSet(Rc::new(set))
+}
+
+/// Adds `chars` to the optional base set at `list[i]`.
+fn char_set_with(list: &[Expr], i: usize, chars: Vec<char>) -> Result<Expr, &'static str> {
+    let set = CharSet::from_chars(chars);
+    match list.get(i) {
+        Some(base) => Ok(new_char_set(char_set_arg(base)?.union(&set))),
+        None => Ok(new_char_set(set)),
+    }
+}
+
+pub fn char_setp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::CharSet(_)))))
+}
+
+pub fn char_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let chars = list.iter().map(char_arg).collect::<Result<Vec<char>, _>>()?;
+    Ok(new_char_set(CharSet::from_chars(chars)))
+}
+
+pub fn list_to_char_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let chars = list_arg(&list[0])?.iter().map(char_arg).collect::<Result<Vec<char>, _>>()?;
+    char_set_with(list, 1, chars)
+}
+
+pub fn string_to_char_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    char_set_with(list, 1, string_arg(&list[0])?.chars().collect())
+}
+
+/// `(ucs-range->char-set low high)` holds the code points from `low` up to
+/// but excluding `high`.
+pub fn ucs_range_to_char_set(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let low = index_arg(&list[0])? as u32;
+    let high = index_arg(&list[1])? as u32;
+    if high <= low { return Ok(new_char_set(CharSet::empty())) }
+
+    let low = char::from_u32(low).ok_or("invalid code point")?;
+    let high = char::from_u32(high - 1).ok_or("invalid code point")?;
+    Ok(new_char_set(CharSet::range(low, high)))
+}
+
+pub fn char_set_to_list(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let set = char_set_arg(&list[0])?;
+    Ok(Expr::List(set.chars().map(|c| Expr::Literal(Literal::Char(c))).collect()))
+}
+
+pub fn char_set_to_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let set = char_set_arg(&list[0])?;
+    Ok(Expr::Literal(Literal::String(set.chars().collect())))
+}
+
+pub fn char_set_size(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Number(char_set_arg(&list[0])?.len() as i32)))
+}
+
+pub fn char_set_containsp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let set = char_set_arg(&list[0])?;
+    Ok(Expr::Literal(Literal::Bool(set.contains(char_arg(&list[1])?))))
+}
+
+pub fn char_set_eq(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let sets = list.iter().map(char_set_arg).collect::<Result<Vec<_>, _>>()?;
+    Ok(Expr::Literal(Literal::Bool(sets.windows(2).all(|w| w[0] == w[1]))))
+}
+
+pub fn char_set_union(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let mut set = CharSet::empty();
+    for arg in list {
+        set = set.union(&*char_set_arg(arg)?);
+    }
+    Ok(new_char_set(set))
+}
+
+pub fn char_set_intersection(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let mut set = CharSet::full();
+    for arg in list {
+        set = set.intersection(&*char_set_arg(arg)?);
+    }
+    Ok(new_char_set(set))
+}
+
+pub fn char_set_difference(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let mut set = (*char_set_arg(&list[0])?).clone();
+    for arg in list[1..].iter() {
+        set = set.difference(&*char_set_arg(arg)?);
+    }
+    Ok(new_char_set(set))
+}
+
+pub fn char_set_complement(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(new_char_set(char_set_arg(&list[0])?.complement()))
+}
+
+pub fn char_set_adjoin(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let chars = list[1..].iter().map(char_arg).collect::<Result<Vec<char>, _>>()?;
+    Ok(new_char_set(char_set_arg(&list[0])?.union(&CharSet::from_chars(chars))))
+}
+
+pub fn char_set_delete(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let chars = list[1..].iter().map(char_arg).collect::<Result<Vec<char>, _>>()?;
+    Ok(new_char_set(char_set_arg(&list[0])?.difference(&CharSet::from_chars(chars))))
+}
+
+pub fn char_set_filter(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let mut chars = Vec::new();
+    for c in char_set_arg(&list[1])?.chars() {
+        if truthy(&eval::apply(&list[0], vec![Expr::Literal(Literal::Char(c))], env.clone())?) {
+            chars.push(c);
+        }
+    }
+    char_set_with(list, 2, chars)
+}
+
+
+/*
+ * SRFI-115 regexp built-ins
+ */
+
+/// Accepts a compiled regexp or compiles an SRE.
+fn regexp_arg(expr: &Expr) -> Result<Rc<Regexp>, &'static str> {
+    match expr {
+        Expr::Regexp(re) => Ok(re.clone()),
+        sre => Ok(Rc::new(Regexp::from_sre(sre)?)),
+    }
+}
+
+fn regexp_match_arg(expr: &Expr) -> Result<Rc<RegexpMatch>, &'static str> {
+    match expr {
+        Expr::RegexpMatch(m) => Ok(m.clone()),
+        _ => Err("expecting a regexp match"),
+    }
+}
+
+/// A regexp, the text to match and the range of the text to consider.
+type RegexpArgs = (Rc<Regexp>, Rc<[char]>, usize, usize);
+
+/// Reads the regexp, string and optional range that most regexp
+/// procedures take, with the range starting at `list[from]`.
+fn regexp_args(list: &[Expr], from: usize) -> Result<RegexpArgs, &'static str> {
+    let re = regexp_arg(&list[0])?;
+    let text: Rc<[char]> = string_arg(&list[1])?.chars().collect();
+    let (start, end) = range_args(list, from, text.len())?;
+    Ok((re, text, start, end))
+}
+
+fn match_or_false(m: Option<RegexpMatch>) -> Expr {
+    match m {
+        Some(m) => Expr::RegexpMatch(Rc::new(m)),
+        None => Expr::Literal(Literal::Bool(false)),
+    }
+}
+
+/// Every match in `text[start..end]` from left to right. After an empty
+/// match the search resumes one character further on.
+fn regexp_matches_in(re: &Regexp,
+                     text: &Rc<[char]>,
+                     start: usize,
+                     end: usize) -> Result<Vec<RegexpMatch>, &'static str> {
+    let mut matches = Vec::new();
+    let mut from = start;
+    while from <= end {
+        let m = match re.search(text, start, end, from)? {
+            Some(m) => m,
+            None => break,
+        };
+        let (ms, me) = m.span(0).unwrap();
+        from = if me == ms { me + 1 } else { me };
+        matches.push(m);
+    }
+    Ok(matches)
+}
+
+/// Expands a substitution: a string, a submatch index or name, the symbols
+/// `pre` and `post`, or a list of these.
+fn substitute(out: &mut String,
+              subst: &Expr,
+              m: &RegexpMatch,
+              text: &[char],
+              start: usize,
+              end: usize) -> Result<(), &'static str> {
+    let (ms, me) = m.span(0).unwrap();
+    match subst {
+        Expr::Literal(Literal::String(s)) => out.push_str(s),
+        Expr::Symbol(s) if &*s.name() == "pre" => out.extend(&text[start..ms]),
+        Expr::Symbol(s) if &*s.name() == "post" => out.extend(&text[me..end]),
+        Expr::List(l) => {
+            for s in l {
+                substitute(out, s, m, text, start, end)?;
+            }
+        },
+        field => {
+            if let Some(s) = m.substring(m.index(field)?) {
+                out.push_str(&s);
+            }
+        },
+    }
+    Ok(())
+}
+
+pub fn regexp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Regexp(regexp_arg(&list[0])?))
+}
+
+/// Compiles a regexp from the conventional string syntax rather than an
+/// SRE.
+pub fn string_to_regexp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Regexp(Rc::new(Regexp::from_pattern(&string_arg(&list[0])?)?)))
+}
+
+pub fn regexpp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Regexp(_)))))
+}
+
+pub fn valid_srep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(Regexp::from_sre(&list[0]).is_ok())))
+}
+
+pub fn regexp_matches(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let (re, text, start, end) = regexp_args(list, 2)?;
+    Ok(match_or_false(re.matches(&text, start, end)?))
+}
+
+pub fn regexp_matchesp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let (re, text, start, end) = regexp_args(list, 2)?;
+    Ok(Expr::Literal(Literal::Bool(re.matches(&text, start, end)?.is_some())))
+}
+
+pub fn regexp_search(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let (re, text, start, end) = regexp_args(list, 2)?;
+    Ok(match_or_false(re.search(&text, start, end, start)?))
+}
+
+/// `(regexp-replace re string subst [start end count])` replaces the
+/// `count`th match, counting from zero.
+pub fn regexp_replace(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 3 || list.len() > 6 { return Err("called with incorrect number of arguments") }
+
+    let (re, text, start, end) = regexp_args(list, 3)?;
+    let count = match list.get(5) {
+        Some(expr) => count_arg(expr)?,
+        None => 0,
+    };
+
+    let matches = regexp_matches_in(&re, &text, start, end)?;
+    let m = match matches.get(count) {
+        Some(m) => m,
+        None => return Ok(Expr::Literal(Literal::String(text.iter().collect()))),
+    };
+
+    let (ms, me) = m.span(0).unwrap();
+    let mut out: String = text[..ms].iter().collect();
+    substitute(&mut out, &list[2], m, &text, start, end)?;
+    out.extend(&text[me..]);
+    Ok(Expr::Literal(Literal::String(out)))
+}
+
+pub fn regexp_replace_all(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 3 || list.len() > 5 { return Err("called with incorrect number of arguments") }
+
+    let (re, text, start, end) = regexp_args(list, 3)?;
+    let mut out: String = text[..start].iter().collect();
+    let mut last = start;
+    for m in regexp_matches_in(&re, &text, start, end)? {
+        let (ms, me) = m.span(0).unwrap();
+        out.extend(&text[last..ms]);
+        substitute(&mut out, &list[2], &m, &text, start, end)?;
+        last = me;
+    }
+    out.extend(&text[last..]);
+    Ok(Expr::Literal(Literal::String(out)))
+}
+
+/// Splits the string on non-empty matches.
+pub fn regexp_split(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let (re, text, start, end) = regexp_args(list, 2)?;
+    let mut pieces = Vec::new();
+    let mut last = start;
+    for m in regexp_matches_in(&re, &text, start, end)? {
+        let (ms, me) = m.span(0).unwrap();
+        if ms == me { continue }
+        pieces.push(Expr::Literal(Literal::String(text[last..ms].iter().collect())));
+        last = me;
+    }
+    pieces.push(Expr::Literal(Literal::String(text[last..end].iter().collect())));
+    Ok(Expr::List(pieces))
+}
+
+/// Returns the text of every non-empty match.
+pub fn regexp_extract(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let (re, text, start, end) = regexp_args(list, 2)?;
+    let strings = regexp_matches_in(&re, &text, start, end)?.into_iter()
+        .filter_map(|m| m.substring(0))
+        .filter(|s| !s.is_empty())
+        .map(|s| Expr::Literal(Literal::String(s)))
+        .collect();
+    Ok(Expr::List(strings))
+}
+
+pub fn regexp_matchp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::RegexpMatch(_)))))
+}
+
+pub fn regexp_match_count(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Number(regexp_match_arg(&list[0])?.count() as i32)))
+}
+
+/// `(regexp-match-submatch m field)` returns the text of a submatch given
+/// by index or name, or `#f` if it did not participate in the match.
+pub fn regexp_match_submatch(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let m = regexp_match_arg(&list[0])?;
+    match m.substring(m.index(&list[1])?) {
+        Some(s) => Ok(Expr::Literal(Literal::String(s))),
+        None => Ok(Expr::Literal(Literal::Bool(false))),
+    }
+}
+
+fn submatch_bound(list: &[Expr], end: bool) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let m = regexp_match_arg(&list[0])?;
+    match m.span(m.index(&list[1])?) {
+        Some((from, to)) => Ok(Expr::Literal(Literal::Number(if end { to } else { from } as i32))),
+        None => Ok(Expr::Literal(Literal::Bool(false))),
+    }
+}
+
+pub fn regexp_match_submatch_start(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    submatch_bound(list, false)
+}
+
+pub fn regexp_match_submatch_end(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    submatch_bound(list, true)
+}
+
+pub fn regexp_match_to_list(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let m = regexp_match_arg(&list[0])?;
+    let strings = (0..=m.count()).map(|i| match m.substring(i) {
+        Some(s) => Expr::Literal(Literal::String(s)),
+        None => Expr::Literal(Literal::Bool(false)),
+    }).collect();
+    Ok(Expr::List(strings))
+}
+
+/*
+ * String built-ins
+ */
+
+fn new_string(s: String) -> Expr {
+    Expr::Literal(Literal::String(s))
+}
+
+/// Tests that each adjacent pair of arguments is ordered by `cmp`.
+fn compare_chain<T>(list: &[Expr],
+                    arg: fn(&Expr) -> Result<T, &'static str>,
+                    cmp: fn(&T, &T) -> bool) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let args = list.iter().map(arg).collect::<Result<Vec<T>, _>>()?;
+    Ok(Expr::Literal(Literal::Bool(args.windows(2).all(|w| cmp(&w[0], &w[1])))))
+}
+
+fn folded_string_arg(expr: &Expr) -> Result<String, &'static str> {
+    Ok(string_arg(expr)?.to_lowercase())
+}
+
+pub fn make_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let k = index_arg(&list[0])?;
+    let fill = match list.get(1) {
+        Some(c) => char_arg(c)?,
+        None => ' ',
+    };
+    sandbox::reserve(k.saturating_mul(fill.len_utf8()))?;
+    let mut string = String::with_capacity(k * fill.len_utf8());
+    let mut left = k;
+    while left > 0 {
+        eval::poll()?;
+        let stretch = eval::POLL_INTERVAL.min(left);
+        string.extend(std::iter::repeat_n(fill, stretch));
+        left -= stretch;
+    }
+    Ok(new_string(string))
+}
+
+pub fn string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    Ok(new_string(list.iter().map(char_arg).collect::<Result<String, _>>()?))
+}
+
+pub fn string_length(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Number(string_arg(&list[0])?.chars().count() as i32)))
+}
+
+pub fn string_ref(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let c = string_arg(&list[0])?.chars().nth(index_arg(&list[1])?).ok_or("index out of range")?;
+    Ok(Expr::Literal(Literal::Char(c)))
+}
+
+/// The characters of the string at `list[0]` within the optional range
+/// starting at `list[from]`.
+fn string_range(list: &[Expr], from: usize) -> Result<Vec<char>, &'static str> {
+    let chars: Vec<char> = string_arg(&list[0])?.chars().collect();
+    let (start, end) = range_args(list, from, chars.len())?;
+    Ok(chars[start..end].to_vec())
+}
+
+pub fn substring(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 3 { return Err("called with incorrect number of arguments") }
+
+    Ok(new_string(string_range(list, 1)?.into_iter().collect()))
+}
+
+pub fn string_copy(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    Ok(new_string(string_range(list, 1)?.into_iter().collect()))
+}
+
+pub fn string_append(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    Ok(new_string(list.iter().map(string_arg).collect::<Result<String, _>>()?))
+}
+
+pub fn string_to_list(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::List(string_range(list, 1)?.into_iter().map(|c| Expr::Literal(Literal::Char(c))).collect()))
+}
+
+pub fn list_to_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(new_string(list_arg(&list[0])?.iter().map(char_arg).collect::<Result<String, _>>()?))
+}
+
+pub fn string_to_vector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    Ok(new_vector(string_range(list, 1)?.into_iter().map(|c| Expr::Literal(Literal::Char(c))).collect()))
+}
+
+pub fn vector_to_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let v = vector_arg(&list[0])?.borrow().clone();
+    let (start, end) = range_args(list, 1, v.len())?;
+    Ok(new_string(v[start..end].iter().map(char_arg).collect::<Result<String, _>>()?))
+}
+
+pub fn string_eq(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, string_arg, |a, b| a == b)
+}
+
+pub fn string_lt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, string_arg, |a, b| a < b)
+}
+
+pub fn string_gt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, string_arg, |a, b| a > b)
+}
+
+pub fn string_lte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, string_arg, |a, b| a <= b)
+}
+
+pub fn string_gte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, string_arg, |a, b| a >= b)
+}
+
+pub fn string_ci_eq(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, folded_string_arg, |a, b| a == b)
+}
+
+pub fn string_ci_lt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, folded_string_arg, |a, b| a < b)
+}
+
+pub fn string_ci_gt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, folded_string_arg, |a, b| a > b)
+}
+
+pub fn string_ci_lte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, folded_string_arg, |a, b| a <= b)
+}
+
+pub fn string_ci_gte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, folded_string_arg, |a, b| a >= b)
+}
+
+pub fn string_upcase(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(new_string(string_arg(&list[0])?.to_uppercase()))
+}
+
+pub fn string_downcase(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(new_string(string_arg(&list[0])?.to_lowercase()))
+}
+
+pub fn string_foldcase(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    string_downcase(list, env)
+}
+
+/// The characters at each index of the given strings, up to the length of
+/// the shortest.
+fn string_columns(strings: &[Expr]) -> Result<Vec<Vec<Expr>>, &'static str> {
+    let strings = strings.iter()
+        .map(|s| Ok(string_arg(s)?.chars().map(|c| Expr::Literal(Literal::Char(c))).collect()))
+        .collect::<Result<Vec<Vec<Expr>>, &'static str>>()?;
+    Ok(zip_lists(&strings))
+}
+
+pub fn string_map(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 { return Err("called with incorrect number of arguments") }
+
+    let mut result = String::new();
+    for args in string_columns(&list[1..])? {
+        result.push(char_arg(&eval::apply(&list[0], args, env.clone())?)?);
+    }
+    Ok(new_string(result))
+}
+
+pub fn string_for_each(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 { return Err("called with incorrect number of arguments") }
+
+    for args in string_columns(&list[1..])? {
+        eval::apply(&list[0], args, env.clone())?;
+    }
+    Ok(Expr::Unspecified)
+}
+
+
+/*
+ * Character built-ins
+ */
+
+fn folded_char_arg(expr: &Expr) -> Result<char, &'static str> {
+    Ok(fold_char(char_arg(expr)?))
+}
+
+fn fold_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Maps a character to the single character `op` converts it to, leaving
+/// it unchanged if the conversion is not one to one.
+fn convert_char(list: &[Expr], op: fn(char) -> char) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Char(op(char_arg(&list[0])?))))
+}
+
+fn char_test(list: &[Expr], test: fn(char) -> bool) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(test(char_arg(&list[0])?))))
+}
+
+pub fn char_eq(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, char_arg, |a, b| a == b)
+}
+
+pub fn char_lt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, char_arg, |a, b| a < b)
+}
+
+pub fn char_gt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, char_arg, |a, b| a > b)
+}
+
+pub fn char_lte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, char_arg, |a, b| a <= b)
+}
+
+pub fn char_gte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, char_arg, |a, b| a >= b)
+}
+
+pub fn char_ci_eq(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, folded_char_arg, |a, b| a == b)
+}
+
+pub fn char_ci_lt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, folded_char_arg, |a, b| a < b)
+}
+
+pub fn char_ci_gt(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, folded_char_arg, |a, b| a > b)
+}
+
+pub fn char_ci_lte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, folded_char_arg, |a, b| a <= b)
+}
+
+pub fn char_ci_gte(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, folded_char_arg, |a, b| a >= b)
+}
+
+pub fn char_to_integer(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Number(char_arg(&list[0])? as i32)))
+}
+
+pub fn integer_to_char(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let c = char::from_u32(index_arg(&list[0])? as u32).ok_or("invalid code point")?;
+    Ok(Expr::Literal(Literal::Char(c)))
+}
+
+pub fn char_alphabeticp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    char_test(list, char::is_alphabetic)
+}
+
+pub fn char_numericp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    char_test(list, char::is_numeric)
+}
+
+pub fn char_whitespacep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    char_test(list, char::is_whitespace)
+}
+
+pub fn char_upper_casep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    char_test(list, char::is_uppercase)
+}
+
+pub fn char_lower_casep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    char_test(list, char::is_lowercase)
+}
+
+/// The value of a decimal digit, or `#f` for any other character.
+pub fn digit_value(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match char_arg(&list[0])?.to_digit(10) {
+        Some(d) => Ok(Expr::Literal(Literal::Number(d as i32))),
+        None => Ok(Expr::Literal(Literal::Bool(false))),
+    }
+}
+
+pub fn char_upcase(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    convert_char(list, |c| {
+        let mut upper = c.to_uppercase();
+        match (upper.next(), upper.next()) {
+            (Some(u), None) => u,
+            _ => c,
+        }
+    })
+}
+
+pub fn char_downcase(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    convert_char(list, fold_char)
+}
+
+pub fn char_foldcase(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    convert_char(list, fold_char)
+}
+
+/*
+ * Symbol built-ins
+ */
+
+pub fn string_to_symbol(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::Literal(Literal::String(s)) => Ok(Expr::Symbol(Symbol::intern(s))),
+        _ => Err("expecting a string"),
+    }
+}
+
+pub fn symbol_to_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let sym = list[0].to_symbol().ok_or("expecting a symbol")?;
+    Ok(Expr::Literal(Literal::String(sym.name().to_string())))
+}
+
+pub fn gensym(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let prefix = match list {
+        [] => String::from("g"),
+        [Expr::Literal(Literal::String(s))] => s.clone(),
+        [Expr::Symbol(s)] => s.name().to_string(),
+        [_] => return Err("expecting a string or symbol prefix"),
+        _ => return Err("called with incorrect number of arguments"),
+    };
+
+    Ok(Expr::Symbol(Symbol::uninterned(&prefix)))
+}
+
+pub fn symbol_internedp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let sym = list[0].to_symbol().ok_or("expecting a symbol")?;
+    Ok(Expr::Literal(Literal::Bool(sym.is_interned())))
+}
+
+
+/*
+ * Tests
+ */
+
+pub fn equalp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let lval = &list[0];
+    let rval = &list[1];
+
+    Ok(Expr::Literal(Literal::Bool(lval == rval)))
+}
+
+/// Identity comparison. Symbols compare by id and heap objects, including
+/// procedures, by pointer. Lists and strings are plain values in slippy, so
+/// they have no identity of their own and are compared structurally.
+fn is_eqv(lval: &Expr, rval: &Expr) -> bool {
+    match (lval, rval) {
+        (Expr::Bytevector(l), Expr::Bytevector(r)) => Rc::ptr_eq(l, r),
+        (Expr::Vector(l), Expr::Vector(r)) => Rc::ptr_eq(l, r),
+        (Expr::Record(l), Expr::Record(r)) => Rc::ptr_eq(l, r),
+        (Expr::RecordProc(l), Expr::RecordProc(r)) => Rc::ptr_eq(l, r),
+        (Expr::Builtin(l), Expr::Builtin(r)) => std::ptr::fn_addr_eq(*l, *r),
+        (Expr::Lambda(l), Expr::Lambda(r)) => Rc::ptr_eq(l, r),
+        (Expr::Closure(l), Expr::Closure(r)) => Rc::ptr_eq(l, r),
+        _ => lval == rval,
+    }
+}
+
+pub fn eqp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(is_eqv(&list[0], &list[1]))))
+}
+
+pub fn listp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::List(_) => Ok(Expr::Literal(Literal::Bool(true))),
+        _             => Ok(Expr::Literal(Literal::Bool(false))),
+    }
+}
+
+pub fn nullp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::List(l) => {
+            if l.is_empty() {
+                Ok(Expr::Literal(Literal::Bool(true)))
+            } else {
+                Ok(Expr::Literal(Literal::Bool(false)))
+            }
+        },
+        _ => Ok(Expr::Literal(Literal::Bool(false))),
+    }
+}
+
+pub fn procedurep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::Lambda(_) | Expr::Closure(_) => {
+            Ok(Expr::Literal(Literal::Bool(true)))
+        },
+        Expr::Builtin(_) => {
+            Ok(Expr::Literal(Literal::Bool(true)))
+        },
+        Expr::RecordProc(_) => {
+            Ok(Expr::Literal(Literal::Bool(true)))
+        },
+        _ => Ok(Expr::Literal(Literal::Bool(false))),
+    }
+}
+
+pub fn numberp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::Literal(Literal::Number(_)) => Ok(Expr::Literal(Literal::Bool(true))),
+        Expr::Literal(Literal::Float(_)) => Ok(Expr::Literal(Literal::Bool(true))),
+        _ => Ok(Expr::Literal(Literal::Bool(false))),
+    }
+}
+
+pub fn symbolp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::Symbol(_) => Ok(Expr::Literal(Literal::Bool(true))),
+        _ => Ok(Expr::Literal(Literal::Bool(false))),
+    }
+}
+
+pub fn bytevectorp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(list[0].is_bytevector())))
+}
+
+pub fn vectorp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(list[0].to_vector().is_some())))
+}
+
+pub fn hash_tablep(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(list[0].to_hash_table().is_some())))
+}
+
+pub fn symbol_eqp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let first = list[0].to_symbol().ok_or("expecting a symbol")?;
+    for val in list.iter().skip(1) {
+        if val.to_symbol().ok_or("expecting a symbol")? != first {
+            return Ok(Expr::Literal(Literal::Bool(false)))
+        }
+    }
+    Ok(Expr::Literal(Literal::Bool(true)))
+}
+
+pub fn charp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Literal(Literal::Char(_))))))
+}
+
+pub fn pairp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::DottedPair(_, _) => Ok(Expr::Literal(Literal::Bool(true))),
+        Expr::List(l) => {
+            if l.is_empty() {
+                Ok(Expr::Literal(Literal::Bool(false)))
+            } else {
+                Ok(Expr::Literal(Literal::Bool(true)))
+            }
+        },
+        _ => Ok(Expr::Literal(Literal::Bool(false))),
+    }
+}
+
+pub fn not(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(list[0].is_false())))
+}
+
+pub fn booleanp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Literal(Literal::Bool(_))))))
+}
+
+pub fn boolean_eq(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    compare_chain(list, |b| match b {
+        Expr::Literal(Literal::Bool(b)) => Ok(*b),
+        _ => Err("expecting a boolean"),
+    }, |a, b| a == b)
+}
+
+pub fn stringp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Literal(Literal::String(_))))))
+}
+
+
+/*
+ * Port built-ins
+ */
+
+fn port_arg(expr: &Expr) -> Result<Rc<Port>, &'static str> {
+    match expr {
+        Expr::Port(p) => Ok(p.clone()),
+        _ => Err("expecting a port"),
+    }
+}
+
+/// Returns the port at `list[i]`, defaulting to the current input port.
+fn input_port_arg(list: &[Expr], i: usize) -> Result<Rc<Port>, &'static str> {
+    match list.get(i) {
+        Some(expr) => port_arg(expr),
+        None => Ok(port::current_input()),
+    }
+}
+
+/// Returns the port at `list[i]`, defaulting to the current output port.
+fn output_port_arg(list: &[Expr], i: usize) -> Result<Rc<Port>, &'static str> {
+    match list.get(i) {
+        Some(expr) => port_arg(expr),
+        None => Ok(port::current_output()),
+    }
+}
+
+fn string_arg(expr: &Expr) -> Result<String, &'static str> {
+    match expr {
+        Expr::Literal(Literal::String(s)) => Ok(s.clone()),
+        _ => Err("expecting a string"),
+    }
+}
+
+fn char_or_eof(c: Option<char>) -> Expr {
+    match c {
+        Some(c) => Expr::Literal(Literal::Char(c)),
+        None => Expr::Eof,
+    }
+}
+
+fn byte_or_eof(b: Option<u8>) -> Expr {
+    match b {
+        Some(b) => Expr::Literal(Literal::Number(b as i32)),
+        None => Expr::Eof,
+    }
+}
+
+fn open_file(list: &[Expr], input: bool, binary: bool) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let path = string_arg(&list[0])?;
+    let port = if input {
+        Port::open_input_file(&path, binary)?
+    } else {
+        Port::open_output_file(&path, binary)?
+    };
+    Ok(Expr::Port(Rc::new(port)))
+}
+
+pub fn current_input_port(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Port(port::current_input()))
+}
+
+pub fn current_output_port(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Port(port::current_output()))
+}
+
+pub fn current_error_port(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Port(port::current_error()))
+}
+
+pub fn file_existsp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(Path::new(&string_arg(&list[0])?).exists())))
+}
+
+pub fn delete_file(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    std::fs::remove_file(string_arg(&list[0])?).map_err(|_| "unable to delete file")?;
+    Ok(Expr::Unspecified)
+}
+
+pub fn open_input_file(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    open_file(list, true, false)
+}
+
+pub fn open_binary_input_file(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    open_file(list, true, true)
+}
+
+pub fn open_output_file(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    open_file(list, false, false)
+}
+
+pub fn open_binary_output_file(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    open_file(list, false, true)
+}
+
+pub fn close_port(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    port_arg(&list[0])?.close()?;
+    Ok(Expr::Unspecified)
+}
+
+pub fn close_input_port(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    if !port_arg(&list[0])?.input { return Err("expecting an input port") }
+    close_port(list, env)
+}
+
+pub fn close_output_port(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    if port_arg(&list[0])?.input { return Err("expecting an output port") }
+    close_port(list, env)
+}
+
+pub fn call_with_input_file(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let port = open_file(&list[0..1], true, false)?;
+    let result = eval::apply(&list[1], vec![port.clone()], env);
+    port_arg(&port)?.close()?;
+    result
+}
+
+pub fn call_with_output_file(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let port = open_file(&list[0..1], false, false)?;
+    let result = eval::apply(&list[1], vec![port.clone()], env);
+    port_arg(&port)?.close()?;
+    result
+}
+
+pub fn with_input_from_file(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let port = port_arg(&open_file(&list[0..1], true, false)?)?;
+    let previous = port::set_current_input(port.clone());
+    let result = eval::apply(&list[1], Vec::new(), env);
+    port::set_current_input(previous);
+    port.close()?;
+    result
+}
+
+pub fn with_output_to_file(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let port = port_arg(&open_file(&list[0..1], false, false)?)?;
+    let previous = port::set_current_output(port.clone());
+    let result = eval::apply(&list[1], Vec::new(), env);
+    port::set_current_output(previous);
+    port.close()?;
+    result
+}
+
+pub fn open_input_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let s = string_arg(&list[0])?;
+    Ok(Expr::Port(Rc::new(Port::open_input_buffer(s.into_bytes(), false))))
+}
+
+pub fn open_output_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Port(Rc::new(Port::open_output_buffer(false))))
+}
+
+pub fn get_output_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let port = port_arg(&list[0])?;
+    if port.binary { return Err("expecting a string output port") }
+    let bytes = port.buffer_contents()?;
+    // Only whole strings are ever written, so the buffer is valid UTF-8.
+    Ok(Expr::Literal(Literal::String(String::from_utf8_lossy(&bytes).into_owned())))
+}
+
+pub fn open_input_bytevector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let bytes = bytevector_arg(&list[0])?.borrow().clone();
+    Ok(Expr::Port(Rc::new(Port::open_input_buffer(bytes, true))))
+}
+
+pub fn open_output_bytevector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Port(Rc::new(Port::open_output_buffer(true))))
+}
+
+pub fn get_output_bytevector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let port = port_arg(&list[0])?;
+    if !port.binary { return Err("expecting a bytevector output port") }
+    Ok(Expr::Bytevector(Rc::new(RefCell::new(port.buffer_contents()?))))
+}
+
+pub fn call_with_output_string(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let port = Expr::Port(Rc::new(Port::open_output_buffer(false)));
+    eval::apply(&list[0], vec![port.clone()], env.clone())?;
+    get_output_string(&[port], env)
+}
+
+pub fn with_output_to_string(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let port = Rc::new(Port::open_output_buffer(false));
+    let previous = port::set_current_output(port.clone());
+    let result = eval::apply(&list[0], Vec::new(), env.clone());
+    port::set_current_output(previous);
+    result?;
+    get_output_string(&[Expr::Port(port)], env)
+}
+
+pub fn read_char(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(char_or_eof(input_port_arg(list, 0)?.read_char()?))
+}
+
+pub fn peek_char(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(char_or_eof(input_port_arg(list, 0)?.peek_char()?))
+}
+
+pub fn char_readyp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(input_port_arg(list, 0)?.char_ready()?)))
+}
+
+pub fn read_line(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 1 { return Err("called with incorrect number of arguments") }
+
+    match input_port_arg(list, 0)?.read_line()? {
+        Some(line) => Ok(Expr::Literal(Literal::String(line))),
+        None => Ok(Expr::Eof),
+    }
+}
+
+pub fn read_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let k = count_arg(&list[0])?;
+    match input_port_arg(list, 1)?.read_string(k)? {
+        Some(s) => Ok(Expr::Literal(Literal::String(s))),
+        None => Ok(Expr::Eof),
+    }
+}
+
+pub fn read_u8(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(byte_or_eof(input_port_arg(list, 0)?.read_u8()?))
+}
+
+pub fn peek_u8(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(byte_or_eof(input_port_arg(list, 0)?.peek_u8()?))
+}
+
+pub fn u8_readyp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(input_port_arg(list, 0)?.u8_ready()?)))
+}
+
+pub fn read_bytevector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let k = count_arg(&list[0])?;
+    match input_port_arg(list, 1)?.read_bytes(k)? {
+        Some(bytes) => Ok(Expr::Bytevector(Rc::new(RefCell::new(bytes)))),
+        None => Ok(Expr::Eof),
     }
+}
+
+pub fn write_char(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
 
+    let c = match &list[0] {
+        Expr::Literal(Literal::Char(c)) => *c,
+        _ => return Err("expecting a character"),
+    };
+    output_port_arg(list, 1)?.write_str(c.encode_utf8(&mut [0; 4]))?;
     Ok(Expr::Unspecified)
 }
 
-pub fn read(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 0 { return Err("input ports are not yet supported for read") }
+pub fn write_string(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 4 { return Err("called with incorrect number of arguments") }
 
-    let mut line = String::new();
-    io::stdin().read_line(&mut line)
-        .expect("Failed to read line.");
+    let chars: Vec<char> = string_arg(&list[0])?.chars().collect();
+    let (start, end) = range_args(list, 2, chars.len())?;
+    let s: String = chars[start..end].iter().collect();
+    output_port_arg(list, 1)?.write_str(&s)?;
+    Ok(Expr::Unspecified)
+}
 
-    let line = format!("'{}", line.trim());
-    let mut lexer = Lexer::new(&line);
-    lexer.scan();
-    let mut parser = Parser::new(lexer.tokens);
-    let result = parser.parse();
-    if result.is_ok() {
-        let exprs = result.unwrap();
-        for expr in exprs.iter() {
-            return eval(expr, env);
-        }
+pub fn write_u8(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let b = byte_arg(&list[0])?;
+    output_port_arg(list, 1)?.write_bytes(&[b])?;
+    Ok(Expr::Unspecified)
+}
+
+pub fn write_bytevector(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 4 { return Err("called with incorrect number of arguments") }
+
+    let bv = bytevector_arg(&list[0])?;
+    let bv = bv.borrow();
+    let (start, end) = range_args(list, 2, bv.len())?;
+    output_port_arg(list, 1)?.write_bytes(&bv[start..end])?;
+    Ok(Expr::Unspecified)
+}
+
+pub fn newline(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 1 { return Err("called with incorrect number of arguments") }
+
+    output_port_arg(list, 0)?.write_str("\n")?;
+    Ok(Expr::Unspecified)
+}
+
+pub fn flush_output_port(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 1 { return Err("called with incorrect number of arguments") }
+
+    output_port_arg(list, 0)?.flush()?;
+    Ok(Expr::Unspecified)
+}
+
+pub fn eof_object(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Eof)
+}
+
+pub fn eof_objectp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Eof))))
+}
+
+pub fn portp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Port(_)))))
+}
+
+pub fn input_portp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(&list[0], Expr::Port(p) if p.input))))
+}
+
+pub fn output_portp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(&list[0], Expr::Port(p) if !p.input))))
+}
+
+pub fn textual_portp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(&list[0], Expr::Port(p) if !p.binary))))
+}
+
+pub fn binary_portp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(&list[0], Expr::Port(p) if p.binary))))
+}
+
+pub fn input_port_openp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let port = port_arg(&list[0])?;
+    Ok(Expr::Literal(Literal::Bool(port.input && port.is_open())))
+}
+
+pub fn output_port_openp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    let port = port_arg(&list[0])?;
+    Ok(Expr::Literal(Literal::Bool(!port.input && port.is_open())))
+}
+
+
+/*
+ * JSON built-ins
+ */
+
+/// Returns the JSON source at `list[i]`: a port, a string to read from, or
+/// by default the current input port.
+fn json_source_arg(list: &[Expr], i: usize) -> Result<Rc<Port>, &'static str> {
+    match list.get(i) {
+        Some(Expr::Literal(Literal::String(s))) => Ok(Rc::new(Port::open_input_buffer(s.clone().into_bytes(), false))),
+        _ => input_port_arg(list, i),
+    }
+}
+
+fn json_object_type_arg(list: &[Expr], i: usize) -> Result<json::ObjectType, &'static str> {
+    match list.get(i) {
+        None => Ok(json::ObjectType::Alist),
+        Some(Expr::Symbol(s)) if &*s.name() == "alist" => Ok(json::ObjectType::Alist),
+        Some(Expr::Symbol(s)) if &*s.name() == "hash-table" => Ok(json::ObjectType::HashTable),
+        Some(_) => Err("expecting 'alist or 'hash-table"),
+    }
+}
+
+/// `(json-read [source object-type])` reads one JSON value, where
+/// `object-type` is `'alist` (the default) or `'hash-table`. A string
+/// source must hold exactly one value.
+pub fn json_read(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let port = json_source_arg(list, 0)?;
+    let objects = json_object_type_arg(list, 1)?;
+    match list.first() {
+        Some(Expr::Literal(Literal::String(_))) => json::read_all(&port, objects, env),
+        _ => json::read(&port, objects, env),
+    }
+}
+
+/// `(json-for-each proc [source object-type])` applies `proc` to each
+/// JSON value in the source, streaming the elements of top-level arrays.
+pub fn json_for_each(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 3 { return Err("called with incorrect number of arguments") }
+
+    let port = json_source_arg(list, 1)?;
+    let objects = json_object_type_arg(list, 2)?;
+    json::for_each(&port, objects, env.clone(), &mut |value| {
+        eval::apply(&list[0], vec![value], env.clone()).map(|_| ())
+    })?;
+
+    Ok(Expr::Unspecified)
+}
+
+pub fn json_write(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let port = output_port_arg(list, 1)?;
+    port.write_str(&json::write(&list[0])?)?;
+
+    Ok(Expr::Unspecified)
+}
+
+
+/*
+ * Environment built-ins
+ */
+
+/// `(eval expr [environment])` evaluates a datum, by default in the top
+/// level of the calling environment.
+pub fn eval_(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let env = environment_arg(list, 1, Env::top_level(env))?;
+    eval(&list[0].to_code(), env)
+}
+
+pub fn environmentp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Bool(matches!(list[0], Expr::Environment(_)))))
+}
+
+/// `(environment import-set ...)` returns an environment holding only the
+/// imported bindings. Import sets are quoted data here.
+pub fn environment(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let sets: Vec<Expr> = list.iter().map(|set| set.to_code()).collect();
+    Ok(Expr::Environment(library::environment(&sets)?))
+}
+
+pub fn interaction_environment(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Environment(Env::top_level(env)))
+}
+
+fn report_version_arg(list: &[Expr]) -> Result<(), &'static str> {
+    match list {
+        [Expr::Literal(Literal::Number(5))] => Ok(()),
+        [_] => Err("only version 5 of the report is supported"),
+        _ => Err("called with incorrect number of arguments"),
+    }
+}
+
+/// The R5RS procedures, taken from the R7RS libraries that replace them.
+pub fn scheme_report_environment(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    report_version_arg(list)?;
+
+    let sets: Vec<Expr> = ["base", "char", "cxr", "eval", "file", "inexact", "lazy", "read", "write"].iter()
+        .map(|name| Expr::List(vec![Expr::Var(Symbol::intern("scheme")), Expr::Var(Symbol::intern(name))]))
+        .collect();
+    Ok(Expr::Environment(library::environment(&sets)?))
+}
+
+/// Only the special forms, which need no bindings.
+pub fn null_environment(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    report_version_arg(list)?;
+
+    Ok(Expr::Environment(Env::empty()))
+}
+
+fn environment_name_arg(list: &[Expr]) -> Result<(Rc<RefCell<Env>>, Symbol), &'static str> {
+    let env = match &list[0] {
+        Expr::Environment(e) => e.clone(),
+        _ => return Err("expecting an environment"),
+    };
+    let name = list[1].to_symbol().ok_or("expecting a symbol")?;
+    Ok((env, name))
+}
+
+pub fn environment_boundp(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let (env, name) = environment_name_arg(list)?;
+    let bound = env.borrow().get(name).is_some();
+    Ok(Expr::Literal(Literal::Bool(bound)))
+}
+
+/// The names bound in an environment and those it extends, in no
+/// particular order.
+pub fn environment_bound_names(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match &list[0] {
+        Expr::Environment(e) => Ok(Expr::List(e.borrow().names().into_iter().map(Expr::Symbol).collect())),
+        _ => Err("expecting an environment"),
+    }
+}
+
+/// `(environment-assign! env name value)` changes an existing binding.
+pub fn environment_assign(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 3 { return Err("called with incorrect number of arguments") }
+
+    let (env, name) = environment_name_arg(list)?;
+    env.borrow_mut().set(name, list[2].clone())?;
+    Ok(Expr::Unspecified)
+}
+
+
+/*
+ * Other
+ */
+
+pub fn apply(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() < 2 { return Err("called with incorrect number of arguments") }
+
+    let proc = &list[0];
+    let objs = &list[1..list.len() - 1];
+    let arg = &list[list.len() - 1];
+
+    let mut objlist = Vec::new();
+    for obj in objs.iter() {
+        objlist.push(obj.clone());
+    }
+
+    if let Expr::List(l) = arg {
+        objlist.append(&mut l.clone());
+        eval::apply(proc, objlist, env)
+    } else {
+        Err("apply expecting a list")
+    }
+}
+
+pub fn values(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() == 1 {
+        Ok(list[0].clone())
     } else {
-        println!("{}", result.unwrap_err());
-        println!("pos: {}", parser.current);
+        Ok(Expr::Values(list.to_vec()))
+    }
+}
+
+pub fn call_with_values(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 2 { return Err("called with incorrect number of arguments") }
+
+    let args = match eval::apply(&list[0], Vec::new(), env.clone())? {
+        Expr::Values(vals) => vals,
+        val => vec![val],
+    };
+    eval::apply(&list[1], args, env)
+}
+
+/// Returns the environment argument at `list[i]`, defaulting to `env`.
+fn environment_arg(list: &[Expr], i: usize, env: Rc<RefCell<Env>>) -> Result<Rc<RefCell<Env>>, &'static str> {
+    match list.get(i) {
+        Some(Expr::Environment(e)) => Ok(e.clone()),
+        Some(_) => Err("expecting an environment"),
+        None => Ok(env),
+    }
+}
+
+fn load_file(list: &[Expr], env: Rc<RefCell<Env>>, once: bool) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let file = string_arg(&list[0])?;
+    library::load(&file, environment_arg(list, 1, env)?, once)?;
+    Ok(Expr::Unspecified)
+}
+
+/// `(load file [environment])` evaluates a file, resolving a relative path
+/// against the directory of the file being loaded.
+pub fn load(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    load_file(list, env, false)
+}
+
+/// Like `load`, but skips a file that has already been loaded. Also bound
+/// as `require`.
+pub fn load_once(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    load_file(list, env, true)
+}
+
+pub fn features(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::List(library::features().into_iter().map(|f| Expr::Symbol(Symbol::intern(f))).collect()))
+}
+
+/// Seconds since the Unix epoch.
+pub fn current_second(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|_| "system clock is before the epoch")?;
+    Ok(float_expr(now.as_secs_f64()))
+}
+
+thread_local! {
+    static EPOCH: Instant = Instant::now();
+}
+
+/// Milliseconds since the first call, which keeps jiffies within fixnum
+/// range for about 24 days.
+pub fn current_jiffy(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let elapsed = EPOCH.with(|epoch| epoch.elapsed().as_millis());
+    Ok(Expr::Literal(Literal::Number(elapsed as i32)))
+}
+
+pub fn jiffies_per_second(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Number(1000)))
+}
+
+pub fn command_line(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::List(std::env::args().map(new_string).collect()))
+}
+
+/// Converts the optional argument of `exit` to a status code: true or
+/// absent means success and false failure.
+fn exit_code(list: &[Expr]) -> Result<i32, &'static str> {
+    match list {
+        [] | [Expr::Literal(Literal::Bool(true))] => Ok(0),
+        [Expr::Literal(Literal::Bool(false))] => Ok(1),
+        [Expr::Literal(Literal::Number(n))] => Ok(*n),
+        [_] => Err("expecting a boolean or integer exit status"),
+        _ => Err("called with incorrect number of arguments"),
+    }
+}
+
+pub fn exit(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let code = exit_code(list)?;
+    let _ = port::current_output().flush();
+    let _ = port::current_error().flush();
+    process::exit(code)
+}
+
+/// Exits without flushing output ports.
+pub fn emergency_exit(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    process::exit(exit_code(list)?)
+}
+
+pub fn get_environment_variable(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() != 1 { return Err("called with incorrect number of arguments") }
+
+    match std::env::var(string_arg(&list[0])?) {
+        Ok(value) => Ok(new_string(value)),
+        Err(_) => Ok(Expr::Literal(Literal::Bool(false))),
     }
+}
+
+pub fn get_environment_variables(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::List(std::env::vars()
+        .map(|(name, value)| Expr::DottedPair(vec![new_string(name)], Box::new(new_string(value))))
+        .collect()))
+}
+
+/// `(gc)` frees unreachable cycles now, returning how many objects were
+/// freed.
+pub fn gc_(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
+
+    Ok(Expr::Literal(Literal::Number(gc::collect().min(i32::MAX as usize) as i32)))
+}
+
+pub fn gc_stats(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if !list.is_empty() { return Err("called with incorrect number of arguments") }
 
-    Err("read error")
+    let (collections, tracked, freed) = gc::stats();
+    let stats = [("collections", collections), ("tracked", tracked), ("freed", freed)];
+    Ok(Expr::List(stats.iter()
+        .map(|(name, n)| Expr::DottedPair(vec![Expr::Symbol(Symbol::intern(name))],
+                                          Box::new(Expr::Literal(Literal::Number((*n).min(i32::MAX as usize) as i32)))))
+        .collect()))
+}
+
+pub fn read(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    if list.len() > 1 { return Err("called with incorrect number of arguments") }
+
+    let port = input_port_arg(list, 0)?;
+    reader::read(&port)
 }
 
 pub fn display(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 1 { return Err("output ports are not yet supported for display") }
+    print(list, printer::Mode::Display)
+}
 
-    let expr = &list[0];
-    println!("{}", expr);
+fn print(list: &[Expr], mode: printer::Mode) -> Result<Expr, &'static str> {
+    if list.is_empty() || list.len() > 2 { return Err("called with incorrect number of arguments") }
+
+    let port = output_port_arg(list, 1)?;
+    port.write_str(&printer::to_string(&list[0], mode))?;
 
     Ok(Expr::Unspecified)
 }
+
+pub fn write(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    print(list, printer::Mode::Write)
+}
+
+/// `(write-shared obj [port])` labels every vector and record that appears
+/// more than once. Lists are values rather than shared pairs, so a list
+/// that appears twice is printed twice.
+pub fn write_shared(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    print(list, printer::Mode::Shared)
+}
+
+pub fn write_simple(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    print(list, printer::Mode::Simple)
+}
+
+/// The largest width or precision a format directive may ask for.
+const MAX_FORMAT_FIELD: usize = 65535;
+
+/// Reads the digits of a directive's width or precision, if there are any.
+fn format_field(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Option<usize>, &'static str> {
+    let mut field = None;
+    while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+        let n = field.unwrap_or(0) * 10 + d.to_digit(10).unwrap() as usize;
+        if n > MAX_FORMAT_FIELD { return Err("format width or precision is too large") }
+        field = Some(n);
+    }
+    Ok(field)
+}
+
+/// Pads `out` with spaces so that `text` fills `width` characters.
+fn pad(out: &mut String, text: &str, width: usize) {
+    for _ in text.chars().count()..width {
+        out.push(' ');
+    }
+}
+
+/// `(format dest fmt arg ...)` after SRFI-28 and SRFI-48. The destination is
+/// a port, `#t` for the current output port, or `#f` (or omitted) to return
+/// the result as a string.
+pub fn format(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let (port, rest) = match list.first() {
+        Some(Expr::Port(p)) => (Some(p.clone()), &list[1..]),
+        Some(Expr::Literal(Literal::Bool(true))) => (Some(port::current_output()), &list[1..]),
+        Some(Expr::Literal(Literal::Bool(false))) => (None, &list[1..]),
+        _ => (None, list),
+    };
+    if rest.is_empty() { return Err("called with incorrect number of arguments") }
+
+    let fmt = string_arg(&rest[0])?;
+    let mut args = rest[1..].iter();
+    let mut out = String::new();
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            out.push(c);
+            continue;
+        }
+
+        // ~[width][,precision]directive
+        let width = format_field(&mut chars)?.unwrap_or(0);
+        let mut precision = None;
+        if chars.next_if_eq(&',').is_some() {
+            precision = Some(format_field(&mut chars)?.ok_or("expecting a precision in format directive")?);
+        }
+
+        let directive = chars.next().ok_or("incomplete format directive")?;
+        let text = match directive.to_ascii_lowercase() {
+            '~' => "~".to_string(),
+            '%' | 'n' => "\n".to_string(),
+            'a' | 's' | 'w' => {
+                let arg = args.next().ok_or("too few arguments for format string")?;
+                let mode = if directive.eq_ignore_ascii_case(&'a') { printer::Mode::Display } else { printer::Mode::Write };
+                let text = printer::to_string(arg, mode);
+                out.push_str(&text);
+                pad(&mut out, &text, width);
+                continue;
+            },
+            'd' | 'x' | 'o' | 'b' => {
+                let arg = args.next().ok_or("too few arguments for format string")?;
+                let n = match arg {
+                    Expr::Literal(Literal::Number(n)) => *n,
+                    Expr::Literal(Literal::Float(f)) if directive == 'd' => {
+                        let text = f.to_string();
+                        pad(&mut out, &text, width);
+                        out.push_str(&text);
+                        continue;
+                    },
+                    _ => return Err("format directive expects an integer"),
+                };
+                let digits = match directive.to_ascii_lowercase() {
+                    'x' => format!("{:x}", n.unsigned_abs()),
+                    'o' => format!("{:o}", n.unsigned_abs()),
+                    'b' => format!("{:b}", n.unsigned_abs()),
+                    _ => n.unsigned_abs().to_string(),
+                };
+                if n < 0 { format!("-{}", digits) } else { digits }
+            },
+            'f' => {
+                let arg = args.next().ok_or("too few arguments for format string")?;
+                let f = match arg {
+                    Expr::Literal(Literal::Number(n)) => *n as f64,
+                    Expr::Literal(Literal::Float(f)) => *f,
+                    _ => return Err("format directive expects a number"),
+                };
+                match precision {
+                    Some(p) => format!("{:.*}", p, f),
+                    None => arg.to_string(),
+                }
+            },
+            _ => return Err("unknown format directive"),
+        };
+        pad(&mut out, &text, width);
+        out.push_str(&text);
+    }
+
+    if args.next().is_some() { return Err("too many arguments for format string") }
+
+    match port {
+        Some(port) => {
+            port.write_str(&out)?;
+            Ok(Expr::Unspecified)
+        },
+        None => Ok(Expr::Literal(Literal::String(out))),
+    }
+}
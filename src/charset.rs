@@ -0,0 +1,226 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// An immutable set of characters, after SRFI-14.
+///
+/// Sets are sorted, disjoint, non-adjacent ranges of code points, so
+/// membership is a binary search and the set operations are merges. The
+/// surrogate code points are never members.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CharSet {
+    ranges: Vec<(u32, u32)>,
+}
+
+const MAX: u32 = 0x10ffff;
+
+/// Every assigned letter, digit and space lies below this, so the
+/// predefined sets only need to scan this far.
+const SCAN_LIMIT: u32 = 0x40000;
+
+impl CharSet {
+    pub fn empty() -> CharSet {
+        CharSet::default()
+    }
+
+    pub fn full() -> CharSet {
+        CharSet::from_ranges(vec![(0, MAX)])
+    }
+
+    pub fn range(from: char, to: char) -> CharSet {
+        CharSet::from_ranges(vec![(from as u32, to as u32)])
+    }
+
+    pub fn from_chars(chars: impl IntoIterator<Item = char>) -> CharSet {
+        CharSet::from_ranges(chars.into_iter().map(|c| (c as u32, c as u32)).collect())
+    }
+
+    /// The characters below `SCAN_LIMIT` satisfying `pred`.
+    pub fn from_predicate(pred: impl Fn(char) -> bool) -> CharSet {
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for c in (0..SCAN_LIMIT).filter_map(char::from_u32).filter(|c| pred(*c)) {
+            let c = c as u32;
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == c => *end = c,
+                _ => ranges.push((c, c)),
+            }
+        }
+        CharSet { ranges }
+    }
+
+    /// Normalizes arbitrary ranges: sorts them, merges overlapping and
+    /// adjacent ones, and drops the surrogates.
+    fn from_ranges(mut ranges: Vec<(u32, u32)>) -> CharSet {
+        ranges.retain(|(from, to)| from <= to);
+        ranges.sort();
+
+        let mut merged: Vec<(u32, u32)> = Vec::new();
+        for (from, to) in ranges {
+            match merged.last_mut() {
+                Some((_, end)) if from <= end.saturating_add(1) => *end = (*end).max(to),
+                _ => merged.push((from, to)),
+            }
+        }
+
+        CharSet { ranges: strip_surrogates(merged) }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        let c = c as u32;
+        self.ranges.binary_search_by(|(from, to)| {
+            if *to < c {
+                std::cmp::Ordering::Less
+            } else if *from > c {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        }).is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.iter().map(|(from, to)| (to - from + 1) as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.ranges.iter().flat_map(|(from, to)| (*from..=*to).filter_map(char::from_u32))
+    }
+
+    pub fn union(&self, other: &CharSet) -> CharSet {
+        let mut ranges = self.ranges.clone();
+        ranges.extend_from_slice(&other.ranges);
+        CharSet::from_ranges(ranges)
+    }
+
+    pub fn complement(&self) -> CharSet {
+        let mut ranges = Vec::new();
+        let mut next = 0;
+        for (from, to) in self.ranges.iter() {
+            if *from > next {
+                ranges.push((next, from - 1));
+            }
+            next = to + 1;
+        }
+        if next <= MAX {
+            ranges.push((next, MAX));
+        }
+        CharSet { ranges: strip_surrogates(ranges) }
+    }
+
+    pub fn intersection(&self, other: &CharSet) -> CharSet {
+        self.complement().union(&other.complement()).complement()
+    }
+
+    pub fn difference(&self, other: &CharSet) -> CharSet {
+        let mut ranges = Vec::new();
+        let mut others = other.ranges.iter().peekable();
+        for (from, to) in self.ranges.iter() {
+            let mut from = *from;
+            while let Some((ofrom, oto)) = others.peek() {
+                if *oto < from {
+                    others.next();
+                    continue;
+                }
+                if *ofrom > *to { break }
+                if *ofrom > from {
+                    ranges.push((from, ofrom - 1));
+                }
+                if *oto >= *to {
+                    from = to + 1;
+                    break;
+                }
+                from = oto + 1;
+                others.next();
+            }
+            if from <= *to {
+                ranges.push((from, *to));
+            }
+        }
+        CharSet { ranges }
+    }
+
+    /// The same set with both cases of every cased letter.
+    pub fn fold_case(&self) -> CharSet {
+        let mut chars = Vec::new();
+        for c in self.chars().filter(|c| c.is_alphabetic()) {
+            chars.extend(c.to_lowercase());
+            chars.extend(c.to_uppercase());
+        }
+        self.union(&CharSet::from_chars(chars))
+    }
+}
+
+fn strip_surrogates(ranges: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    let mut stripped = Vec::new();
+    for (from, to) in ranges {
+        if to < 0xd800 || from > 0xdfff {
+            stripped.push((from, to));
+        } else {
+            if from < 0xd800 { stripped.push((from, 0xd7ff)) }
+            if to > 0xdfff { stripped.push((0xe000, to)) }
+        }
+    }
+    stripped
+}
+
+/// Latin-1 punctuation and symbols. Rust has no general category lookup,
+/// so beyond ASCII these sets follow ISO-8859-1.
+const PUNCTUATION: &str = "!\"#%&'()*,-./:;?@[\\]_{}\u{a1}\u{a7}\u{ab}\u{b6}\u{b7}\u{bb}\u{bf}";
+const SYMBOLS: &str = "$+<=>^`|~\u{a2}\u{a3}\u{a4}\u{a5}\u{a6}\u{a8}\u{a9}\u{ac}\u{ae}\u{af}\u{b0}\u{b1}\u{b4}\u{b8}\u{d7}\u{f7}";
+
+/// The names of the SRFI-14 standard sets, as bound in the global
+/// environment without the `char-set:` prefix.
+pub const STANDARD_SETS: &[&str] = &[
+    "lower-case", "upper-case", "title-case", "letter", "digit", "letter+digit",
+    "graphic", "printing", "whitespace", "iso-control", "punctuation", "symbol",
+    "hex-digit", "blank", "ascii", "empty", "full",
+];
+
+fn compute_standard(name: &str) -> CharSet {
+    match name {
+        "lower-case" => CharSet::from_predicate(|c| c.is_lowercase()),
+        "upper-case" => CharSet::from_predicate(|c| c.is_uppercase()),
+        "title-case" => CharSet::from_chars("\u{1c5}\u{1c8}\u{1cb}\u{1f2}\u{1fbc}\u{1fcc}\u{1ffc}".chars())
+            .union(&CharSet::range('\u{1f88}', '\u{1f8f}'))
+            .union(&CharSet::range('\u{1f98}', '\u{1f9f}'))
+            .union(&CharSet::range('\u{1fa8}', '\u{1faf}')),
+        "letter" => CharSet::from_predicate(|c| c.is_alphabetic()),
+        // Rust only exposes the broader Unicode numeric property.
+        "digit" => CharSet::from_predicate(|c| c.is_numeric()),
+        "letter+digit" => standard("letter").union(&standard("digit")),
+        "graphic" => standard("letter+digit").union(&standard("punctuation")).union(&standard("symbol")),
+        "printing" => standard("graphic").union(&standard("whitespace")),
+        "whitespace" => CharSet::from_predicate(|c| c.is_whitespace()),
+        "iso-control" => CharSet::from_predicate(|c| c.is_control()),
+        "punctuation" => CharSet::from_chars(PUNCTUATION.chars()),
+        "symbol" => CharSet::from_chars(SYMBOLS.chars()),
+        "hex-digit" => CharSet::from_chars("0123456789abcdefABCDEF".chars()),
+        "blank" => CharSet::from_chars(" \t\u{a0}\u{1680}\u{202f}\u{205f}\u{3000}".chars())
+            .union(&CharSet::range('\u{2000}', '\u{200a}')),
+        "ascii" => CharSet::range('\0', '\x7f'),
+        "full" => CharSet::full(),
+        _ => CharSet::empty(),
+    }
+}
+
+thread_local! {
+    static STANDARD: RefCell<Vec<(&'static str, Rc<CharSet>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// One of the SRFI-14 standard sets by name, e.g. `digit`. The Unicode sets
+/// are built by scanning on first use and cached.
+pub fn standard(name: &str) -> Rc<CharSet> {
+    let cached = STANDARD.with(|s| s.borrow().iter().find(|(n, _)| *n == name).map(|(_, set)| set.clone()));
+    if let Some(set) = cached {
+        return set;
+    }
+
+    let set = Rc::new(compute_standard(name));
+    if let Some(name) = STANDARD_SETS.iter().find(|n| **n == name) {
+        STANDARD.with(|s| s.borrow_mut().push((name, set.clone())));
+    }
+    set
+}
@@ -0,0 +1,381 @@
+use crate::analyze::body_defines;
+use crate::ast::Expr;
+use crate::parser::MAX_DEPTH;
+use crate::record;
+use crate::symbol;
+use crate::symbol::Symbol;
+
+use std::rc::Rc;
+
+/// A bytecode instruction. Local variables are addressed by how many
+/// frames out they live and their slot in that frame; anything not bound
+/// by an enclosing `lambda` or `let` is a global looked up by name.
+#[derive(Clone, Copy)]
+pub enum Op {
+    Const(usize),
+    LocalRef(usize, usize),
+    LocalSet(usize, usize),
+    LocalDefine(usize),
+    GlobalRef(Symbol),
+    GlobalSet(Symbol),
+    GlobalDefine(Symbol),
+    Closure(usize),
+    Jump(usize),
+    JumpIfFalse(usize),
+    /// `cond` only takes a clause when its test is `#t` itself.
+    JumpUnlessTrue(usize),
+    Pop,
+    /// Starts a body expression other than the last. An error before the
+    /// matching `Uncatch` is dropped and execution resumes at the target
+    /// with an unspecified value.
+    Catch(usize),
+    Uncatch,
+    Call(usize),
+    TailCall(usize),
+    Return,
+    /// Hands a top-level form to the tree-walker, for the library and
+    /// record forms the compiler does not translate.
+    Eval(usize),
+    /// Makes the record type for a `define-record-type` in a body, pushing
+    /// the values of the names it defines.
+    Record(usize),
+}
+
+/// The compiled body of a procedure, or of a top-level form when `arity`
+/// is zero and there is no frame.
+#[derive(Default)]
+pub struct Code {
+    pub arity: usize,
+    pub frame_size: usize,
+    pub ops: Vec<Op>,
+    pub consts: Vec<Expr>,
+    pub codes: Vec<Rc<Code>>,
+}
+
+#[derive(Default)]
+struct Compiler {
+    scopes: Vec<Vec<Symbol>>,
+    code: Code,
+    depth: usize,
+}
+
+/// Compiles a top-level form.
+pub fn compile(expr: &Expr) -> Result<Rc<Code>, &'static str> {
+    let mut compiler = Compiler::default();
+    compiler.expr(expr, true)?;
+    compiler.emit(Op::Return);
+    Ok(Rc::new(compiler.code))
+}
+
+impl Compiler {
+    fn emit(&mut self, op: Op) -> usize {
+        self.code.ops.push(op);
+        self.code.ops.len() - 1
+    }
+
+    fn constant(&mut self, expr: Expr) {
+        self.code.consts.push(expr);
+        let i = self.code.consts.len() - 1;
+        self.emit(Op::Const(i));
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.code.ops.len();
+        match &mut self.code.ops[at] {
+            Op::Jump(t) | Op::JumpIfFalse(t) | Op::JumpUnlessTrue(t) | Op::Catch(t) => *t = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn resolve(&self, name: Symbol) -> Option<(usize, usize)> {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(slot) = scope.iter().rposition(|s| *s == name) {
+                return Some((depth, slot));
+            }
+        }
+        None
+    }
+
+    fn expr(&mut self, expr: &Expr, tail: bool) -> Result<(), &'static str> {
+        if self.depth == MAX_DEPTH { return Err("nested too deeply") }
+        self.depth += 1;
+        let compiled = self.form(expr, tail);
+        self.depth -= 1;
+        compiled
+    }
+
+    fn form(&mut self, expr: &Expr, tail: bool) -> Result<(), &'static str> {
+        match expr {
+            Expr::List(list) => {
+                if list.is_empty() {
+                    self.constant(expr.clone());
+                    return Ok(())
+                }
+
+                match &list[0] {
+                    Expr::Var(atom) => match *atom {
+                        symbol::LAMBDA => self.lambda(list),
+                        symbol::DEFINE => self.define(list),
+                        symbol::IF     => self.ifexpr(list, tail),
+                        symbol::QUOTE  => {
+                            if list.len() != 2 { return Err("invalid quote syntax") }
+                            self.constant(list[1].to_datum());
+                            Ok(())
+                        },
+                        symbol::SET    => self.set(list),
+                        symbol::BEGIN  => self.body(&list[1..list.len()], tail),
+                        symbol::LET    => self.let_(list, tail),
+                        symbol::COND   => self.cond(list, tail),
+                        symbol::DEFINE_RECORD_TYPE if !self.scopes.is_empty() => self.record(expr, list),
+                        symbol::DEFINE_RECORD_TYPE |
+                        symbol::DEFINE_LIBRARY |
+                        symbol::IMPORT |
+                        symbol::INCLUDE |
+                        symbol::INCLUDE_CI |
+                        symbol::COND_EXPAND => {
+                            if !self.scopes.is_empty() {
+                                return Err("form is only supported at top level when compiled")
+                            }
+                            self.code.consts.push(expr.clone());
+                            let i = self.code.consts.len() - 1;
+                            self.emit(Op::Eval(i));
+                            Ok(())
+                        },
+                        _ => self.application(list, tail),
+                    },
+                    Expr::List(_) => self.application(list, tail),
+                    Expr::Literal(_) => Err("not applicable"),
+                    _ => Err("not implemented"),
+                }
+            },
+
+            Expr::Var(atom) => {
+                match self.resolve(*atom) {
+                    Some((depth, slot)) => self.emit(Op::LocalRef(depth, slot)),
+                    None => self.emit(Op::GlobalRef(*atom)),
+                };
+                Ok(())
+            },
+
+            Expr::Literal(_) |
+            Expr::Symbol(_) |
+            Expr::Bytevector(_) |
+            Expr::Vector(_) |
+            Expr::HashTable(_) |
+            Expr::Mapping(_) |
+            Expr::CharSet(_) |
+            Expr::Regexp(_) |
+            Expr::RegexpMatch(_) |
+            Expr::RecordType(_) |
+            Expr::Record(_) |
+            Expr::RecordProc(_) |
+            Expr::Port(_) |
+            Expr::Environment(_) |
+            Expr::Eof => {
+                self.constant(expr.clone());
+                Ok(())
+            },
+            _ => Err("not implemented"),
+        }
+    }
+
+    /// A sequence whose value is that of its last expression.
+    fn body(&mut self, exprs: &[Expr], tail: bool) -> Result<(), &'static str> {
+        if exprs.is_empty() {
+            self.constant(Expr::Unspecified);
+            return Ok(())
+        }
+
+        for (i, expr) in exprs.iter().enumerate() {
+            if i + 1 < exprs.len() {
+                let catch = self.emit(Op::Catch(0));
+                self.expr(expr, false)?;
+                self.emit(Op::Uncatch);
+                self.patch(catch);
+                self.emit(Op::Pop);
+            } else {
+                self.expr(expr, tail)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn closure(&mut self, params: &[Expr], body: &[Expr]) -> Result<(), &'static str> {
+        let mut scope = Vec::new();
+        for param in params {
+            scope.push(param.from_var().ok_or("lambda parameters must be identifiers")?);
+        }
+        let arity = scope.len();
+        // Forms that would bind names as they run are rejected below.
+        for expr in body {
+            body_defines(expr, &mut scope);
+        }
+
+        let mut scopes = self.scopes.clone();
+        scopes.push(scope);
+        let mut compiler = Compiler { scopes, code: Code::default(), depth: self.depth };
+        compiler.body(body, true)?;
+        compiler.emit(Op::Return);
+        compiler.code.arity = arity;
+        compiler.code.frame_size = compiler.scopes.last().unwrap().len();
+
+        self.code.codes.push(Rc::new(compiler.code));
+        let i = self.code.codes.len() - 1;
+        self.emit(Op::Closure(i));
+        Ok(())
+    }
+
+    fn lambda(&mut self, list: &[Expr]) -> Result<(), &'static str> {
+        match list.get(1) {
+            Some(Expr::List(params)) => self.closure(params, &list[2..list.len()]),
+            _ => Err("not implemented"),
+        }
+    }
+
+    fn define(&mut self, list: &[Expr]) -> Result<(), &'static str> {
+        if list.len() < 3 { return Err("invalid define statement"); }
+
+        let name = match &list[1] {
+            Expr::List(vars) => {
+                if vars.is_empty() { return Err("define vars cannot be empty") }
+
+                let name = vars[0].from_var().ok_or("define name must be an identifier")?;
+                self.closure(&vars[1..vars.len()], &list[2..list.len()])?;
+                name
+            },
+            Expr::Var(atom) => {
+                self.expr(&list[2], false)?;
+                *atom
+            },
+            _ => return Err("invalid define statement"),
+        };
+
+        match self.scopes.last() {
+            Some(scope) => {
+                let slot = scope.iter().rposition(|s| *s == name).ok_or("invalid define statement")?;
+                self.emit(Op::LocalDefine(slot));
+            },
+            None => { self.emit(Op::GlobalDefine(name)); },
+        }
+        Ok(())
+    }
+
+    /// A record type defined in a body is made each time the body runs,
+    /// and its procedures go in the frame like other internal definitions.
+    fn record(&mut self, expr: &Expr, list: &[Expr]) -> Result<(), &'static str> {
+        let bindings = record::define(list)?;
+        self.code.consts.push(expr.clone());
+        let i = self.code.consts.len() - 1;
+        self.emit(Op::Record(i));
+
+        let scope = self.scopes.last().unwrap();
+        let slots: Vec<usize> = bindings.iter().rev()
+            .map(|(name, _)| scope.iter().rposition(|s| s == name).unwrap())
+            .collect();
+        for slot in slots {
+            self.emit(Op::LocalDefine(slot));
+            self.emit(Op::Pop);
+        }
+        self.constant(Expr::Unspecified);
+        Ok(())
+    }
+
+    fn ifexpr(&mut self, list: &[Expr], tail: bool) -> Result<(), &'static str> {
+        if list.len() != 3 && list.len() != 4 { return Err("invalid if syntax") }
+
+        self.expr(&list[1], false)?;
+        let alternate = self.emit(Op::JumpIfFalse(0));
+        self.expr(&list[2], tail)?;
+        let end = self.emit(Op::Jump(0));
+        self.patch(alternate);
+        match list.get(3) {
+            Some(expr) => self.expr(expr, tail)?,
+            None => self.constant(Expr::Unspecified),
+        }
+        self.patch(end);
+        Ok(())
+    }
+
+    fn set(&mut self, list: &[Expr]) -> Result<(), &'static str> {
+        if list.len() != 3 { return Err("invalid set syntax") }
+
+        let name = list[1].from_var().ok_or("first parameter must be an atom")?;
+        self.expr(&list[2], false)?;
+        match self.resolve(name) {
+            Some((depth, slot)) => self.emit(Op::LocalSet(depth, slot)),
+            None => self.emit(Op::GlobalSet(name)),
+        };
+        Ok(())
+    }
+
+    /// `let` is compiled as the application of a `lambda`.
+    fn let_(&mut self, list: &[Expr], tail: bool) -> Result<(), &'static str> {
+        let decs = match list.get(1) {
+            Some(Expr::List(decs)) => decs,
+            _ => return Err("expecting list of declarations"),
+        };
+
+        let mut vars = Vec::new();
+        let mut vals = Vec::new();
+        for dec in decs {
+            let dec = match dec {
+                Expr::List(dec) if dec.len() == 2 => dec,
+                _ => return Err("expecting a pair"),
+            };
+            if !dec[0].is_var() { return Err("expecting an atom in let declaration pair") }
+            vars.push(dec[0].clone());
+            vals.push(&dec[1]);
+        }
+
+        self.closure(&vars, &list[2..list.len()])?;
+        for val in vals {
+            self.expr(val, false)?;
+        }
+        self.emit(if tail { Op::TailCall(vars.len()) } else { Op::Call(vars.len()) });
+        Ok(())
+    }
+
+    fn cond(&mut self, list: &[Expr], tail: bool) -> Result<(), &'static str> {
+        let mut ends = Vec::new();
+        let mut has_else = false;
+        for clause in &list[1..list.len()] {
+            let clause = match clause {
+                Expr::List(clause) => clause,
+                _ => return Err("expecting a list in cond"),
+            };
+            if clause.len() != 2 { return Err("invalid format in cond") }
+
+            if let Expr::Var(pred) = &clause[0] {
+                if *pred != symbol::ELSE { return Err("expecting else in cond") }
+                self.expr(&clause[1], tail)?;
+                has_else = true;
+                break;
+            }
+
+            self.expr(&clause[0], false)?;
+            let next = self.emit(Op::JumpUnlessTrue(0));
+            self.expr(&clause[1], tail)?;
+            ends.push(self.emit(Op::Jump(0)));
+            self.patch(next);
+        }
+
+        if !has_else {
+            self.constant(Expr::Unspecified);
+        }
+        for end in ends {
+            self.patch(end);
+        }
+        Ok(())
+    }
+
+    fn application(&mut self, list: &[Expr], tail: bool) -> Result<(), &'static str> {
+        for expr in list {
+            self.expr(expr, false)?;
+        }
+
+        let argc = list.len() - 1;
+        self.emit(if tail { Op::TailCall(argc) } else { Op::Call(argc) });
+        Ok(())
+    }
+}
@@ -2,82 +2,580 @@ use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::RefCell;
 use crate::builtins;
+use crate::charset;
 use crate::ast::Expr;
+use crate::gc;
+use crate::gc::{Trace, Tracer};
+use crate::symbol::Symbol;
 
 #[derive(Clone, Debug, Default)]
 pub struct Env {
     node: Option<Rc<RefCell<Env>>>,
-    env: HashMap<String, Expr>
+    env: HashMap<Symbol, Expr>,
+    // The variables the analyzer found for a procedure body or `let`, which
+    // are kept in slots rather than the map. A slot is empty until its
+    // variable is bound.
+    names: Rc<[Symbol]>,
+    slots: Vec<Option<Expr>>,
+    // Names imported from a library, each referring to a variable in the
+    // library's environment, so changes the library makes are seen.
+    imports: HashMap<Symbol, (Rc<RefCell<Env>>, Symbol)>,
 }
 
 impl Env {
     pub fn new() -> Rc<RefCell<Env>> {
         let mut global_env = HashMap::new();
-        global_env.insert(String::from("="), Expr::Builtin(builtins::equal));
-        global_env.insert(String::from("<"), Expr::Builtin(builtins::lt));
-        global_env.insert(String::from("<="), Expr::Builtin(builtins::lte));
-        global_env.insert(String::from(">"), Expr::Builtin(builtins::gt));
-        global_env.insert(String::from(">="), Expr::Builtin(builtins::gte));
-        global_env.insert(String::from("+"), Expr::Builtin(builtins::add));
-        global_env.insert(String::from("-"), Expr::Builtin(builtins::sub));
-        global_env.insert(String::from("*"), Expr::Builtin(builtins::mul));
-        global_env.insert(String::from("list"), Expr::Builtin(builtins::list));
-        global_env.insert(String::from("car"), Expr::Builtin(builtins::car));
-        global_env.insert(String::from("cdr"), Expr::Builtin(builtins::cdr));
-        global_env.insert(String::from("cons"), Expr::Builtin(builtins::cons));
-        global_env.insert(String::from("append"), Expr::Builtin(builtins::append));
-        global_env.insert(String::from("length"), Expr::Builtin(builtins::length));
-        global_env.insert(String::from("reverse"), Expr::Builtin(builtins::reverse));
-        global_env.insert(String::from("equal?"), Expr::Builtin(builtins::equalp));
-        global_env.insert(String::from("list?"), Expr::Builtin(builtins::listp));
-        global_env.insert(String::from("null?"), Expr::Builtin(builtins::nullp));
-        global_env.insert(String::from("number?"), Expr::Builtin(builtins::numberp));
-        global_env.insert(String::from("procedure?"), Expr::Builtin(builtins::procedurep));
-        global_env.insert(String::from("symbol?"), Expr::Builtin(builtins::symbolp));
-        global_env.insert(String::from("pair?"), Expr::Builtin(builtins::pairp));
-        global_env.insert(String::from("apply"), Expr::Builtin(builtins::apply));
-        global_env.insert(String::from("load"), Expr::Builtin(builtins::load));
-        global_env.insert(String::from("read"), Expr::Builtin(builtins::read));
-        global_env.insert(String::from("display"), Expr::Builtin(builtins::display));
-        Rc::new(RefCell::new(Env { node: None, env: global_env }))
-    }
-
-    pub fn get(&self, key: &String) -> Option<Expr> {
-        let result = self.env.get(key);
-        match result {
-            Some(result) => Some(result.clone()),
-            None => {
-                match &self.node {
-                    Some(env) => env.borrow().get(key),
-                    None => None
+        global_env.insert(Symbol::intern("="), Expr::Builtin(builtins::equal));
+        global_env.insert(Symbol::intern("<"), Expr::Builtin(builtins::lt));
+        global_env.insert(Symbol::intern("<="), Expr::Builtin(builtins::lte));
+        global_env.insert(Symbol::intern(">"), Expr::Builtin(builtins::gt));
+        global_env.insert(Symbol::intern(">="), Expr::Builtin(builtins::gte));
+        global_env.insert(Symbol::intern("+"), Expr::Builtin(builtins::add));
+        global_env.insert(Symbol::intern("-"), Expr::Builtin(builtins::sub));
+        global_env.insert(Symbol::intern("number->string"), Expr::Builtin(builtins::number_to_string));
+        global_env.insert(Symbol::intern("string->number"), Expr::Builtin(builtins::string_to_number));
+        global_env.insert(Symbol::intern("/"), Expr::Builtin(builtins::div));
+        global_env.insert(Symbol::intern("quotient"), Expr::Builtin(builtins::quotient));
+        global_env.insert(Symbol::intern("remainder"), Expr::Builtin(builtins::remainder));
+        global_env.insert(Symbol::intern("modulo"), Expr::Builtin(builtins::modulo));
+        global_env.insert(Symbol::intern("abs"), Expr::Builtin(builtins::abs));
+        global_env.insert(Symbol::intern("min"), Expr::Builtin(builtins::min));
+        global_env.insert(Symbol::intern("max"), Expr::Builtin(builtins::max));
+        global_env.insert(Symbol::intern("zero?"), Expr::Builtin(builtins::zerop));
+        global_env.insert(Symbol::intern("positive?"), Expr::Builtin(builtins::positivep));
+        global_env.insert(Symbol::intern("negative?"), Expr::Builtin(builtins::negativep));
+        global_env.insert(Symbol::intern("odd?"), Expr::Builtin(builtins::oddp));
+        global_env.insert(Symbol::intern("even?"), Expr::Builtin(builtins::evenp));
+        global_env.insert(Symbol::intern("integer?"), Expr::Builtin(builtins::integerp));
+        global_env.insert(Symbol::intern("exact?"), Expr::Builtin(builtins::exactp));
+        global_env.insert(Symbol::intern("inexact?"), Expr::Builtin(builtins::inexactp));
+        global_env.insert(Symbol::intern("exact-integer?"), Expr::Builtin(builtins::exact_integerp));
+        global_env.insert(Symbol::intern("exact"), Expr::Builtin(builtins::exact));
+        global_env.insert(Symbol::intern("inexact"), Expr::Builtin(builtins::inexact));
+        global_env.insert(Symbol::intern("floor"), Expr::Builtin(builtins::floor));
+        global_env.insert(Symbol::intern("ceiling"), Expr::Builtin(builtins::ceiling));
+        global_env.insert(Symbol::intern("round"), Expr::Builtin(builtins::round));
+        global_env.insert(Symbol::intern("truncate"), Expr::Builtin(builtins::truncate));
+        global_env.insert(Symbol::intern("square"), Expr::Builtin(builtins::square));
+        global_env.insert(Symbol::intern("exp"), Expr::Builtin(builtins::exp));
+        global_env.insert(Symbol::intern("log"), Expr::Builtin(builtins::log));
+        global_env.insert(Symbol::intern("sin"), Expr::Builtin(builtins::sin));
+        global_env.insert(Symbol::intern("cos"), Expr::Builtin(builtins::cos));
+        global_env.insert(Symbol::intern("tan"), Expr::Builtin(builtins::tan));
+        global_env.insert(Symbol::intern("asin"), Expr::Builtin(builtins::asin));
+        global_env.insert(Symbol::intern("acos"), Expr::Builtin(builtins::acos));
+        global_env.insert(Symbol::intern("atan"), Expr::Builtin(builtins::atan));
+        global_env.insert(Symbol::intern("sqrt"), Expr::Builtin(builtins::sqrt));
+        global_env.insert(Symbol::intern("exact-integer-sqrt"), Expr::Builtin(builtins::exact_integer_sqrt));
+        global_env.insert(Symbol::intern("finite?"), Expr::Builtin(builtins::finitep));
+        global_env.insert(Symbol::intern("infinite?"), Expr::Builtin(builtins::infinitep));
+        global_env.insert(Symbol::intern("nan?"), Expr::Builtin(builtins::nanp));
+        global_env.insert(Symbol::intern("*"), Expr::Builtin(builtins::mul));
+        global_env.insert(Symbol::intern("list"), Expr::Builtin(builtins::list));
+        global_env.insert(Symbol::intern("car"), Expr::Builtin(builtins::car));
+        global_env.insert(Symbol::intern("cdr"), Expr::Builtin(builtins::cdr));
+        global_env.insert(Symbol::intern("cons"), Expr::Builtin(builtins::cons));
+        global_env.insert(Symbol::intern("append"), Expr::Builtin(builtins::append));
+        global_env.insert(Symbol::intern("length"), Expr::Builtin(builtins::length));
+        global_env.insert(Symbol::intern("reverse"), Expr::Builtin(builtins::reverse));
+        global_env.insert(Symbol::intern("map"), Expr::Builtin(builtins::map));
+        global_env.insert(Symbol::intern("for-each"), Expr::Builtin(builtins::for_each));
+        global_env.insert(Symbol::intern("append-map"), Expr::Builtin(builtins::append_map));
+        global_env.insert(Symbol::intern("filter-map"), Expr::Builtin(builtins::filter_map));
+        global_env.insert(Symbol::intern("filter"), Expr::Builtin(builtins::filter));
+        global_env.insert(Symbol::intern("remove"), Expr::Builtin(builtins::remove));
+        global_env.insert(Symbol::intern("partition"), Expr::Builtin(builtins::partition));
+        global_env.insert(Symbol::intern("fold"), Expr::Builtin(builtins::fold));
+        global_env.insert(Symbol::intern("fold-right"), Expr::Builtin(builtins::fold_right));
+        global_env.insert(Symbol::intern("reduce"), Expr::Builtin(builtins::reduce));
+        global_env.insert(Symbol::intern("find"), Expr::Builtin(builtins::find));
+        global_env.insert(Symbol::intern("find-tail"), Expr::Builtin(builtins::find_tail));
+        global_env.insert(Symbol::intern("any"), Expr::Builtin(builtins::any));
+        global_env.insert(Symbol::intern("every"), Expr::Builtin(builtins::every));
+        global_env.insert(Symbol::intern("count"), Expr::Builtin(builtins::count));
+        global_env.insert(Symbol::intern("list-index"), Expr::Builtin(builtins::list_index));
+        global_env.insert(Symbol::intern("delete"), Expr::Builtin(builtins::delete));
+        global_env.insert(Symbol::intern("delete-duplicates"), Expr::Builtin(builtins::delete_duplicates));
+        global_env.insert(Symbol::intern("iota"), Expr::Builtin(builtins::iota));
+        global_env.insert(Symbol::intern("last"), Expr::Builtin(builtins::last));
+        global_env.insert(Symbol::intern("take"), Expr::Builtin(builtins::take));
+        global_env.insert(Symbol::intern("drop"), Expr::Builtin(builtins::drop));
+        global_env.insert(Symbol::intern("split-at"), Expr::Builtin(builtins::split_at));
+        global_env.insert(Symbol::intern("list-tail"), Expr::Builtin(builtins::list_tail));
+        global_env.insert(Symbol::intern("list-ref"), Expr::Builtin(builtins::list_ref));
+        global_env.insert(Symbol::intern("member"), Expr::Builtin(builtins::member));
+        global_env.insert(Symbol::intern("memq"), Expr::Builtin(builtins::memv));
+        global_env.insert(Symbol::intern("memv"), Expr::Builtin(builtins::memv));
+        global_env.insert(Symbol::intern("assoc"), Expr::Builtin(builtins::assoc));
+        global_env.insert(Symbol::intern("assq"), Expr::Builtin(builtins::assv));
+        global_env.insert(Symbol::intern("assv"), Expr::Builtin(builtins::assv));
+        global_env.insert(Symbol::intern("alist-cons"), Expr::Builtin(builtins::alist_cons));
+        global_env.insert(Symbol::intern("alist-copy"), Expr::Builtin(builtins::alist_copy));
+        global_env.insert(Symbol::intern("alist-delete"), Expr::Builtin(builtins::alist_delete));
+        global_env.insert(Symbol::intern("list-copy"), Expr::Builtin(builtins::list_copy));
+        global_env.insert(Symbol::intern("make-list"), Expr::Builtin(builtins::make_list));
+        global_env.insert(Symbol::intern("caar"), Expr::Builtin(builtins::caar));
+        global_env.insert(Symbol::intern("cadr"), Expr::Builtin(builtins::cadr));
+        global_env.insert(Symbol::intern("cdar"), Expr::Builtin(builtins::cdar));
+        global_env.insert(Symbol::intern("cddr"), Expr::Builtin(builtins::cddr));
+        global_env.insert(Symbol::intern("caaar"), Expr::Builtin(builtins::caaar));
+        global_env.insert(Symbol::intern("caadr"), Expr::Builtin(builtins::caadr));
+        global_env.insert(Symbol::intern("cadar"), Expr::Builtin(builtins::cadar));
+        global_env.insert(Symbol::intern("caddr"), Expr::Builtin(builtins::caddr));
+        global_env.insert(Symbol::intern("cdaar"), Expr::Builtin(builtins::cdaar));
+        global_env.insert(Symbol::intern("cdadr"), Expr::Builtin(builtins::cdadr));
+        global_env.insert(Symbol::intern("cddar"), Expr::Builtin(builtins::cddar));
+        global_env.insert(Symbol::intern("cdddr"), Expr::Builtin(builtins::cdddr));
+        global_env.insert(Symbol::intern("caaaar"), Expr::Builtin(builtins::caaaar));
+        global_env.insert(Symbol::intern("caaadr"), Expr::Builtin(builtins::caaadr));
+        global_env.insert(Symbol::intern("caadar"), Expr::Builtin(builtins::caadar));
+        global_env.insert(Symbol::intern("caaddr"), Expr::Builtin(builtins::caaddr));
+        global_env.insert(Symbol::intern("cadaar"), Expr::Builtin(builtins::cadaar));
+        global_env.insert(Symbol::intern("cadadr"), Expr::Builtin(builtins::cadadr));
+        global_env.insert(Symbol::intern("caddar"), Expr::Builtin(builtins::caddar));
+        global_env.insert(Symbol::intern("cadddr"), Expr::Builtin(builtins::cadddr));
+        global_env.insert(Symbol::intern("cdaaar"), Expr::Builtin(builtins::cdaaar));
+        global_env.insert(Symbol::intern("cdaadr"), Expr::Builtin(builtins::cdaadr));
+        global_env.insert(Symbol::intern("cdadar"), Expr::Builtin(builtins::cdadar));
+        global_env.insert(Symbol::intern("cdaddr"), Expr::Builtin(builtins::cdaddr));
+        global_env.insert(Symbol::intern("cddaar"), Expr::Builtin(builtins::cddaar));
+        global_env.insert(Symbol::intern("cddadr"), Expr::Builtin(builtins::cddadr));
+        global_env.insert(Symbol::intern("cdddar"), Expr::Builtin(builtins::cdddar));
+        global_env.insert(Symbol::intern("cddddr"), Expr::Builtin(builtins::cddddr));
+        global_env.insert(Symbol::intern("equal?"), Expr::Builtin(builtins::equalp));
+        global_env.insert(Symbol::intern("eq?"), Expr::Builtin(builtins::eqp));
+        global_env.insert(Symbol::intern("eqv?"), Expr::Builtin(builtins::eqp));
+        global_env.insert(Symbol::intern("symbol=?"), Expr::Builtin(builtins::symbol_eqp));
+        global_env.insert(Symbol::intern("string->symbol"), Expr::Builtin(builtins::string_to_symbol));
+        global_env.insert(Symbol::intern("symbol->string"), Expr::Builtin(builtins::symbol_to_string));
+        global_env.insert(Symbol::intern("gensym"), Expr::Builtin(builtins::gensym));
+        global_env.insert(Symbol::intern("generate-uninterned-symbol"), Expr::Builtin(builtins::gensym));
+        global_env.insert(Symbol::intern("symbol-interned?"), Expr::Builtin(builtins::symbol_internedp));
+        global_env.insert(Symbol::intern("make-string"), Expr::Builtin(builtins::make_string));
+        global_env.insert(Symbol::intern("string"), Expr::Builtin(builtins::string));
+        global_env.insert(Symbol::intern("string-length"), Expr::Builtin(builtins::string_length));
+        global_env.insert(Symbol::intern("string-ref"), Expr::Builtin(builtins::string_ref));
+        global_env.insert(Symbol::intern("substring"), Expr::Builtin(builtins::substring));
+        global_env.insert(Symbol::intern("string-copy"), Expr::Builtin(builtins::string_copy));
+        global_env.insert(Symbol::intern("string-append"), Expr::Builtin(builtins::string_append));
+        global_env.insert(Symbol::intern("string->list"), Expr::Builtin(builtins::string_to_list));
+        global_env.insert(Symbol::intern("list->string"), Expr::Builtin(builtins::list_to_string));
+        global_env.insert(Symbol::intern("string->vector"), Expr::Builtin(builtins::string_to_vector));
+        global_env.insert(Symbol::intern("vector->string"), Expr::Builtin(builtins::vector_to_string));
+        global_env.insert(Symbol::intern("string=?"), Expr::Builtin(builtins::string_eq));
+        global_env.insert(Symbol::intern("string<?"), Expr::Builtin(builtins::string_lt));
+        global_env.insert(Symbol::intern("string>?"), Expr::Builtin(builtins::string_gt));
+        global_env.insert(Symbol::intern("string<=?"), Expr::Builtin(builtins::string_lte));
+        global_env.insert(Symbol::intern("string>=?"), Expr::Builtin(builtins::string_gte));
+        global_env.insert(Symbol::intern("string-ci=?"), Expr::Builtin(builtins::string_ci_eq));
+        global_env.insert(Symbol::intern("string-ci<?"), Expr::Builtin(builtins::string_ci_lt));
+        global_env.insert(Symbol::intern("string-ci>?"), Expr::Builtin(builtins::string_ci_gt));
+        global_env.insert(Symbol::intern("string-ci<=?"), Expr::Builtin(builtins::string_ci_lte));
+        global_env.insert(Symbol::intern("string-ci>=?"), Expr::Builtin(builtins::string_ci_gte));
+        global_env.insert(Symbol::intern("string-upcase"), Expr::Builtin(builtins::string_upcase));
+        global_env.insert(Symbol::intern("string-downcase"), Expr::Builtin(builtins::string_downcase));
+        global_env.insert(Symbol::intern("string-foldcase"), Expr::Builtin(builtins::string_foldcase));
+        global_env.insert(Symbol::intern("string-map"), Expr::Builtin(builtins::string_map));
+        global_env.insert(Symbol::intern("string-for-each"), Expr::Builtin(builtins::string_for_each));
+        global_env.insert(Symbol::intern("char=?"), Expr::Builtin(builtins::char_eq));
+        global_env.insert(Symbol::intern("char<?"), Expr::Builtin(builtins::char_lt));
+        global_env.insert(Symbol::intern("char>?"), Expr::Builtin(builtins::char_gt));
+        global_env.insert(Symbol::intern("char<=?"), Expr::Builtin(builtins::char_lte));
+        global_env.insert(Symbol::intern("char>=?"), Expr::Builtin(builtins::char_gte));
+        global_env.insert(Symbol::intern("char-ci=?"), Expr::Builtin(builtins::char_ci_eq));
+        global_env.insert(Symbol::intern("char-ci<?"), Expr::Builtin(builtins::char_ci_lt));
+        global_env.insert(Symbol::intern("char-ci>?"), Expr::Builtin(builtins::char_ci_gt));
+        global_env.insert(Symbol::intern("char-ci<=?"), Expr::Builtin(builtins::char_ci_lte));
+        global_env.insert(Symbol::intern("char-ci>=?"), Expr::Builtin(builtins::char_ci_gte));
+        global_env.insert(Symbol::intern("char->integer"), Expr::Builtin(builtins::char_to_integer));
+        global_env.insert(Symbol::intern("integer->char"), Expr::Builtin(builtins::integer_to_char));
+        global_env.insert(Symbol::intern("char-alphabetic?"), Expr::Builtin(builtins::char_alphabeticp));
+        global_env.insert(Symbol::intern("char-numeric?"), Expr::Builtin(builtins::char_numericp));
+        global_env.insert(Symbol::intern("char-whitespace?"), Expr::Builtin(builtins::char_whitespacep));
+        global_env.insert(Symbol::intern("char-upper-case?"), Expr::Builtin(builtins::char_upper_casep));
+        global_env.insert(Symbol::intern("char-lower-case?"), Expr::Builtin(builtins::char_lower_casep));
+        global_env.insert(Symbol::intern("digit-value"), Expr::Builtin(builtins::digit_value));
+        global_env.insert(Symbol::intern("char-upcase"), Expr::Builtin(builtins::char_upcase));
+        global_env.insert(Symbol::intern("char-downcase"), Expr::Builtin(builtins::char_downcase));
+        global_env.insert(Symbol::intern("char-foldcase"), Expr::Builtin(builtins::char_foldcase));
+        global_env.insert(Symbol::intern("list?"), Expr::Builtin(builtins::listp));
+        global_env.insert(Symbol::intern("null?"), Expr::Builtin(builtins::nullp));
+        global_env.insert(Symbol::intern("number?"), Expr::Builtin(builtins::numberp));
+        global_env.insert(Symbol::intern("procedure?"), Expr::Builtin(builtins::procedurep));
+        global_env.insert(Symbol::intern("symbol?"), Expr::Builtin(builtins::symbolp));
+        global_env.insert(Symbol::intern("char?"), Expr::Builtin(builtins::charp));
+        global_env.insert(Symbol::intern("pair?"), Expr::Builtin(builtins::pairp));
+        global_env.insert(Symbol::intern("not"), Expr::Builtin(builtins::not));
+        global_env.insert(Symbol::intern("boolean?"), Expr::Builtin(builtins::booleanp));
+        global_env.insert(Symbol::intern("boolean=?"), Expr::Builtin(builtins::boolean_eq));
+        global_env.insert(Symbol::intern("string?"), Expr::Builtin(builtins::stringp));
+        global_env.insert(Symbol::intern("bytevector?"), Expr::Builtin(builtins::bytevectorp));
+        global_env.insert(Symbol::intern("bytevector"), Expr::Builtin(builtins::bytevector));
+        global_env.insert(Symbol::intern("make-bytevector"), Expr::Builtin(builtins::make_bytevector));
+        global_env.insert(Symbol::intern("bytevector-length"), Expr::Builtin(builtins::bytevector_length));
+        global_env.insert(Symbol::intern("bytevector-u8-ref"), Expr::Builtin(builtins::bytevector_u8_ref));
+        global_env.insert(Symbol::intern("bytevector-u8-set!"), Expr::Builtin(builtins::bytevector_u8_set));
+        global_env.insert(Symbol::intern("bytevector-copy"), Expr::Builtin(builtins::bytevector_copy));
+        global_env.insert(Symbol::intern("bytevector-copy!"), Expr::Builtin(builtins::bytevector_copy_mut));
+        global_env.insert(Symbol::intern("bytevector-append"), Expr::Builtin(builtins::bytevector_append));
+        global_env.insert(Symbol::intern("utf8->string"), Expr::Builtin(builtins::utf8_to_string));
+        global_env.insert(Symbol::intern("string->utf8"), Expr::Builtin(builtins::string_to_utf8));
+        global_env.insert(Symbol::intern("native-endianness"), Expr::Builtin(builtins::native_endianness));
+        global_env.insert(Symbol::intern("bytevector-s8-ref"), Expr::Builtin(builtins::bytevector_s8_ref));
+        global_env.insert(Symbol::intern("bytevector-s8-set!"), Expr::Builtin(builtins::bytevector_s8_set));
+        global_env.insert(Symbol::intern("bytevector-u16-ref"), Expr::Builtin(builtins::bytevector_u16_ref));
+        global_env.insert(Symbol::intern("bytevector-u16-set!"), Expr::Builtin(builtins::bytevector_u16_set));
+        global_env.insert(Symbol::intern("bytevector-s16-ref"), Expr::Builtin(builtins::bytevector_s16_ref));
+        global_env.insert(Symbol::intern("bytevector-s16-set!"), Expr::Builtin(builtins::bytevector_s16_set));
+        global_env.insert(Symbol::intern("bytevector-u32-ref"), Expr::Builtin(builtins::bytevector_u32_ref));
+        global_env.insert(Symbol::intern("bytevector-u32-set!"), Expr::Builtin(builtins::bytevector_u32_set));
+        global_env.insert(Symbol::intern("bytevector-s32-ref"), Expr::Builtin(builtins::bytevector_s32_ref));
+        global_env.insert(Symbol::intern("bytevector-s32-set!"), Expr::Builtin(builtins::bytevector_s32_set));
+        global_env.insert(Symbol::intern("bytevector-ieee-single-ref"), Expr::Builtin(builtins::bytevector_ieee_single_ref));
+        global_env.insert(Symbol::intern("bytevector-ieee-single-set!"), Expr::Builtin(builtins::bytevector_ieee_single_set));
+        global_env.insert(Symbol::intern("bytevector-ieee-double-ref"), Expr::Builtin(builtins::bytevector_ieee_double_ref));
+        global_env.insert(Symbol::intern("bytevector-ieee-double-set!"), Expr::Builtin(builtins::bytevector_ieee_double_set));
+        global_env.insert(Symbol::intern("vector?"), Expr::Builtin(builtins::vectorp));
+        global_env.insert(Symbol::intern("vector"), Expr::Builtin(builtins::vector));
+        global_env.insert(Symbol::intern("make-vector"), Expr::Builtin(builtins::make_vector));
+        global_env.insert(Symbol::intern("vector-length"), Expr::Builtin(builtins::vector_length));
+        global_env.insert(Symbol::intern("vector-ref"), Expr::Builtin(builtins::vector_ref));
+        global_env.insert(Symbol::intern("vector-set!"), Expr::Builtin(builtins::vector_set));
+        global_env.insert(Symbol::intern("vector->list"), Expr::Builtin(builtins::vector_to_list));
+        global_env.insert(Symbol::intern("list->vector"), Expr::Builtin(builtins::list_to_vector));
+        global_env.insert(Symbol::intern("vector-fill!"), Expr::Builtin(builtins::vector_fill));
+        global_env.insert(Symbol::intern("sort"), Expr::Builtin(builtins::sort));
+        global_env.insert(Symbol::intern("list-sort"), Expr::Builtin(builtins::list_sort));
+        global_env.insert(Symbol::intern("list-merge"), Expr::Builtin(builtins::list_merge));
+        global_env.insert(Symbol::intern("list-sorted?"), Expr::Builtin(builtins::list_sortedp));
+        global_env.insert(Symbol::intern("vector-sort"), Expr::Builtin(builtins::vector_sort));
+        global_env.insert(Symbol::intern("vector-sort!"), Expr::Builtin(builtins::vector_sort_mut));
+        global_env.insert(Symbol::intern("vector-sorted?"), Expr::Builtin(builtins::vector_sortedp));
+        global_env.insert(Symbol::intern("apply"), Expr::Builtin(builtins::apply));
+        global_env.insert(Symbol::intern("values"), Expr::Builtin(builtins::values));
+        global_env.insert(Symbol::intern("call-with-values"), Expr::Builtin(builtins::call_with_values));
+        global_env.insert(Symbol::intern("load"), Expr::Builtin(builtins::load));
+        global_env.insert(Symbol::intern("load-once"), Expr::Builtin(builtins::load_once));
+        global_env.insert(Symbol::intern("require"), Expr::Builtin(builtins::load_once));
+        global_env.insert(Symbol::intern("eval"), Expr::Builtin(builtins::eval_));
+        global_env.insert(Symbol::intern("environment?"), Expr::Builtin(builtins::environmentp));
+        global_env.insert(Symbol::intern("environment"), Expr::Builtin(builtins::environment));
+        global_env.insert(Symbol::intern("interaction-environment"), Expr::Builtin(builtins::interaction_environment));
+        global_env.insert(Symbol::intern("scheme-report-environment"), Expr::Builtin(builtins::scheme_report_environment));
+        global_env.insert(Symbol::intern("null-environment"), Expr::Builtin(builtins::null_environment));
+        global_env.insert(Symbol::intern("environment-bound?"), Expr::Builtin(builtins::environment_boundp));
+        global_env.insert(Symbol::intern("environment-bound-names"), Expr::Builtin(builtins::environment_bound_names));
+        global_env.insert(Symbol::intern("environment-assign!"), Expr::Builtin(builtins::environment_assign));
+        global_env.insert(Symbol::intern("gc"), Expr::Builtin(builtins::gc_));
+        global_env.insert(Symbol::intern("gc-stats"), Expr::Builtin(builtins::gc_stats));
+        global_env.insert(Symbol::intern("features"), Expr::Builtin(builtins::features));
+        global_env.insert(Symbol::intern("current-second"), Expr::Builtin(builtins::current_second));
+        global_env.insert(Symbol::intern("current-jiffy"), Expr::Builtin(builtins::current_jiffy));
+        global_env.insert(Symbol::intern("jiffies-per-second"), Expr::Builtin(builtins::jiffies_per_second));
+        global_env.insert(Symbol::intern("command-line"), Expr::Builtin(builtins::command_line));
+        global_env.insert(Symbol::intern("exit"), Expr::Builtin(builtins::exit));
+        global_env.insert(Symbol::intern("emergency-exit"), Expr::Builtin(builtins::emergency_exit));
+        global_env.insert(Symbol::intern("get-environment-variable"), Expr::Builtin(builtins::get_environment_variable));
+        global_env.insert(Symbol::intern("get-environment-variables"), Expr::Builtin(builtins::get_environment_variables));
+        global_env.insert(Symbol::intern("read"), Expr::Builtin(builtins::read));
+        global_env.insert(Symbol::intern("display"), Expr::Builtin(builtins::display));
+        global_env.insert(Symbol::intern("write"), Expr::Builtin(builtins::write));
+        global_env.insert(Symbol::intern("write-shared"), Expr::Builtin(builtins::write_shared));
+        global_env.insert(Symbol::intern("write-simple"), Expr::Builtin(builtins::write_simple));
+        global_env.insert(Symbol::intern("format"), Expr::Builtin(builtins::format));
+        global_env.insert(Symbol::intern("current-input-port"), Expr::Builtin(builtins::current_input_port));
+        global_env.insert(Symbol::intern("current-output-port"), Expr::Builtin(builtins::current_output_port));
+        global_env.insert(Symbol::intern("current-error-port"), Expr::Builtin(builtins::current_error_port));
+        global_env.insert(Symbol::intern("file-exists?"), Expr::Builtin(builtins::file_existsp));
+        global_env.insert(Symbol::intern("delete-file"), Expr::Builtin(builtins::delete_file));
+        global_env.insert(Symbol::intern("open-input-file"), Expr::Builtin(builtins::open_input_file));
+        global_env.insert(Symbol::intern("open-binary-input-file"), Expr::Builtin(builtins::open_binary_input_file));
+        global_env.insert(Symbol::intern("open-output-file"), Expr::Builtin(builtins::open_output_file));
+        global_env.insert(Symbol::intern("open-binary-output-file"), Expr::Builtin(builtins::open_binary_output_file));
+        global_env.insert(Symbol::intern("close-port"), Expr::Builtin(builtins::close_port));
+        global_env.insert(Symbol::intern("close-input-port"), Expr::Builtin(builtins::close_input_port));
+        global_env.insert(Symbol::intern("close-output-port"), Expr::Builtin(builtins::close_output_port));
+        global_env.insert(Symbol::intern("call-with-input-file"), Expr::Builtin(builtins::call_with_input_file));
+        global_env.insert(Symbol::intern("call-with-output-file"), Expr::Builtin(builtins::call_with_output_file));
+        global_env.insert(Symbol::intern("with-input-from-file"), Expr::Builtin(builtins::with_input_from_file));
+        global_env.insert(Symbol::intern("with-output-to-file"), Expr::Builtin(builtins::with_output_to_file));
+        global_env.insert(Symbol::intern("open-input-string"), Expr::Builtin(builtins::open_input_string));
+        global_env.insert(Symbol::intern("open-output-string"), Expr::Builtin(builtins::open_output_string));
+        global_env.insert(Symbol::intern("get-output-string"), Expr::Builtin(builtins::get_output_string));
+        global_env.insert(Symbol::intern("open-input-bytevector"), Expr::Builtin(builtins::open_input_bytevector));
+        global_env.insert(Symbol::intern("open-output-bytevector"), Expr::Builtin(builtins::open_output_bytevector));
+        global_env.insert(Symbol::intern("get-output-bytevector"), Expr::Builtin(builtins::get_output_bytevector));
+        global_env.insert(Symbol::intern("call-with-output-string"), Expr::Builtin(builtins::call_with_output_string));
+        global_env.insert(Symbol::intern("with-output-to-string"), Expr::Builtin(builtins::with_output_to_string));
+        global_env.insert(Symbol::intern("read-char"), Expr::Builtin(builtins::read_char));
+        global_env.insert(Symbol::intern("peek-char"), Expr::Builtin(builtins::peek_char));
+        global_env.insert(Symbol::intern("char-ready?"), Expr::Builtin(builtins::char_readyp));
+        global_env.insert(Symbol::intern("read-line"), Expr::Builtin(builtins::read_line));
+        global_env.insert(Symbol::intern("read-string"), Expr::Builtin(builtins::read_string));
+        global_env.insert(Symbol::intern("read-u8"), Expr::Builtin(builtins::read_u8));
+        global_env.insert(Symbol::intern("peek-u8"), Expr::Builtin(builtins::peek_u8));
+        global_env.insert(Symbol::intern("u8-ready?"), Expr::Builtin(builtins::u8_readyp));
+        global_env.insert(Symbol::intern("read-bytevector"), Expr::Builtin(builtins::read_bytevector));
+        global_env.insert(Symbol::intern("write-char"), Expr::Builtin(builtins::write_char));
+        global_env.insert(Symbol::intern("write-string"), Expr::Builtin(builtins::write_string));
+        global_env.insert(Symbol::intern("write-u8"), Expr::Builtin(builtins::write_u8));
+        global_env.insert(Symbol::intern("write-bytevector"), Expr::Builtin(builtins::write_bytevector));
+        global_env.insert(Symbol::intern("newline"), Expr::Builtin(builtins::newline));
+        global_env.insert(Symbol::intern("flush-output-port"), Expr::Builtin(builtins::flush_output_port));
+        global_env.insert(Symbol::intern("eof-object"), Expr::Builtin(builtins::eof_object));
+        global_env.insert(Symbol::intern("eof-object?"), Expr::Builtin(builtins::eof_objectp));
+        global_env.insert(Symbol::intern("port?"), Expr::Builtin(builtins::portp));
+        global_env.insert(Symbol::intern("input-port?"), Expr::Builtin(builtins::input_portp));
+        global_env.insert(Symbol::intern("output-port?"), Expr::Builtin(builtins::output_portp));
+        global_env.insert(Symbol::intern("textual-port?"), Expr::Builtin(builtins::textual_portp));
+        global_env.insert(Symbol::intern("binary-port?"), Expr::Builtin(builtins::binary_portp));
+        global_env.insert(Symbol::intern("input-port-open?"), Expr::Builtin(builtins::input_port_openp));
+        global_env.insert(Symbol::intern("output-port-open?"), Expr::Builtin(builtins::output_port_openp));
+        global_env.insert(Symbol::intern("hash-table?"), Expr::Builtin(builtins::hash_tablep));
+        global_env.insert(Symbol::intern("make-hash-table"), Expr::Builtin(builtins::make_hash_table));
+        global_env.insert(Symbol::intern("alist->hash-table"), Expr::Builtin(builtins::alist_to_hash_table));
+        global_env.insert(Symbol::intern("hash-table-ref"), Expr::Builtin(builtins::hash_table_ref));
+        global_env.insert(Symbol::intern("hash-table-ref/default"), Expr::Builtin(builtins::hash_table_ref_default));
+        global_env.insert(Symbol::intern("hash-table-set!"), Expr::Builtin(builtins::hash_table_set));
+        global_env.insert(Symbol::intern("hash-table-delete!"), Expr::Builtin(builtins::hash_table_delete));
+        global_env.insert(Symbol::intern("hash-table-contains?"), Expr::Builtin(builtins::hash_table_contains));
+        global_env.insert(Symbol::intern("hash-table-exists?"), Expr::Builtin(builtins::hash_table_contains));
+        global_env.insert(Symbol::intern("hash-table-update!"), Expr::Builtin(builtins::hash_table_update));
+        global_env.insert(Symbol::intern("hash-table-update!/default"), Expr::Builtin(builtins::hash_table_update_default));
+        global_env.insert(Symbol::intern("hash-table-count"), Expr::Builtin(builtins::hash_table_count));
+        global_env.insert(Symbol::intern("hash-table-size"), Expr::Builtin(builtins::hash_table_size));
+        global_env.insert(Symbol::intern("hash-table-keys"), Expr::Builtin(builtins::hash_table_keys));
+        global_env.insert(Symbol::intern("hash-table-values"), Expr::Builtin(builtins::hash_table_values));
+        global_env.insert(Symbol::intern("hash-table->alist"), Expr::Builtin(builtins::hash_table_to_alist));
+        global_env.insert(Symbol::intern("hash-table-walk"), Expr::Builtin(builtins::hash_table_walk));
+        global_env.insert(Symbol::intern("hash-table-clear!"), Expr::Builtin(builtins::hash_table_clear));
+        global_env.insert(Symbol::intern("hash-table-copy"), Expr::Builtin(builtins::hash_table_copy));
+        global_env.insert(Symbol::intern("hash"), Expr::Builtin(builtins::hash));
+        global_env.insert(Symbol::intern("hash-by-identity"), Expr::Builtin(builtins::hash_by_identity));
+        global_env.insert(Symbol::intern("string-hash"), Expr::Builtin(builtins::string_hash));
+        global_env.insert(Symbol::intern("make-default-comparator"), Expr::Builtin(builtins::make_default_comparator));
+        global_env.insert(Symbol::intern("mapping"), Expr::Builtin(builtins::mapping));
+        global_env.insert(Symbol::intern("mapping?"), Expr::Builtin(builtins::mappingp));
+        global_env.insert(Symbol::intern("mapping-empty?"), Expr::Builtin(builtins::mapping_emptyp));
+        global_env.insert(Symbol::intern("mapping-size"), Expr::Builtin(builtins::mapping_size));
+        global_env.insert(Symbol::intern("mapping-contains?"), Expr::Builtin(builtins::mapping_containsp));
+        global_env.insert(Symbol::intern("mapping-ref"), Expr::Builtin(builtins::mapping_ref));
+        global_env.insert(Symbol::intern("mapping-ref/default"), Expr::Builtin(builtins::mapping_ref_default));
+        global_env.insert(Symbol::intern("mapping-set"), Expr::Builtin(builtins::mapping_set));
+        global_env.insert(Symbol::intern("mapping-adjoin"), Expr::Builtin(builtins::mapping_adjoin));
+        global_env.insert(Symbol::intern("mapping-delete"), Expr::Builtin(builtins::mapping_delete));
+        global_env.insert(Symbol::intern("mapping-fold"), Expr::Builtin(builtins::mapping_fold));
+        global_env.insert(Symbol::intern("mapping-for-each"), Expr::Builtin(builtins::mapping_for_each));
+        global_env.insert(Symbol::intern("mapping-keys"), Expr::Builtin(builtins::mapping_keys));
+        global_env.insert(Symbol::intern("mapping-values"), Expr::Builtin(builtins::mapping_values));
+        global_env.insert(Symbol::intern("mapping->alist"), Expr::Builtin(builtins::mapping_to_alist));
+        global_env.insert(Symbol::intern("alist->mapping"), Expr::Builtin(builtins::alist_to_mapping));
+        global_env.insert(Symbol::intern("mapping-comparator"), Expr::Builtin(builtins::mapping_comparator));
+        global_env.insert(Symbol::intern("char-set?"), Expr::Builtin(builtins::char_setp));
+        global_env.insert(Symbol::intern("char-set"), Expr::Builtin(builtins::char_set));
+        global_env.insert(Symbol::intern("list->char-set"), Expr::Builtin(builtins::list_to_char_set));
+        global_env.insert(Symbol::intern("string->char-set"), Expr::Builtin(builtins::string_to_char_set));
+        global_env.insert(Symbol::intern("ucs-range->char-set"), Expr::Builtin(builtins::ucs_range_to_char_set));
+        global_env.insert(Symbol::intern("char-set->list"), Expr::Builtin(builtins::char_set_to_list));
+        global_env.insert(Symbol::intern("char-set->string"), Expr::Builtin(builtins::char_set_to_string));
+        global_env.insert(Symbol::intern("char-set-size"), Expr::Builtin(builtins::char_set_size));
+        global_env.insert(Symbol::intern("char-set-contains?"), Expr::Builtin(builtins::char_set_containsp));
+        global_env.insert(Symbol::intern("char-set="), Expr::Builtin(builtins::char_set_eq));
+        global_env.insert(Symbol::intern("char-set-union"), Expr::Builtin(builtins::char_set_union));
+        global_env.insert(Symbol::intern("char-set-intersection"), Expr::Builtin(builtins::char_set_intersection));
+        global_env.insert(Symbol::intern("char-set-difference"), Expr::Builtin(builtins::char_set_difference));
+        global_env.insert(Symbol::intern("char-set-complement"), Expr::Builtin(builtins::char_set_complement));
+        global_env.insert(Symbol::intern("char-set-adjoin"), Expr::Builtin(builtins::char_set_adjoin));
+        global_env.insert(Symbol::intern("char-set-delete"), Expr::Builtin(builtins::char_set_delete));
+        global_env.insert(Symbol::intern("char-set-filter"), Expr::Builtin(builtins::char_set_filter));
+        for name in charset::STANDARD_SETS {
+            global_env.insert(Symbol::intern(&format!("char-set:{}", name)), Expr::CharSet(charset::standard(name)));
+        }
+        global_env.insert(Symbol::intern("regexp"), Expr::Builtin(builtins::regexp));
+        global_env.insert(Symbol::intern("string->regexp"), Expr::Builtin(builtins::string_to_regexp));
+        global_env.insert(Symbol::intern("regexp?"), Expr::Builtin(builtins::regexpp));
+        global_env.insert(Symbol::intern("valid-sre?"), Expr::Builtin(builtins::valid_srep));
+        global_env.insert(Symbol::intern("regexp-matches"), Expr::Builtin(builtins::regexp_matches));
+        global_env.insert(Symbol::intern("regexp-matches?"), Expr::Builtin(builtins::regexp_matchesp));
+        global_env.insert(Symbol::intern("regexp-search"), Expr::Builtin(builtins::regexp_search));
+        global_env.insert(Symbol::intern("regexp-replace"), Expr::Builtin(builtins::regexp_replace));
+        global_env.insert(Symbol::intern("regexp-replace-all"), Expr::Builtin(builtins::regexp_replace_all));
+        global_env.insert(Symbol::intern("regexp-split"), Expr::Builtin(builtins::regexp_split));
+        global_env.insert(Symbol::intern("regexp-extract"), Expr::Builtin(builtins::regexp_extract));
+        global_env.insert(Symbol::intern("regexp-match?"), Expr::Builtin(builtins::regexp_matchp));
+        global_env.insert(Symbol::intern("regexp-match-count"), Expr::Builtin(builtins::regexp_match_count));
+        global_env.insert(Symbol::intern("regexp-match-submatch"), Expr::Builtin(builtins::regexp_match_submatch));
+        global_env.insert(Symbol::intern("regexp-match-submatch-start"), Expr::Builtin(builtins::regexp_match_submatch_start));
+        global_env.insert(Symbol::intern("regexp-match-submatch-end"), Expr::Builtin(builtins::regexp_match_submatch_end));
+        global_env.insert(Symbol::intern("regexp-match->list"), Expr::Builtin(builtins::regexp_match_to_list));
+        global_env.insert(Symbol::intern("json-read"), Expr::Builtin(builtins::json_read));
+        global_env.insert(Symbol::intern("json-for-each"), Expr::Builtin(builtins::json_for_each));
+        global_env.insert(Symbol::intern("json-write"), Expr::Builtin(builtins::json_write));
+        gc::env(Env { env: global_env, ..Env::default() })
+    }
+
+    /// A top-level environment with only the builtins named in `allowed`.
+    /// Bindings that are not procedures, like the standard char-sets, are
+    /// all kept.
+    pub fn restricted(allowed: &[&str]) -> Rc<RefCell<Env>> {
+        let full = Env::new();
+        let env = full.borrow().env.iter()
+            .filter(|(name, value)| !matches!(value, Expr::Builtin(_)) || allowed.contains(&&*name.name()))
+            .map(|(name, value)| (*name, value.clone()))
+            .collect();
+        gc::env(Env { env, ..Env::default() })
+    }
+
+    /// An environment with no bindings, where only special forms are
+    /// available.
+    pub fn empty() -> Rc<RefCell<Env>> {
+        gc::env(Env::default())
+    }
+
+    /// Every name visible in this environment, including those of the
+    /// environments it extends.
+    pub fn names(&self) -> Vec<Symbol> {
+        let mut names: Vec<Symbol> = self.env.keys().chain(self.imports.keys()).copied().collect();
+        for (name, slot) in self.names.iter().zip(&self.slots) {
+            if slot.is_some() && !names.contains(name) {
+                names.push(*name);
+            }
+        }
+        if let Some(env) = &self.node {
+            for name in env.borrow().names() {
+                if !names.contains(&name) {
+                    names.push(name);
                 }
-            },
+            }
         }
+        names
+    }
+
+    fn slot(&self, key: Symbol) -> Option<usize> {
+        self.names.iter().position(|name| *name == key)
     }
 
-    pub fn set(&mut self, key: String, value: Expr) -> Result<(), &'static str> {
-        let result = self.env.get(&key);
-        match result {
-            Some(_) => {
-                self.env.insert(key, value);
+    pub fn get(&self, key: Symbol) -> Option<Expr> {
+        if let Some(value) = self.env.get(&key) {
+            return Some(value.clone())
+        }
+        if let Some(i) = self.slot(key) && let Some(value) = &self.slots[i] {
+            return Some(value.clone())
+        }
+        if let Some((env, name)) = self.imports.get(&key) {
+            return env.borrow().get(*name)
+        }
+
+        match &self.node {
+            Some(env) => env.borrow().get(key),
+            None => None
+        }
+    }
+
+    /// Looks up the variable in `slot` of the environment `depth` out,
+    /// skipping those the analyzer has found cannot bind it. An empty slot
+    /// falls back to looking the name up.
+    pub fn get_at(&self, depth: usize, slot: usize, key: Symbol) -> Option<Expr> {
+        if depth == 0 {
+            return match self.slots.get(slot) {
+                Some(Some(value)) => Some(value.clone()),
+                _ => self.get(key),
+            }
+        }
+
+        self.node.as_ref()?.borrow().get_at(depth - 1, slot, key)
+    }
+
+    pub fn set_at(&mut self, depth: usize, slot: usize, key: Symbol, value: Expr) -> Result<(), &'static str> {
+        if depth == 0 {
+            if let Some(bound @ Some(_)) = self.slots.get_mut(slot) {
+                *bound = Some(value);
                 return Ok(())
-            },
-            None => {
-                match &self.node {
-                    Some(env) => env.borrow_mut().set(key, value),
-                    None => return Err("key not found")
-                }
-            },
+            }
+            return self.set(key, value)
+        }
+
+        match &self.node {
+            Some(env) => env.borrow_mut().set_at(depth - 1, slot, key, value),
+            None => Err("variable is not bound"),
+        }
+    }
+
+    pub fn set(&mut self, key: Symbol, value: Expr) -> Result<(), &'static str> {
+        if let Some(bound) = self.env.get_mut(&key) {
+            *bound = value;
+            return Ok(())
+        }
+        if let Some(i) = self.slot(key) && self.slots[i].is_some() {
+            self.slots[i] = Some(value);
+            return Ok(())
+        }
+        // The variable belongs to the library, and may be shared with
+        // every other program that imports it.
+        if self.imports.contains_key(&key) { return Err("imported variables cannot be set") }
+
+        match &self.node {
+            Some(env) => env.borrow_mut().set(key, value),
+            None => Err("variable is not bound")
+        }
+    }
+
+    pub fn insert(&mut self, key: Symbol, value: Expr) {
+        match self.slot(key) {
+            Some(i) => self.slots[i] = Some(value),
+            None => { self.env.insert(key, value); },
         }
     }
 
-    pub fn insert(&mut self, key: String, value: Expr) {
-        self.env.insert(key, value);
+    /// Binds `key` to the variable `name` of a library's environment,
+    /// replacing any definition of `key` here.
+    pub fn import(&mut self, key: Symbol, env: Rc<RefCell<Env>>, name: Symbol) {
+        self.env.remove(&key);
+        self.imports.insert(key, (env, name));
     }
 
     pub fn extend_env(&mut self, prev_env: Rc<RefCell<Env>>) -> Rc<RefCell<Env>> {
-        Rc::new(RefCell::new(Env { node: Some(prev_env), env: HashMap::new() }))
+        gc::env(Env { node: Some(prev_env), ..Env::default() })
     }
-}
 
+    /// An environment for a procedure body or `let`, with a slot for each
+    /// of `names`.
+    pub fn frame(parent: Rc<RefCell<Env>>, names: Rc<[Symbol]>) -> Rc<RefCell<Env>> {
+        let slots = vec![None; names.len()];
+        gc::env(Env { node: Some(parent), names, slots, ..Env::default() })
+    }
 
+    /// Removes every binding, for the collector to break a cycle.
+    pub fn clear(&mut self) -> Vec<Expr> {
+        let mut values: Vec<Expr> = self.env.drain().map(|(_, value)| value).collect();
+        values.extend(self.slots.iter_mut().filter_map(Option::take));
+        values.extend(self.imports.drain().map(|(_, (env, _))| Expr::Environment(env)));
+        values
+    }
+
+    /// The outermost environment that `env` extends.
+    pub fn top_level(env: Rc<RefCell<Env>>) -> Rc<RefCell<Env>> {
+        let mut env = env;
+        loop {
+            let node = env.borrow().node.clone();
+            match node {
+                Some(parent) => env = parent,
+                None => return env,
+            }
+        }
+    }
+}
+
+impl Trace for Env {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Some(env) = &self.node {
+            tracer.env(env);
+        }
+        for value in self.env.values().chain(self.slots.iter().flatten()) {
+            tracer.expr(value);
+        }
+        for (env, _) in self.imports.values() {
+            tracer.env(env);
+        }
+    }
+}
@@ -1,256 +1,389 @@
-use crate::ast::{Expr, Literal};
+use crate::analyze;
+use crate::analyze::{Body, Node, Procedure};
+use crate::ast::{Expr, Lambda, Literal};
 use crate::env::Env;
-
-use std::cell::RefCell;
+use crate::gc;
+use crate::library;
+use crate::record;
+use crate::sandbox;
+use crate::symbol;
+use crate::symbol::Symbol;
+use crate::vm;
+
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 
-pub fn eval(program: &Expr, env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    match program {
-        Expr::List(list) => {
-            if list.is_empty() {
-                return Ok(Expr::List(list.to_vec()))
-            }
+/// How deep evaluation may go before it fails with "recursion too deep",
+/// unless changed with `set_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 1_000_000;
 
-            let head = &list[0];
-            match head {
-                Expr::Var(atom) => {
-                    match atom.as_str() {
-                        "lambda" => lambda(&list, env),
-                        "define" => define(&list, env),
-                        "if"     => ifexpr(&list, env),
-                        "quote"  => quote(&list, env),
-                        "set!"   => set(&list, env),
-                        "begin"  => begin(&list, env),
-                        "let"    => let_(&list, env),
-                        "cond"   => cond(&list, env),
-                        _ => {
-                            let mut args = Vec::new();
-                            for op in &list[1..list.len()] {
-                                let result = eval(op, env.clone());
-                                if result.is_err() { return result; }
-
-                                args.push(result.unwrap());
-                            }
-
-                            let var = env.borrow().get(&atom);
-                            if var.is_none() { return Err("undefined variable"); }
-                            let proc = var.unwrap();
-                            apply(&proc, args, env)
-                        },
-                    }
-                },
-                Expr::Literal(_) => {
-                    Err("not applicable")
-                },
-                Expr::List(_) => {
-                    let proc = eval(head, env.clone());
-                    if proc.is_err() { return proc }
+/// When evaluation is re-entered from Rust, as when a builtin like `map`
+/// calls a procedure, and less than this much of the Rust stack is left, it
+/// goes on in a new `STACK_SEGMENT` allocated on the heap.
+const RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
 
-                    let mut args = Vec::new();
-                    for op in &list[1..list.len()] {
-                        let result = eval(op, env.clone());
-                        if result.is_err() { return result; }
+/// How much of the depth limit re-entering evaluation counts for. It uses
+/// far more memory than a call within one evaluation, so a runaway
+/// recursion through `map` fails at about the same size as any other.
+const NESTED_DEPTH: usize = 32;
 
-                        args.push(result.unwrap());
-                    }
+thread_local! {
+    static MAX_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_DEPTH) };
+    /// How deep the running evaluation had gone when it last called into
+    /// Rust, which nested evaluations start counting from.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
 
-                    apply(&proc.unwrap(), args, env)
-                }
-                _ => Err("not implemented")
-            }
-        },
+/// Set from outside, usually by a SIGINT handler, to stop evaluation.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
-        Expr::Var(atom) => {
-            let var = env.borrow().get(&atom);
-            match var {
-                Some(val) => Ok(val.clone()),
-                None => {
-                    Err("undefined variable")
-                },
-            }
-        },
+/// Makes running evaluation fail with "user interrupt". It stays
+/// interrupted until `clear_interrupt`, so the error is not ignored on the
+/// way out. This only sets a flag, so it is safe to call from a signal
+/// handler or another thread. Returns whether an interrupt was already
+/// pending.
+pub fn interrupt() -> bool {
+    INTERRUPTED.swap(true, Ordering::Relaxed)
+}
 
-        Expr::Literal(l) => Ok(Expr::Literal(l.clone())),
-        _ => Err("not implemented"),
-    }
+pub fn clear_interrupt() {
+    INTERRUPTED.store(false, Ordering::Relaxed);
 }
 
-fn lambda(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if let Expr::List(args) = &list[1] {
-        let body = &list[2..list.len()];
+/// How many elements, comparisons or other units of work long-running
+/// builtins get through between calls to `poll`.
+pub const POLL_INTERVAL: usize = 4096;
 
-        Ok(Expr::Lambda(args.to_vec(), body.to_vec()))
-    } else {
-        Err("not implemented")
-    }
+/// Called by the evaluators before each step, and by long-running builtins
+/// as they go, to stop on an interrupt or when a sandbox limit is reached.
+pub fn poll() -> Result<(), &'static str> {
+    if INTERRUPTED.load(Ordering::Relaxed) { return Err("user interrupt") }
+
+    sandbox::check()
 }
 
-fn define(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() < 3 { return Err("invalid define statement"); }
+pub fn max_depth() -> usize {
+    MAX_DEPTH.with(|depth| depth.get())
+}
 
-    match &list[1] {
-        Expr::List(vars) => {
-            if vars.is_empty() { return Err("define vars cannot be empty") }
+pub fn set_max_depth(depth: usize) {
+    MAX_DEPTH.with(|max| max.set(depth));
+}
 
-            let args = if vars.len() == 1 {
-                Vec::new()
-            } else {
-                vars[1..vars.len()].to_vec()
-            };
+/// Records how deep the running evaluation is before it calls a builtin,
+/// so that evaluations nested in the builtin count toward the same limit.
+pub fn reached(depth: usize) {
+    DEPTH.with(|d| d.set(depth));
+}
 
-            env.borrow_mut().insert(
-                vars[0].to_string(),
-                Expr::Lambda(args, list[2..list.len()].to_vec())
-            );
+/// Puts back the depth of the enclosing evaluation when a nested one ends,
+/// even by panicking.
+struct Restore(usize);
 
-            Ok(Expr::Unspecified)
-        },
-        Expr::Var(atom) => {
-            let val = &list[2];
-            let val = eval(val, env.clone());
-            if val.is_err() { return val; }
-            let val = val.unwrap();
-            if val.is_unspecified() {
-                return Err("unspecified value cannot be used as an expression")
-            }
+impl Drop for Restore {
+    fn drop(&mut self) {
+        reached(self.0);
+    }
+}
 
-            env.borrow_mut().insert(atom.to_string(), val.clone());
+/// Runs `f` as a nested evaluation, passing it the depth it starts at, or
+/// fails if that is already too deep.
+pub fn nested<T>(f: impl FnOnce(usize) -> Result<T, &'static str>) -> Result<T, &'static str> {
+    let outer = DEPTH.with(|depth| depth.get());
+    if outer + NESTED_DEPTH > max_depth() { return Err("recursion too deep") }
 
-            Ok(Expr::Unspecified)
-        },
-        _ => Err("invalid define statement")
-    }
+    let _restore = Restore(outer);
+    stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, || f(outer + NESTED_DEPTH))
 }
 
-fn ifexpr(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    let test = eval(&list[1], env.clone());
-    if test.is_err() { return test }
-    let test = test.unwrap();
+/// Analyzes a program and then runs it. Syntax errors anywhere in the
+/// program, including in procedure bodies, are reported before any of it
+/// runs.
+pub fn eval(program: &Expr, env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let node = analyze::analyze(program)?;
+    run(Step::Eval(Rc::new(node), env))
+}
 
-    if let Expr::Literal(Literal::Bool(false)) = test {
-        if list.len() == 3 { return Ok(Expr::Unspecified) }
+/// What is left to do once the value being computed is known. Evaluation
+/// keeps these on a heap-allocated stack rather than recursing, so deep
+/// recursion in Scheme does not use up the Rust stack.
+enum Continuation {
+    If(Rc<Node>, Option<Rc<Node>>, Rc<RefCell<Env>>),
+    /// Waiting on the test of clause `i`.
+    Cond(Rc<[(Rc<Node>, Rc<Node>)]>, usize, Option<Rc<Node>>, Rc<RefCell<Env>>),
+    /// Runs the rest of a body from `i`. Errors before the last
+    /// expression of a body do not stop it, so they unwind to here.
+    Sequence(Body, usize, Rc<RefCell<Env>>),
+    Define(Symbol, Rc<RefCell<Env>>),
+    Set(Option<(usize, usize)>, Symbol, Rc<RefCell<Env>>),
+    /// Waiting on the value for binding `i`, to put in the let environment.
+    Let(Rc<[(Symbol, Rc<Node>)]>, usize, Body, Rc<RefCell<Env>>, Rc<RefCell<Env>>),
+    Operator(Body, Rc<RefCell<Env>>),
+    Operands(Callee, Body, Vec<Expr>, Rc<RefCell<Env>>),
+}
 
-        let alternate = &list[3];
-        return eval(alternate, env);
-    } else {
-        let consequent = &list[2];
-        return eval(consequent, env);
-    }
+/// The procedure of a call, or the variable naming it. A named procedure
+/// is looked up once the arguments are evaluated.
+enum Callee {
+    Value(Expr),
+    Named(Rc<Node>),
 }
 
-fn quote(list: &[Expr], _env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 2 { return Err("invalid quote syntax") }
-    Ok(list[1].clone())
+enum Step {
+    Eval(Rc<Node>, Rc<RefCell<Env>>),
+    Apply(Expr, Vec<Expr>, Rc<RefCell<Env>>),
+    Value(Result<Expr, &'static str>),
 }
 
-fn set(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    if list.len() != 3 { return Err("invalid set syntax") }
+fn run(step: Step) -> Result<Expr, &'static str> {
+    nested(|depth| {
+        let max_depth = max_depth().saturating_sub(depth);
+        let mut stack = Vec::new();
+        let mut step = step;
+        loop {
+            step = match step {
+                Step::Eval(_, _) if stack.len() >= max_depth => Step::Value(Err("recursion too deep")),
+                Step::Eval(node, env) => match poll() {
+                    Ok(()) => execute(&node, env, &mut stack),
+                    Err(err) => Step::Value(Err(err)),
+                },
+                Step::Apply(proc, args, env) => {
+                    reached(depth + stack.len());
+                    enter(proc, args, env, &mut stack)
+                },
+                Step::Value(Err(err)) => {
+                    // Unwind to the innermost body with expressions left.
+                    loop {
+                        match stack.pop() {
+                            Some(Continuation::Sequence(body, i, env)) => break sequence(body, i, env, &mut stack),
+                            Some(_) => continue,
+                            None => return Err(err),
+                        }
+                    }
+                },
+                Step::Value(Ok(value)) => match stack.pop() {
+                    Some(k) => resume(k, value, &mut stack),
+                    None => return Ok(value),
+                },
+            }
+        }
+    })
+}
+
+fn variable(node: &Node, env: &Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    match node {
+        Node::Global(atom) => env.borrow().get(*atom).ok_or("undefined variable"),
+        Node::Local(depth, slot, atom) => env.borrow().get_at(*depth, *slot, *atom).ok_or("undefined variable"),
+        _ => unreachable!(),
+    }
+}
+
+fn execute(node: &Node, env: Rc<RefCell<Env>>, stack: &mut Vec<Continuation>) -> Step {
+    match node {
+        Node::Const(expr) => Step::Value(Ok(expr.clone())),
+        Node::Global(_) | Node::Local(..) => Step::Value(variable(node, &env)),
+        Node::Set(depth, atom, val) => {
+            stack.push(Continuation::Set(*depth, *atom, env.clone()));
+            Step::Eval(val.clone(), env)
+        },
+        Node::Define(atom, val) => {
+            stack.push(Continuation::Define(*atom, env.clone()));
+            Step::Eval(val.clone(), env)
+        },
+        Node::Lambda(procedure) => Step::Value(Ok(closure(procedure.clone(), env))),
+        Node::If(test, consequent, alternate) => {
+            stack.push(Continuation::If(consequent.clone(), alternate.clone(), env.clone()));
+            Step::Eval(test.clone(), env)
+        },
+        Node::Cond(clauses, else_) => {
+            if clauses.is_empty() { return otherwise(else_, env) }
 
-    let var = &list[1];
-    match var {
-        Expr::Var(atom) => {
-            let val = &list[2];
-            let val = eval(val, env.clone());
-            if val.is_err() { return val; }
-            let val = val.unwrap();
+            stack.push(Continuation::Cond(clauses.clone(), 0, else_.clone(), env.clone()));
+            Step::Eval(clauses[0].0.clone(), env)
+        },
+        Node::Begin(body) => sequence(body.clone(), 0, env, stack),
+        Node::Let(bindings, names, body) => {
+            let let_env = Env::frame(env.clone(), names.clone());
+            if bindings.is_empty() { return sequence(body.clone(), 0, let_env, stack) }
 
-            let result = env.borrow_mut().set(atom.to_string(), val);
-            if result.is_ok() {
-                Ok(Expr::Unspecified)
+            stack.push(Continuation::Let(bindings.clone(), 0, body.clone(), let_env, env.clone()));
+            Step::Eval(bindings[0].1.clone(), env)
+        },
+        Node::Call(proc, args) => {
+            // Arguments are evaluated before a named procedure is looked
+            // up, so errors in them are reported first.
+            if let Node::Global(_) | Node::Local(..) = **proc {
+                if args.is_empty() {
+                    return match variable(proc, &env) {
+                        Ok(proc) => Step::Apply(proc, Vec::new(), env),
+                        Err(err) => Step::Value(Err(err)),
+                    }
+                }
+                stack.push(Continuation::Operands(Callee::Named(proc.clone()), args.clone(), Vec::new(), env.clone()));
+                Step::Eval(args[0].clone(), env)
             } else {
-                Err("variable is not bound")
+                stack.push(Continuation::Operator(args.clone(), env.clone()));
+                Step::Eval(proc.clone(), env)
             }
         },
-        _ => Err("first parameter must be an atom"),
+        Node::Syntax(atom, list) => {
+            // Library forms read files and can bind any builtin.
+            if *atom != symbol::DEFINE_RECORD_TYPE && *atom != symbol::COND_EXPAND
+                && let Err(err) = sandbox::permit() {
+                return Step::Value(Err(err))
+            }
+
+            let result = match *atom {
+                symbol::DEFINE_RECORD_TYPE => define_record_type(list, env),
+                symbol::DEFINE_LIBRARY => library::define_library(list),
+                symbol::IMPORT => library::import(list, env),
+                symbol::INCLUDE => library::include(list, env, false),
+                symbol::INCLUDE_CI => library::include(list, env, true),
+                symbol::COND_EXPAND => library::cond_expand(list, env),
+                _ => unreachable!(),
+            };
+            Step::Value(result)
+        },
     }
 }
 
-fn begin(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    let mut result = Ok(Expr::Unspecified);
-    for expr in list.iter().skip(1) {
-        result = eval(expr, env.clone());
-    }
+fn resume(k: Continuation, value: Expr, stack: &mut Vec<Continuation>) -> Step {
+    match k {
+        Continuation::If(consequent, alternate, env) => {
+            if let Expr::Literal(Literal::Bool(false)) = value {
+                otherwise(&alternate, env)
+            } else {
+                Step::Eval(consequent, env)
+            }
+        },
+        Continuation::Cond(clauses, i, else_, env) => {
+            if value.is_true() {
+                Step::Eval(clauses[i].1.clone(), env)
+            } else if i + 1 < clauses.len() {
+                let test = clauses[i + 1].0.clone();
+                stack.push(Continuation::Cond(clauses, i + 1, else_, env.clone()));
+                Step::Eval(test, env)
+            } else {
+                otherwise(&else_, env)
+            }
+        },
+        Continuation::Sequence(body, i, env) => sequence(body, i, env, stack),
+        Continuation::Define(atom, env) => {
+            if value.is_unspecified() {
+                return Step::Value(Err("unspecified value cannot be used as an expression"))
+            }
 
-    result
-}
+            env.borrow_mut().insert(atom, value);
 
-fn let_(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    let decs = &list[1];
-    if !decs.is_list() { return Err("expecting list of declarations") }
-    let decs = decs.to_vec().unwrap();
-    let let_env = env.borrow_mut().extend_env(env.clone());
-    for dec in decs.iter() {
-        if !dec.is_list() { return Err("expecting a pair") }
-        let dec = dec.to_vec().unwrap();
-        let var = &dec[0];
-
-        let val = &dec[1];
-        let val = eval(val, env.clone());
-        if val.is_err() { return val }
-        let val = val.unwrap();
-
-        if !var.is_var() { return Err("expecting an atom in let declaration pair") }
-        let var = var.from_var().unwrap();
-        let_env.borrow_mut().insert(var, val.clone());
-    }
+            Step::Value(Ok(Expr::Unspecified))
+        },
+        Continuation::Set(depth, atom, env) => {
+            let result = match depth {
+                Some((depth, slot)) => env.borrow_mut().set_at(depth, slot, atom, value),
+                None => env.borrow_mut().set(atom, value),
+            };
+            Step::Value(result.map(|()| Expr::Unspecified))
+        },
+        Continuation::Let(bindings, i, body, let_env, env) => {
+            let_env.borrow_mut().insert(bindings[i].0, value);
+            if i + 1 < bindings.len() {
+                let val = bindings[i + 1].1.clone();
+                stack.push(Continuation::Let(bindings, i + 1, body, let_env, env.clone()));
+                Step::Eval(val, env)
+            } else {
+                sequence(body, 0, let_env, stack)
+            }
+        },
+        Continuation::Operator(args, env) => {
+            if args.is_empty() { return Step::Apply(value, Vec::new(), env) }
+
+            let arg = args[0].clone();
+            stack.push(Continuation::Operands(Callee::Value(value), args, Vec::new(), env.clone()));
+            Step::Eval(arg, env)
+        },
+        Continuation::Operands(callee, args, mut values, env) => {
+            values.push(value);
+            if values.len() < args.len() {
+                let arg = args[values.len()].clone();
+                stack.push(Continuation::Operands(callee, args, values, env.clone()));
+                return Step::Eval(arg, env)
+            }
 
-    let mut result = Ok(Expr::Unspecified);
-    let exprs = &list[2..list.len()];
-    for expr in exprs.iter() {
-        result = eval(expr, let_env.clone());
+            match callee {
+                Callee::Value(proc) => Step::Apply(proc, values, env),
+                Callee::Named(name) => match variable(&name, &env) {
+                    Ok(proc) => Step::Apply(proc, values, env),
+                    Err(err) => Step::Value(Err(err)),
+                },
+            }
+        },
     }
+}
 
-    result
+fn otherwise(node: &Option<Rc<Node>>, env: Rc<RefCell<Env>>) -> Step {
+    match node {
+        Some(node) => Step::Eval(node.clone(), env),
+        None => Step::Value(Ok(Expr::Unspecified)),
+    }
 }
 
-fn cond(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
-    let conditions = &list[1..list.len()];
-    for condition in conditions.iter() {
-        if !condition.is_list() { return Err("expecting a list in cond") }
-        let list = condition.to_vec().unwrap();
-        if list.len() != 2 { return Err("invalid format in cond") }
-
-        let pred = &list[0];
-        if pred.is_var() {
-            let else_ = pred.from_var().unwrap();
-            if else_ == "else" {
-                return eval(&list[1], env.clone());
-            } else {
-                return Err("expecting else in cond");
-            }
-        }
+/// Runs a body from expression `i` for the value of its last expression.
+/// The last expression is in tail position, so nothing is left on the
+/// stack for it.
+fn sequence(body: Body, i: usize, env: Rc<RefCell<Env>>, stack: &mut Vec<Continuation>) -> Step {
+    if body.is_empty() { return Step::Value(Ok(Expr::Unspecified)) }
 
-        let pred_result = eval(pred, env.clone());
-        if pred_result.is_err() { return pred_result }
-        if pred_result.unwrap().is_true() {
-            return eval(&list[1], env)
-        }
+    let node = body[i].clone();
+    if i + 1 < body.len() {
+        stack.push(Continuation::Sequence(body, i + 1, env.clone()));
     }
-
-    Ok(Expr::Unspecified)
+    Step::Eval(node, env)
 }
 
-pub fn apply(proc: &Expr, args: Vec<Expr>, env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+/// Applies a procedure in tail position. Procedures defined in Scheme run
+/// on the same stack; anything else is applied directly.
+fn enter(proc: Expr, args: Vec<Expr>, env: Rc<RefCell<Env>>, stack: &mut Vec<Continuation>) -> Step {
     match proc {
-        Expr::Lambda(parms, body) => {
-            if parms.len() != args.len() { return Err("applied to incorrect number of args") }
-
-            let proc_env = env.borrow_mut().extend_env(env.clone());
-            for (p, a) in parms.iter().zip(args) {
-                proc_env.borrow_mut().insert(p.from_var().unwrap(), a);
+        Expr::Lambda(lambda) => {
+            let procedure = &lambda.procedure;
+            if procedure.params.len() != args.len() {
+                return Step::Value(Err("applied to incorrect number of args"))
             }
 
-            let mut result = Ok(Expr::Unspecified);
-            for expr in body {
-                result = eval(&expr, proc_env.clone());
+            let proc_env = Env::frame(lambda.env.clone(), procedure.names.clone());
+            for (p, a) in procedure.params.iter().zip(args) {
+                proc_env.borrow_mut().insert(*p, a);
             }
 
-            result
+            sequence(procedure.body.clone(), 0, proc_env, stack)
         },
+        _ => Step::Value(apply(&proc, args, env)),
+    }
+}
+
+fn closure(procedure: Rc<Procedure>, env: Rc<RefCell<Env>>) -> Expr {
+    gc::lambda(Lambda { procedure, env })
+}
+
+fn define_record_type(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    for (name, value) in record::define(list)? {
+        env.borrow_mut().insert(name, value);
+    }
+
+    Ok(Expr::Unspecified)
+}
+
+pub fn apply(proc: &Expr, args: Vec<Expr>, env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    match proc {
+        Expr::Lambda(_) => run(Step::Apply(proc.clone(), args, env)),
         Expr::Builtin(builtin) => {
             builtin(&args, env)
-        }
+        },
+        Expr::Closure(closure) => vm::apply(closure, args),
+        Expr::RecordProc(proc) => record::apply(proc, args),
         _ => Err("unable to apply"),
     }
 }
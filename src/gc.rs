@@ -0,0 +1,294 @@
+use crate::ast::{Expr, Lambda};
+use crate::env::Env;
+use crate::vm::{Closure, Frame};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// Heap objects are reference counted, which frees everything except
+/// cycles, like a procedure stored in the environment it closes over. The
+/// collector finds those by trial deletion: it counts the references each
+/// tracked object receives from other tracked objects, and any object with
+/// more references than that is held from outside the heap, by a running
+/// procedure or a Rust value. Objects not reachable from one of those are
+/// garbage, and emptying them breaks their cycles so they are freed.
+///
+/// Environments, vectors and procedures are tracked. Cycles through hash
+/// tables, records or mappings are not found, since what they refer to is
+/// treated as held from outside.
+enum Object {
+    Env(Weak<RefCell<Env>>),
+    Vector(Weak<RefCell<Vec<Expr>>>),
+    Lambda(Weak<Lambda>),
+    Closure(Weak<Closure>),
+    Frame(Weak<Frame>),
+}
+
+enum Live {
+    Env(Rc<RefCell<Env>>),
+    Vector(Rc<RefCell<Vec<Expr>>>),
+    Lambda(Rc<Lambda>),
+    Closure(Rc<Closure>),
+    Frame(Rc<Frame>),
+}
+
+struct Heap {
+    objects: Vec<Object>,
+    threshold: usize,
+    collections: usize,
+    freed: usize,
+}
+
+/// Collection runs once this many objects have been tracked, and after
+/// that once the heap doubles in size.
+const INITIAL_THRESHOLD: usize = 10_000;
+
+thread_local! {
+    static HEAP: RefCell<Heap> = const { RefCell::new(Heap {
+        objects: Vec::new(),
+        threshold: INITIAL_THRESHOLD,
+        collections: 0,
+        freed: 0,
+    }) };
+}
+
+/// Collects the heap objects an object refers to directly.
+#[derive(Default)]
+pub struct Tracer {
+    children: Vec<usize>,
+}
+
+pub trait Trace {
+    fn trace(&self, tracer: &mut Tracer);
+}
+
+fn address<T>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+impl Tracer {
+    pub fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Vector(v) => self.children.push(address(v)),
+            Expr::Lambda(l) => self.children.push(address(l)),
+            Expr::Closure(c) => self.children.push(address(c)),
+            Expr::Environment(e) => self.env(e),
+            Expr::List(l) | Expr::Values(l) => {
+                for expr in l {
+                    self.expr(expr);
+                }
+            },
+            Expr::DottedPair(car, cdr) => {
+                for expr in car {
+                    self.expr(expr);
+                }
+                self.expr(cdr);
+            },
+            _ => (),
+        }
+    }
+
+    pub fn env(&mut self, env: &Rc<RefCell<Env>>) {
+        self.children.push(address(env));
+    }
+
+    pub fn frame(&mut self, frame: &Rc<Frame>) {
+        self.children.push(address(frame));
+    }
+}
+
+impl Object {
+    fn upgrade(&self) -> Option<Live> {
+        match self {
+            Object::Env(e) => e.upgrade().map(Live::Env),
+            Object::Vector(v) => v.upgrade().map(Live::Vector),
+            Object::Lambda(l) => l.upgrade().map(Live::Lambda),
+            Object::Closure(c) => c.upgrade().map(Live::Closure),
+            Object::Frame(f) => f.upgrade().map(Live::Frame),
+        }
+    }
+}
+
+impl Live {
+    fn downgrade(&self) -> Object {
+        match self {
+            Live::Env(e) => Object::Env(Rc::downgrade(e)),
+            Live::Vector(v) => Object::Vector(Rc::downgrade(v)),
+            Live::Lambda(l) => Object::Lambda(Rc::downgrade(l)),
+            Live::Closure(c) => Object::Closure(Rc::downgrade(c)),
+            Live::Frame(f) => Object::Frame(Rc::downgrade(f)),
+        }
+    }
+
+    fn address(&self) -> usize {
+        match self {
+            Live::Env(e) => address(e),
+            Live::Vector(v) => address(v),
+            Live::Lambda(l) => address(l),
+            Live::Closure(c) => address(c),
+            Live::Frame(f) => address(f),
+        }
+    }
+
+    /// References other than the collector's own.
+    fn references(&self) -> usize {
+        let count = match self {
+            Live::Env(e) => Rc::strong_count(e),
+            Live::Vector(v) => Rc::strong_count(v),
+            Live::Lambda(l) => Rc::strong_count(l),
+            Live::Closure(c) => Rc::strong_count(c),
+            Live::Frame(f) => Rc::strong_count(f),
+        };
+        count - 1
+    }
+
+    /// Returns false for an object that is being changed, which is left
+    /// alone and treated as held from outside.
+    fn trace(&self, tracer: &mut Tracer) -> bool {
+        match self {
+            Live::Env(e) => match e.try_borrow() {
+                Ok(e) => e.trace(tracer),
+                Err(_) => return false,
+            },
+            Live::Vector(v) => match v.try_borrow() {
+                Ok(v) => {
+                    for expr in v.iter() {
+                        tracer.expr(expr);
+                    }
+                },
+                Err(_) => return false,
+            },
+            Live::Lambda(l) => tracer.env(&l.env),
+            Live::Closure(c) => c.trace(tracer),
+            Live::Frame(f) => f.trace(tracer),
+        }
+        true
+    }
+
+    /// Empties a garbage object. What it held is returned rather than
+    /// dropped, so nothing is freed while the heap is being swept.
+    fn clear(&self, dropped: &mut Vec<Expr>) {
+        match self {
+            Live::Env(e) => {
+                if let Ok(mut e) = e.try_borrow_mut() {
+                    dropped.extend(e.clear());
+                }
+            },
+            Live::Vector(v) => {
+                if let Ok(mut v) = v.try_borrow_mut() {
+                    dropped.append(&mut v);
+                }
+            },
+            Live::Frame(f) => dropped.extend(f.clear()),
+            Live::Lambda(_) | Live::Closure(_) => (),
+        }
+    }
+}
+
+fn track(object: Object) {
+    let full = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.push(object);
+        heap.objects.len() > heap.threshold
+    });
+
+    if full {
+        collect();
+    }
+}
+
+pub fn env(env: Env) -> Rc<RefCell<Env>> {
+    let env = Rc::new(RefCell::new(env));
+    track(Object::Env(Rc::downgrade(&env)));
+    env
+}
+
+pub fn vector(elements: Vec<Expr>) -> Expr {
+    let vector = Rc::new(RefCell::new(elements));
+    track(Object::Vector(Rc::downgrade(&vector)));
+    Expr::Vector(vector)
+}
+
+pub fn lambda(lambda: Lambda) -> Expr {
+    let lambda = Rc::new(lambda);
+    track(Object::Lambda(Rc::downgrade(&lambda)));
+    Expr::Lambda(lambda)
+}
+
+pub fn closure(closure: Closure) -> Expr {
+    let closure = Rc::new(closure);
+    track(Object::Closure(Rc::downgrade(&closure)));
+    Expr::Closure(closure)
+}
+
+pub fn frame(frame: Frame) -> Rc<Frame> {
+    let frame = Rc::new(frame);
+    track(Object::Frame(Rc::downgrade(&frame)));
+    frame
+}
+
+/// Frees unreachable cycles, returning how many objects were freed.
+pub fn collect() -> usize {
+    let objects = HEAP.with(|heap| std::mem::take(&mut heap.borrow_mut().objects));
+    let live: Vec<Live> = objects.iter().filter_map(Object::upgrade).collect();
+    drop(objects);
+
+    let index: HashMap<usize, usize> = live.iter().enumerate().map(|(i, object)| (object.address(), i)).collect();
+    let mut internal = vec![0; live.len()];
+    let mut children = Vec::with_capacity(live.len());
+    let mut roots = Vec::new();
+    for (i, object) in live.iter().enumerate() {
+        let mut tracer = Tracer::default();
+        if !object.trace(&mut tracer) {
+            roots.push(i);
+        }
+
+        let edges: Vec<usize> = tracer.children.iter().filter_map(|child| index.get(child).copied()).collect();
+        for &child in &edges {
+            internal[child] += 1;
+        }
+        children.push(edges);
+    }
+
+    roots.extend((0..live.len()).filter(|&i| live[i].references() > internal[i]));
+    let mut reachable = vec![false; live.len()];
+    while let Some(i) = roots.pop() {
+        if reachable[i] { continue }
+        reachable[i] = true;
+        roots.extend(&children[i]);
+    }
+
+    let mut dropped = Vec::new();
+    let mut survivors = Vec::new();
+    for (object, reachable) in live.iter().zip(&reachable) {
+        if *reachable {
+            survivors.push(object.downgrade());
+        } else {
+            object.clear(&mut dropped);
+        }
+    }
+    let freed = live.len() - survivors.len();
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        survivors.append(&mut heap.objects);
+        heap.objects = survivors;
+        heap.threshold = INITIAL_THRESHOLD.max(2 * heap.objects.len());
+        heap.collections += 1;
+        heap.freed += freed;
+    });
+
+    drop(dropped);
+    drop(live);
+    freed
+}
+
+/// How many collections have run, how many objects are tracked, and how
+/// many have been freed by the collector.
+pub fn stats() -> (usize, usize, usize) {
+    HEAP.with(|heap| {
+        let heap = heap.borrow();
+        (heap.collections, heap.objects.len(), heap.freed)
+    })
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Literal};
+use crate::env::Env;
+use crate::eval;
+
+/// A mutable hash table keyed on arbitrary expressions.
+///
+/// Entries are kept in a vector so iteration order is stable, with an index
+/// from hash value to entry positions. Tables created without procedures
+/// compare keys with `equal?` and hash them with `Hash for Expr`; otherwise
+/// the given Scheme procedures are applied.
+#[derive(Clone, Default)]
+pub struct HashTable {
+    equiv: Option<Expr>,
+    hash: Option<Expr>,
+    entries: Vec<(u64, Expr, Expr)>,
+    index: HashMap<u64, Vec<usize>>,
+}
+
+/// Hashes an expression consistently with `equal?`.
+pub fn hash_expr(expr: &Expr) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    expr.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Hashes an expression consistently with `eqv?`: by identity for vectors,
+/// bytevectors and records, which can change after they are used as keys.
+pub fn hash_eqv(expr: &Expr) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match expr {
+        Expr::Vector(v) => Rc::as_ptr(v).hash(&mut hasher),
+        Expr::Bytevector(b) => Rc::as_ptr(b).hash(&mut hasher),
+        Expr::Record(r) => Rc::as_ptr(r).hash(&mut hasher),
+        _ => expr.hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+impl HashTable {
+    pub fn new(equiv: Option<Expr>, hash: Option<Expr>) -> HashTable {
+        HashTable { equiv, hash, ..Default::default() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn hash_of(&self, key: &Expr, env: Rc<RefCell<Env>>) -> Result<u64, &'static str> {
+        match &self.hash {
+            Some(proc) => {
+                match eval::apply(proc, vec![key.clone()], env)? {
+                    Expr::Literal(Literal::Number(n)) => Ok(n as u64),
+                    _ => Err("hash function must return an integer"),
+                }
+            },
+            None => Ok(hash_expr(key)),
+        }
+    }
+
+    /// Finds the position of `key` among the entries sharing `hash`.
+    pub fn position(&self,
+                    key: &Expr,
+                    hash: u64,
+                    env: Rc<RefCell<Env>>) -> Result<Option<usize>, &'static str> {
+        let candidates = match self.index.get(&hash) {
+            Some(candidates) => candidates,
+            None => return Ok(None),
+        };
+
+        for i in candidates.iter() {
+            let (_, k, _) = &self.entries[*i];
+            let same = match &self.equiv {
+                Some(proc) => !eval::apply(proc, vec![k.clone(), key.clone()], env.clone())?.is_false(),
+                None => k == key,
+            };
+            if same { return Ok(Some(*i)) }
+        }
+
+        Ok(None)
+    }
+
+    pub fn get(&self, key: &Expr, env: Rc<RefCell<Env>>) -> Result<Option<Expr>, &'static str> {
+        let hash = self.hash_of(key, env.clone())?;
+        let pos = self.position(key, hash, env)?;
+        Ok(pos.map(|i| self.entries[i].2.clone()))
+    }
+
+    /// Stores `value` at `pos` if the key is present, otherwise appends a
+    /// new entry. `pos` must come from `position` with no intervening change.
+    pub fn insert_at(&mut self, pos: Option<usize>, hash: u64, key: Expr, value: Expr) {
+        match pos {
+            Some(i) => self.entries[i].2 = value,
+            None => {
+                self.index.entry(hash).or_default().push(self.entries.len());
+                self.entries.push((hash, key, value));
+            },
+        }
+    }
+
+    /// Removes the entry at `i`, which must be a valid position.
+    pub fn remove_at(&mut self, i: usize) {
+        let hash = self.entries[i].0;
+        self.unindex(hash, i);
+
+        let last = self.entries.len() - 1;
+        if i != last {
+            let moved = self.entries[last].0;
+            self.unindex(moved, last);
+            self.index.entry(moved).or_default().push(i);
+        }
+        self.entries.swap_remove(i);
+    }
+
+    fn unindex(&mut self, hash: u64, i: usize) {
+        if let Some(positions) = self.index.get_mut(&hash) {
+            positions.retain(|p| *p != i);
+            if positions.is_empty() {
+                self.index.remove(&hash);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    pub fn entries(&self) -> Vec<(Expr, Expr)> {
+        self.entries.iter().map(|(_, k, v)| (k.clone(), v.clone())).collect()
+    }
+}
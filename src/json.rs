@@ -0,0 +1,480 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Literal};
+use crate::gc;
+use crate::env::Env;
+use crate::hashtable::HashTable;
+use crate::library;
+use crate::port::Port;
+use crate::symbol::Symbol;
+
+/// How JSON objects are represented when read. Either way the keys are
+/// symbols.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ObjectType {
+    Alist,
+    HashTable,
+}
+
+/// Arrays and objects nested deeper than this are rejected rather than
+/// exhausting the stack.
+const MAX_DEPTH: usize = 512;
+
+/// Reads the next JSON value from a textual input port, leaving the rest of
+/// the port unread. Returns the eof object when only whitespace remains, so
+/// concatenated or newline-delimited documents can be read one at a time.
+///
+/// Arrays become vectors, `null` becomes the symbol `null`, and integers
+/// that fit a fixnum become integers while every other number is a float.
+///
+/// Errors give the line and column they were found at, counted from where
+/// reading started.
+pub fn read(port: &Port, objects: ObjectType, env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let mut reader = Reader::new(port, objects, env);
+    if !reader.skip_whitespace()? {
+        return Ok(Expr::Eof);
+    }
+    reader.value()
+}
+
+/// Like `read`, but the value must be all that remains on the port apart
+/// from whitespace.
+pub fn read_all(port: &Port, objects: ObjectType, env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let mut reader = Reader::new(port, objects, env);
+    if !reader.skip_whitespace()? {
+        return Ok(Expr::Eof);
+    }
+    let value = reader.value()?;
+    if reader.skip_whitespace()? {
+        return Err(reader.error("unexpected text after value"));
+    }
+    Ok(value)
+}
+
+/// Reads every JSON value remaining on the port, passing each to `f`. The
+/// elements of a top-level array are passed one at a time as they are read
+/// instead of the array itself, so a large array never has to be held in
+/// memory at once.
+pub fn for_each(port: &Port,
+                objects: ObjectType,
+                env: Rc<RefCell<Env>>,
+                f: &mut dyn FnMut(Expr) -> Result<(), &'static str>) -> Result<(), &'static str> {
+    let mut reader = Reader::new(port, objects, env);
+    while reader.skip_whitespace()? {
+        if reader.peek()? != Some('[') {
+            f(reader.value()?)?;
+            continue;
+        }
+
+        reader.next()?;
+        reader.depth += 1;
+        if reader.sequence(']')? {
+            loop {
+                f(reader.value()?)?;
+                if !reader.separator(']', "expected ',' or ']' in array")? {
+                    break;
+                }
+            }
+        }
+        reader.depth -= 1;
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    port: &'a Port,
+    objects: ObjectType,
+    env: Rc<RefCell<Env>>,
+    depth: usize,
+    // Where the next character is.
+    line: usize,
+    column: usize,
+}
+
+impl Reader<'_> {
+    fn new(port: &Port, objects: ObjectType, env: Rc<RefCell<Env>>) -> Reader<'_> {
+        Reader { port, objects, env, depth: 0, line: 1, column: 1 }
+    }
+
+    /// An error at the next character.
+    fn error(&self, message: &str) -> &'static str {
+        library::message(format!("invalid JSON at line {}, column {}: {}", self.line, self.column, message))
+    }
+
+    fn peek(&self) -> Result<Option<char>, &'static str> {
+        self.port.peek_char()
+    }
+
+    /// Peeks, failing at end of input.
+    fn peek_some(&self) -> Result<char, &'static str> {
+        self.peek()?.ok_or_else(|| self.error("unexpected end of input"))
+    }
+
+    fn next(&mut self) -> Result<char, &'static str> {
+        let c = self.peek_some()?;
+        self.port.read_char()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Ok(c)
+    }
+
+    /// Skips whitespace. Returns false at end of input.
+    fn skip_whitespace(&mut self) -> Result<bool, &'static str> {
+        loop {
+            match self.peek()? {
+                Some(' ' | '\t' | '\n' | '\r') => { self.next()?; },
+                Some(_) => return Ok(true),
+                None => return Ok(false),
+            }
+        }
+    }
+
+    /// Skips whitespace, failing at end of input.
+    fn skip_to_token(&mut self) -> Result<char, &'static str> {
+        self.skip_whitespace()?;
+        self.peek_some()
+    }
+
+    fn value(&mut self) -> Result<Expr, &'static str> {
+        match self.skip_to_token()? {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' => {
+                self.next()?;
+                Ok(Expr::Literal(Literal::String(self.string()?)))
+            },
+            '-' | '0'..='9' => self.number(),
+            't' => self.literal("true", Expr::Literal(Literal::Bool(true))),
+            'f' => self.literal("false", Expr::Literal(Literal::Bool(false))),
+            'n' => self.literal("null", Expr::Symbol(Symbol::intern("null"))),
+            _ => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Expr) -> Result<Expr, &'static str> {
+        for expected in word.chars() {
+            if self.peek_some()? != expected {
+                return Err(self.error("unknown literal"));
+            }
+            self.next()?;
+        }
+        Ok(value)
+    }
+
+    /// Starts a sequence after its opening bracket. Returns false if it is
+    /// empty, having consumed the closing bracket.
+    fn sequence(&mut self, close: char) -> Result<bool, &'static str> {
+        if self.depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        if self.skip_to_token()? == close {
+            self.next()?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Reads the comma between elements or the closing bracket. Returns
+    /// false at the end of the sequence.
+    fn separator(&mut self, close: char, error: &'static str) -> Result<bool, &'static str> {
+        match self.skip_to_token()? {
+            ',' => {
+                self.next()?;
+                Ok(true)
+            },
+            c if c == close => {
+                self.next()?;
+                Ok(false)
+            },
+            _ => Err(self.error(error)),
+        }
+    }
+
+    fn array(&mut self) -> Result<Expr, &'static str> {
+        self.next()?;
+        self.depth += 1;
+        let mut elements = Vec::new();
+        if self.sequence(']')? {
+            loop {
+                elements.push(self.value()?);
+                if !self.separator(']', "expected ',' or ']' in array")? {
+                    break;
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(gc::vector(elements))
+    }
+
+    fn object(&mut self) -> Result<Expr, &'static str> {
+        self.next()?;
+        self.depth += 1;
+        let mut members = Vec::new();
+        if self.sequence('}')? {
+            loop {
+                if self.skip_to_token()? != '"' {
+                    return Err(self.error("expected a string as object key"));
+                }
+                self.next()?;
+                let key = Expr::Symbol(Symbol::intern(&self.string()?));
+                if self.skip_to_token()? != ':' {
+                    return Err(self.error("expected ':' after object key"));
+                }
+                self.next()?;
+                members.push((key, self.value()?));
+                if !self.separator('}', "expected ',' or '}' in object")? {
+                    break;
+                }
+            }
+        }
+        self.depth -= 1;
+
+        match self.objects {
+            ObjectType::Alist => {
+                Ok(Expr::List(members.into_iter().map(|(key, value)| pair(key, value)).collect()))
+            },
+            ObjectType::HashTable => {
+                // Later duplicates of a key replace earlier ones.
+                let mut table = HashTable::new(None, None);
+                for (key, value) in members {
+                    let hash = table.hash_of(&key, self.env.clone())?;
+                    let pos = table.position(&key, hash, self.env.clone())?;
+                    table.insert_at(pos, hash, key, value);
+                }
+                Ok(Expr::HashTable(Rc::new(RefCell::new(table))))
+            },
+        }
+    }
+
+    /// Reads a string after its opening quote.
+    fn string(&mut self) -> Result<String, &'static str> {
+        let mut s = String::new();
+        loop {
+            match self.peek_some()? {
+                '"' => {
+                    self.next()?;
+                    return Ok(s)
+                },
+                '\\' => {
+                    self.next()?;
+                    let c = match self.peek_some()? {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\x08',
+                        'f' => '\x0c',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            self.next()?;
+                            s.push(self.unicode_escape()?);
+                            continue;
+                        },
+                        _ => return Err(self.error("unknown escape in string")),
+                    };
+                    self.next()?;
+                    s.push(c);
+                },
+                c if (c as u32) < 0x20 => return Err(self.error("unescaped control character in string")),
+                _ => s.push(self.next()?),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, &'static str> {
+        let mut n = 0;
+        for _ in 0..4 {
+            let digit = self.peek_some()?.to_digit(16).ok_or_else(|| self.error("expected four hex digits after \\u"))?;
+            self.next()?;
+            n = n * 16 + digit;
+        }
+        Ok(n)
+    }
+
+    /// Decodes the escape after `\u`, combining a surrogate pair.
+    fn unicode_escape(&mut self) -> Result<char, &'static str> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("unpaired surrogate in string"));
+        }
+        for expected in ['\\', 'u'] {
+            if self.peek_some()? != expected {
+                return Err(self.error("unpaired surrogate in string"));
+            }
+            self.next()?;
+        }
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(self.error("unpaired surrogate in string"));
+        }
+        Ok(char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)).unwrap())
+    }
+
+    fn digits(&mut self, text: &mut String) -> Result<usize, &'static str> {
+        let mut count = 0;
+        while let Some(c) = self.peek()? && c.is_ascii_digit() {
+            text.push(self.next()?);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn number(&mut self) -> Result<Expr, &'static str> {
+        let mut text = String::new();
+        if self.peek()? == Some('-') {
+            text.push(self.next()?);
+        }
+
+        match self.peek()? {
+            Some('0') => text.push(self.next()?),
+            Some('1'..='9') => { self.digits(&mut text)?; },
+            _ => return Err(self.error("malformed number")),
+        }
+
+        let mut integer = true;
+        if self.peek()? == Some('.') {
+            integer = false;
+            text.push(self.next()?);
+            if self.digits(&mut text)? == 0 {
+                return Err(self.error("malformed number"));
+            }
+        }
+        if let Some('e' | 'E') = self.peek()? {
+            integer = false;
+            text.push(self.next()?);
+            if let Some('+' | '-') = self.peek()? {
+                text.push(self.next()?);
+            }
+            if self.digits(&mut text)? == 0 {
+                return Err(self.error("malformed number"));
+            }
+        }
+
+        if let Some(c) = self.peek()? && (c.is_ascii_alphanumeric() || c == '.') {
+            return Err(self.error("malformed number"));
+        }
+
+        if integer && let Ok(n) = text.parse::<i32>() {
+            return Ok(Expr::Literal(Literal::Number(n)));
+        }
+        match text.parse::<f64>() {
+            Ok(f) if f.is_finite() => Ok(Expr::Literal(Literal::Float(f))),
+            _ => Err(self.error("number out of range")),
+        }
+    }
+}
+
+/// Builds an association as `cons` would.
+fn pair(key: Expr, value: Expr) -> Expr {
+    match value {
+        Expr::List(mut l) => {
+            l.insert(0, key);
+            Expr::List(l)
+        },
+        Expr::DottedPair(mut l, end) => {
+            l.insert(0, key);
+            Expr::DottedPair(l, end)
+        },
+        value => Expr::DottedPair(vec![key], Box::new(value)),
+    }
+}
+
+/// Splits an association back into its key and value, as `car` and `cdr`
+/// would.
+fn unpair(expr: &Expr) -> Option<(Expr, Expr)> {
+    match expr {
+        Expr::List(l) if !l.is_empty() => Some((l[0].clone(), Expr::List(l[1..].to_vec()))),
+        Expr::DottedPair(l, end) if l.len() == 1 => Some((l[0].clone(), (**end).clone())),
+        Expr::DottedPair(l, end) => Some((l[0].clone(), Expr::DottedPair(l[1..].to_vec(), end.clone()))),
+        _ => None,
+    }
+}
+
+/// Writes a value as compact JSON. Vectors become arrays; association
+/// lists and hash tables with symbol or string keys become objects, the
+/// empty list being the empty object; and the symbol `null` becomes `null`.
+pub fn write(expr: &Expr) -> Result<String, &'static str> {
+    let mut out = String::new();
+    write_value(expr, &mut out, 0)?;
+    Ok(out)
+}
+
+fn write_value(expr: &Expr, out: &mut String, depth: usize) -> Result<(), &'static str> {
+    if depth > MAX_DEPTH {
+        return Err("cannot write JSON nested this deeply");
+    }
+
+    match expr {
+        Expr::Literal(Literal::Bool(b)) => out.push_str(if *b { "true" } else { "false" }),
+        Expr::Literal(Literal::Number(n)) => out.push_str(&n.to_string()),
+        Expr::Literal(Literal::Float(f)) => {
+            if !f.is_finite() {
+                return Err("cannot write NaN or infinity as JSON");
+            }
+            out.push_str(&format!("{}", Literal::Float(*f)));
+        },
+        Expr::Literal(Literal::String(s)) => write_string(s, out),
+        Expr::Symbol(s) if &*s.name() == "null" => out.push_str("null"),
+        Expr::Vector(v) => {
+            out.push('[');
+            for (i, e) in v.borrow().iter().enumerate() {
+                if i > 0 { out.push(',') }
+                write_value(e, out, depth + 1)?;
+            }
+            out.push(']');
+        },
+        Expr::List(l) => {
+            let members = l.iter()
+                .map(unpair)
+                .collect::<Option<Vec<_>>>()
+                .ok_or("cannot write a list that is not an association list as JSON")?;
+            write_object(&members, out, depth)?;
+        },
+        Expr::HashTable(h) => {
+            let members = h.borrow().entries();
+            write_object(&members, out, depth)?;
+        },
+        _ => return Err("cannot write this value as JSON"),
+    }
+    Ok(())
+}
+
+fn write_object(members: &[(Expr, Expr)], out: &mut String, depth: usize) -> Result<(), &'static str> {
+    out.push('{');
+    for (i, (key, value)) in members.iter().enumerate() {
+        if i > 0 { out.push(',') }
+        match key {
+            Expr::Symbol(s) => write_string(&s.name(), out),
+            Expr::Literal(Literal::String(s)) => write_string(s, out),
+            _ => return Err("JSON object keys must be symbols or strings"),
+        }
+        out.push(':');
+        write_value(value, out, depth + 1)?;
+    }
+    out.push('}');
+    Ok(())
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\x08' => out.push_str("\\b"),
+            '\x0c' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -1,4 +1,19 @@
-use crate::ast::{Literal, Token, TokenType};
+use crate::ast::{char_from_name, Literal, Token, TokenType};
+
+use std::fmt;
+
+/// A token the lexer could not read, with where it was found.
+pub struct LexError {
+    pub line: u32,
+    pub lexeme: String,
+    pub message: &'static str,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error at line {}, symbol '{}': {}", self.line, self.lexeme, self.message)
+    }
+}
 
 pub struct Lexer {
     pub source: Vec<char>,
@@ -6,6 +21,7 @@ pub struct Lexer {
     start: usize,
     current: usize,
     line: u32,
+    pub errors: Vec<LexError>,
 }
 
 impl Lexer {
@@ -17,6 +33,7 @@ impl Lexer {
             start: 0,
             current: 0,
             line: 1,
+            errors: Vec::new(),
         }
     }
 
@@ -35,7 +52,9 @@ impl Lexer {
             '(' => self.add_token(TokenType::LParen),
             ')' => self.add_token(TokenType::RParen),
             '.' => {
-                if Lexer::is_ident(self.peek()) {
+                if self.starts_number(n) {
+                    self.number()
+                } else if Lexer::is_ident(self.peek()) {
                     self.identifier()
                 } else {
                     self.add_token(TokenType::Dot)
@@ -48,6 +67,22 @@ impl Lexer {
                     self.add_literal_token(TokenType::Bool, Some(Literal::Bool(true)))
                 } else if self.match_char('f') {
                     self.add_literal_token(TokenType::Bool, Some(Literal::Bool(false)))
+                } else if self.match_char('\\') {
+                    self.character()
+                } else if self.match_char('(') {
+                    self.add_token(TokenType::Vector)
+                } else if Lexer::is_digit(self.peek()) {
+                    self.datum_label()
+                } else if "xXoObBdDeEiI".contains(self.peek()) {
+                    self.number()
+                } else if self.peek() == 'u' && self.peek_next() == '8' {
+                    self.advance();
+                    self.advance();
+                    if self.match_char('(') {
+                        self.add_token(TokenType::Bytevector)
+                    } else {
+                        self.error("expecting ( after #u8");
+                    }
                 }
             },
             ';'  => {
@@ -59,8 +94,9 @@ impl Lexer {
             '\r' => (),
             '\n' => self.line += 1,
             '"' => self.string(),
+            '|' => self.bar_identifier(),
             _   => {
-                if Lexer::is_digit(n) {
+                if Lexer::is_digit(n) || self.starts_number(n) {
                     self.number();
                 } else if Lexer::is_ident(n) {
                     self.identifier();
@@ -72,7 +108,7 @@ impl Lexer {
     }
 
     fn is_digit(c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
     }
 
     fn is_dot(c: char) -> bool {
@@ -101,56 +137,95 @@ impl Lexer {
             c == '~'
     }
 
-    fn float(&mut self) {
-        while Lexer::is_digit(self.peek()) {
-            self.advance();
-        }
+    fn is_delimiter(c: char) -> bool {
+        c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == ';' || c == '\'' || c == '\0'
+    }
 
-        let slice: Vec<char> = self.source[self.start..self.current].to_vec();
-        let slice: String = slice.iter().collect();
-        let digit: f64 = match slice.parse() {
-            Ok(d) => d,
-            Err(_) => {
-                0.0
-            }
-        };
-        self.add_literal_token(TokenType::Float, Some(Literal::Float(digit)));
+    /// Whether the characters at the cursor continue a numeral after a sign
+    /// or leading dot.
+    fn starts_number(&mut self, c: char) -> bool {
+        match c {
+            '+' | '-' => {
+                Lexer::is_digit(self.peek()) ||
+                    (Lexer::is_dot(self.peek()) && Lexer::is_digit(self.peek_next()))
+            },
+            '.' => Lexer::is_digit(self.peek()),
+            _ => false,
+        }
     }
 
     fn number(&mut self) {
-        while Lexer::is_digit(self.peek()) {
+        while !Lexer::is_delimiter(self.peek()) {
             self.advance();
         }
-        if Lexer::is_dot(self.peek()) {
+
+        let slice: String = self.source[self.start..self.current].iter().collect();
+        match parse_number(&slice, 10) {
+            Some(n @ Literal::Float(_)) => self.add_literal_token(TokenType::Float, Some(n)),
+            Some(n) => self.add_literal_token(TokenType::Number, Some(n)),
+            None => self.error("malformed numeral"),
+        }
+    }
+
+    fn identifier(&mut self) {
+        while Lexer::is_ident(self.peek()) || Lexer::is_digit(self.peek()) {
             self.advance();
-            return self.float();
         }
 
-        let slice: Vec<char> = self.source[self.start..self.current].to_vec();
-        let slice: String = slice.iter().collect();
-        let digit: i32 = match slice.parse() {
-            Ok(d) => d,
-            Err(_) => {
-                0
-            }
-        };
-        self.add_literal_token(TokenType::Number, Some(Literal::Number(digit)))
+        // +inf.0, -inf.0, +nan.0 and -nan.0 look like identifiers.
+        let slice: String = self.source[self.start..self.current].iter().collect();
+        match parse_number(&slice, 10) {
+            Some(n) => self.add_literal_token(TokenType::Float, Some(n)),
+            None => self.add_token(TokenType::Identifier),
+        }
     }
 
-    fn identifier(&mut self) {
+    fn character(&mut self) {
+        if self.is_at_end() {
+            self.error("expecting a character after #\\");
+            return;
+        }
+
+        // The first character is taken as is, so #\( and #\space both work.
+        self.advance();
         while Lexer::is_ident(self.peek()) || Lexer::is_digit(self.peek()) {
             self.advance();
         }
 
-        self.add_token(TokenType::Identifier)
+        let slice: String = self.source[self.start + 2..self.current].iter().collect();
+        let c = if slice.chars().count() == 1 {
+            slice.chars().next()
+        } else if let Some(c) = char_from_name(&slice) {
+            Some(c)
+        } else if let Some(hex) = slice.strip_prefix('x') {
+            u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+        } else {
+            None
+        };
+
+        match c {
+            Some(c) => self.add_literal_token(TokenType::Char, Some(Literal::Char(c))),
+            None => self.error("unknown character name"),
+        }
     }
 
     fn string(&mut self) {
+        let mut string = String::new();
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.error("unterminated string.");
+            let c = self.advance();
+            match c {
+                '\\' => match self.escape('"') {
+                    Some(c) => string.push(c),
+                    None => if !self.line_continuation() {
+                        self.error("unknown escape in string");
+                    },
+                },
+                '\n' => {
+                    self.line += 1;
+                    string.push(c);
+                },
+                _ => string.push(c),
             }
-            self.advance();
         }
 
         if self.is_at_end() {
@@ -159,9 +234,100 @@ impl Lexer {
         }
 
         self.advance();
-        let slice: Vec<char> = self.source[self.start + 1..self.current - 1].to_vec();
-        let slice: String = slice.iter().collect();
-        self.add_literal_token(TokenType::String, Some(Literal::String(slice)));
+        self.add_literal_token(TokenType::String, Some(Literal::String(string)));
+    }
+
+    /// Reads a symbol written between vertical bars. The token's lexeme is
+    /// the symbol name with escapes resolved.
+    fn bar_identifier(&mut self) {
+        let mut name = String::new();
+        while self.peek() != '|' && !self.is_at_end() {
+            let c = self.advance();
+            match c {
+                '\\' => match self.escape('|') {
+                    Some(c) => name.push(c),
+                    None => self.error("unknown escape in symbol"),
+                },
+                '\n' => {
+                    self.line += 1;
+                    name.push(c);
+                },
+                _ => name.push(c),
+            }
+        }
+
+        if self.is_at_end() {
+            self.error("unterminated symbol.");
+            return;
+        }
+
+        self.advance();
+        self.tokens.push(Token::new(TokenType::Identifier, name, self.line, None));
+    }
+
+    /// Reads the escape following a backslash in a string or symbol.
+    /// `delimiter` is the quote character that may itself be escaped.
+    fn escape(&mut self, delimiter: char) -> Option<char> {
+        let c = match self.peek() {
+            'a' => '\x07',
+            'b' => '\x08',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            '\\' => '\\',
+            'x' => {
+                self.advance();
+                let mut hex = String::new();
+                while self.peek().is_ascii_hexdigit() {
+                    hex.push(self.advance());
+                }
+                if !self.match_char(';') { return None }
+                return u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+            },
+            c if c == delimiter => c,
+            _ => return None,
+        };
+        self.advance();
+        Some(c)
+    }
+
+    /// Skips a backslash at the end of a line together with the leading
+    /// whitespace of the next line.
+    fn line_continuation(&mut self) -> bool {
+        let start = self.current;
+        while self.peek() == ' ' || self.peek() == '\t' {
+            self.advance();
+        }
+        if !self.match_char('\n') {
+            self.current = start;
+            return false;
+        }
+
+        self.line += 1;
+        while self.peek() == ' ' || self.peek() == '\t' {
+            self.advance();
+        }
+        true
+    }
+
+    /// Reads a datum label, `#n=` or `#n#`, after the `#`.
+    fn datum_label(&mut self) {
+        while Lexer::is_digit(self.peek()) {
+            self.advance();
+        }
+
+        let slice: String = self.source[self.start + 1..self.current].iter().collect();
+        let n = match slice.parse() {
+            Ok(n) => Literal::Number(n),
+            Err(_) => return self.error("datum label is too large"),
+        };
+        if self.match_char('=') {
+            self.add_literal_token(TokenType::LabelDef, Some(n))
+        } else if self.match_char('#') {
+            self.add_literal_token(TokenType::LabelRef, Some(n))
+        } else {
+            self.error("expecting = or # after datum label")
+        }
     }
 
     fn advance(&mut self) -> char {
@@ -181,6 +347,14 @@ impl Lexer {
         self.source[self.current]
     }
 
+    fn peek_next(&mut self) -> char {
+        if self.current + 1 >= self.source.len() {
+            return '\0';
+        }
+
+        self.source[self.current + 1]
+    }
+
     fn match_char(&mut self, expected: char) -> bool {
         if self.is_at_end() {
             return false;
@@ -207,9 +381,94 @@ impl Lexer {
         self.tokens.push(Token::new(token, lexeme, self.line, literal));
     }
 
-    fn error(&mut self, message: &str) {
+    fn error(&mut self, message: &'static str) {
         let slice: Vec<char> = self.source[self.start..self.current].to_vec();
         let lexeme: String = slice.iter().collect();
-        println!("error at line {}, symbol '{}': {}", self.line, lexeme, message);
+        self.errors.push(LexError { line: self.line, lexeme, message });
+    }
+}
+
+/// Parses Scheme numeric syntax: an optional `#x`, `#o`, `#b` or `#d` radix
+/// prefix and `#e` or `#i` exactness prefix, in either order, then an integer in that radix or
+/// a decimal with optional exponent, or one of `+inf.0`, `-inf.0`, `+nan.0`.
+/// An integer too large for a fixnum is read as inexact, as arithmetic that
+/// overflows is. Returns `None` if `s` is not a numeral, or is an exact
+/// numeral that does not fit a fixnum.
+pub fn parse_number(s: &str, radix: u32) -> Option<Literal> {
+    let mut prefix = None;
+    let mut exact = None;
+    let mut s = s;
+    while let Some(rest) = s.strip_prefix('#') {
+        match rest.chars().next()?.to_ascii_lowercase() {
+            'x' if prefix.is_none() => prefix = Some(16),
+            'o' if prefix.is_none() => prefix = Some(8),
+            'b' if prefix.is_none() => prefix = Some(2),
+            'd' if prefix.is_none() => prefix = Some(10),
+            'e' if exact.is_none() => exact = Some(true),
+            'i' if exact.is_none() => exact = Some(false),
+            _ => return None,
+        }
+        s = &rest[1..];
+    }
+
+    let n = parse_real(s, prefix.unwrap_or(radix))?;
+    match (exact, n) {
+        (Some(true), Literal::Float(f)) => {
+            if f.fract() == 0.0 && f >= i32::MIN as f64 && f <= i32::MAX as f64 {
+                Some(Literal::Number(f as i32))
+            } else {
+                None
+            }
+        },
+        (Some(false), Literal::Number(n)) => Some(Literal::Float(n as f64)),
+        (_, n) => Some(n),
     }
 }
+
+fn parse_real(s: &str, radix: u32) -> Option<Literal> {
+    let body = s.strip_prefix(['+', '-']);
+    match body {
+        Some("inf.0") => {
+            let inf = if s.starts_with('-') { f64::NEG_INFINITY } else { f64::INFINITY };
+            return Some(Literal::Float(inf));
+        },
+        Some("nan.0") => return Some(Literal::Float(f64::NAN)),
+        _ => (),
+    }
+
+    let digits = body.unwrap_or(s);
+    if digits.is_empty() { return None }
+
+    if digits.chars().all(|c| c.is_digit(radix)) {
+        if let Ok(n) = i32::from_str_radix(s, radix) { return Some(Literal::Number(n)) }
+
+        let magnitude = match radix {
+            10 => digits.parse().ok()?,
+            _ => digits.chars().fold(0.0, |n, c| n * radix as f64 + c.to_digit(radix).unwrap() as f64),
+        };
+        return Some(Literal::Float(if s.starts_with('-') { -magnitude } else { magnitude }));
+    }
+
+    if radix != 10 { return None }
+
+    // digits [. digits] [e [sign] digits], with at least one mantissa digit
+    let (mantissa, exponent) = match digits.find(['e', 'E']) {
+        Some(i) => (&digits[..i], Some(&digits[i + 1..])),
+        None => (digits, None),
+    };
+    let mut parts = mantissa.splitn(2, '.');
+    let whole = parts.next().unwrap_or("");
+    let fraction = parts.next().unwrap_or("");
+    let valid_mantissa = whole.len() + fraction.len() > 0 &&
+        whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit());
+    let valid_exponent = match exponent {
+        Some(e) => {
+            let e = e.strip_prefix(['+', '-']).unwrap_or(e);
+            !e.is_empty() && e.chars().all(|c| c.is_ascii_digit())
+        },
+        None => true,
+    };
+    if !valid_mantissa || !valid_exponent { return None }
+
+    s.parse().ok().map(Literal::Float)
+}
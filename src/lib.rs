@@ -1,6 +1,23 @@
+pub mod analyze;
 pub mod ast;
 pub mod builtins;
+pub mod charset;
+pub mod compiler;
 pub mod env;
 pub mod eval;
+pub mod gc;
+pub mod hashtable;
+pub mod json;
 pub mod lexer;
+pub mod library;
+pub mod mapping;
 pub mod parser;
+pub mod port;
+pub mod printer;
+pub mod reader;
+pub mod record;
+pub mod regexp;
+pub mod sandbox;
+pub mod stdlib;
+pub mod symbol;
+pub mod vm;
@@ -0,0 +1,529 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Literal};
+use crate::env::Env;
+use crate::eval::eval;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::sandbox;
+use crate::stdlib;
+use crate::symbol;
+use crate::symbol::Symbol;
+
+/// A library created by `define-library`. Its body is evaluated once, in
+/// an environment of its own, and importers only see the exported names.
+///
+/// Imports are bindings rather than copies: an imported name refers to the
+/// library's variable, so a later `set!` of it inside the library is seen
+/// by every importer.
+pub struct Library {
+    env: Rc<RefCell<Env>>,
+    // Pairs of the name seen by importers and the name in `env`.
+    exports: Vec<(Symbol, Symbol)>,
+    // Exported special forms. These are known to the evaluator everywhere,
+    // so importing one binds nothing.
+    syntax: Vec<Symbol>,
+}
+
+/// An imported name and the library environment and name of the variable
+/// it refers to, which is `None` for a special form.
+type Binding = (Symbol, Option<(Rc<RefCell<Env>>, Symbol)>);
+
+enum Entry {
+    Loading,
+    Loaded(Rc<Library>),
+}
+
+thread_local! {
+    static LIBRARIES: RefCell<HashMap<String, Entry>> = RefCell::new(HashMap::new());
+    static SEARCH_PATH: RefCell<Vec<PathBuf>> = RefCell::new(default_search_path());
+    // The directories of the files being loaded, innermost last.
+    static LOADING: RefCell<Vec<PathBuf>> = const { RefCell::new(Vec::new()) };
+    // Every file loaded so far, by canonical path.
+    static LOADED: RefCell<HashSet<PathBuf>> = RefCell::new(HashSet::new());
+    // The files being loaded right now, by canonical path.
+    static ACTIVE: RefCell<HashSet<PathBuf>> = RefCell::new(HashSet::new());
+    static MESSAGES: RefCell<HashSet<&'static str>> = RefCell::new(HashSet::new());
+}
+
+/// The directories in `SLIPPY_PATH` followed by the current directory.
+pub fn default_search_path() -> Vec<PathBuf> {
+    let mut path: Vec<PathBuf> = match std::env::var_os("SLIPPY_PATH") {
+        Some(dirs) => std::env::split_paths(&dirs).collect(),
+        None => Vec::new(),
+    };
+    path.push(PathBuf::from("."));
+    path
+}
+
+/// Sets the directories searched for library files, in order.
+pub fn set_search_path(path: Vec<PathBuf>) {
+    SEARCH_PATH.with(|p| *p.borrow_mut() = path);
+}
+
+/// The feature identifiers recognized by `cond-expand`.
+pub fn features() -> Vec<&'static str> {
+    let mut features = vec!["r7rs", "full-unicode", "slippy", std::env::consts::OS];
+    if cfg!(unix) {
+        features.push("posix");
+    }
+    features
+}
+
+/// Resolves a path relative to the directory of the file being loaded, or
+/// the current directory at top level.
+pub fn resolve(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    match LOADING.with(|l| l.borrow().last().cloned()) {
+        Some(dir) => dir.join(path),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Prefixes an error with the file and line it occurred at.
+fn context(path: &Path, line: Option<u32>, err: &'static str) -> &'static str {
+    message(match line {
+        Some(line) => format!("{}:{}: {}", path.display(), line, err),
+        None => format!("{}: {}", path.display(), err),
+    })
+}
+
+/// Turns an error message built at run time into a static string.
+///
+/// Errors are static strings, so the message is leaked. Each distinct
+/// message is only leaked once, which bounds the cost to the number of
+/// different errors a session reports.
+pub fn message(message: String) -> &'static str {
+    MESSAGES.with(|m| {
+        let mut messages = m.borrow_mut();
+        match messages.get(message.as_str()) {
+            Some(message) => *message,
+            None => {
+                let message: &'static str = Box::leak(message.into_boxed_str());
+                messages.insert(message);
+                message
+            },
+        }
+    })
+}
+
+/// Reads and parses every expression in a file, with the line each one
+/// starts on.
+fn read_file(path: &Path) -> Result<Vec<(u32, Expr)>, &'static str> {
+    let source = fs::read_to_string(path).map_err(|_| context(path, None, "unable to read file"))?;
+
+    let mut lexer = Lexer::new(&source);
+    lexer.scan();
+    if let Some(err) = lexer.errors.first() { return Err(context(path, Some(err.line), err.message)) }
+
+    let mut parser = Parser::new(lexer.tokens);
+    parser.parse_with_lines().map_err(|err| context(path, Some(parser.line()), err))
+}
+
+/// Evaluates the contents of a file in `env`, stopping at the first error.
+fn eval_file(path: &Path, env: Rc<RefCell<Env>>) -> Result<(), &'static str> {
+    let exprs = read_file(path)?;
+    loading(path, || {
+        for (line, expr) in exprs.iter() {
+            eval(expr, env.clone()).map_err(|err| context(path, Some(*line), err))?;
+        }
+        Ok(())
+    })
+}
+
+/// Loads a source file into `env`. Relative paths resolve against the
+/// directory of the file being loaded. With `once`, a file that has
+/// already been loaded is skipped. A file that loads itself, directly or
+/// through others, would never finish and is an error.
+pub fn load(file: &str, env: Rc<RefCell<Env>>, once: bool) -> Result<(), &'static str> {
+    let path = resolve(file);
+    let canonical = fs::canonicalize(&path).map_err(|_| context(&path, None, "no such file"))?;
+    let first = LOADED.with(|l| l.borrow_mut().insert(canonical.clone()));
+    if once && !first {
+        return Ok(());
+    }
+    if !ACTIVE.with(|a| a.borrow_mut().insert(canonical.clone())) {
+        return Err(context(&path, None, "circular load"));
+    }
+
+    let result = eval_file(&path, env);
+    ACTIVE.with(|a| a.borrow_mut().remove(&canonical));
+    // A file that failed to load may be loaded again once it is fixed.
+    if result.is_err() && first {
+        LOADED.with(|l| l.borrow_mut().remove(&canonical));
+    }
+    result
+}
+
+/// Runs `f` with relative paths resolving against the directory of `path`.
+pub fn loading<T>(path: &Path, f: impl FnOnce() -> T) -> T {
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    LOADING.with(|l| l.borrow_mut().push(dir));
+    let result = f();
+    LOADING.with(|l| l.borrow_mut().pop());
+    result
+}
+
+/// Names a library by its parts joined with slashes, which is also where
+/// its file lives on the search path: `(foo bar)` is `foo/bar.sld`.
+fn library_key(name: &Expr) -> Result<String, &'static str> {
+    let parts = match name {
+        Expr::List(parts) if !parts.is_empty() => parts,
+        _ => return Err("invalid library name"),
+    };
+
+    let mut key = Vec::new();
+    for part in parts {
+        match part {
+            Expr::Var(s) | Expr::Symbol(s) => key.push(s.name().to_string()),
+            Expr::Literal(Literal::Number(n)) if *n >= 0 => key.push(n.to_string()),
+            _ => return Err("invalid library name"),
+        }
+    }
+    Ok(key.join("/"))
+}
+
+fn find_library_file(key: &str) -> Option<PathBuf> {
+    let file = format!("{}.sld", key);
+    SEARCH_PATH.with(|p| p.borrow().iter().map(|dir| dir.join(&file)).find(|path| path.is_file()))
+}
+
+/// Returns a library, loading it from the search path on first use.
+fn find_library(name: &Expr) -> Result<Rc<Library>, &'static str> {
+    let key = library_key(name)?;
+    let entry = LIBRARIES.with(|l| match l.borrow().get(&key) {
+        Some(Entry::Loaded(lib)) => Ok(Some(lib.clone())),
+        Some(Entry::Loading) => Err("circular library import"),
+        None => Ok(None),
+    })?;
+    if let Some(lib) = entry {
+        return Ok(lib);
+    }
+
+    if let Some(lib) = standard_library(&key) {
+        let lib = Rc::new(lib);
+        LIBRARIES.with(|l| l.borrow_mut().insert(key, Entry::Loaded(lib.clone())));
+        return Ok(lib);
+    }
+
+    let path = find_library_file(&key).ok_or("library not found")?;
+    eval_file(&path, Env::new())?;
+
+    LIBRARIES.with(|l| match l.borrow().get(&key) {
+        Some(Entry::Loaded(lib)) => Ok(lib.clone()),
+        _ => Err("library file does not define the library"),
+    })
+}
+
+/// Builds one of the R7RS standard libraries over the builtins.
+fn standard_library(key: &str) -> Option<Library> {
+    let names = stdlib::exports(key)?;
+    let env = Env::new();
+    let mut lib = Library { env: env.clone(), exports: Vec::new(), syntax: Vec::new() };
+    for name in names {
+        let name = Symbol::intern(name);
+        if env.borrow().get(name).is_some() {
+            lib.exports.push((name, name));
+        } else if name.is_syntax() {
+            lib.syntax.push(name);
+        }
+    }
+    Some(lib)
+}
+
+fn library_available(name: &Expr) -> Result<bool, &'static str> {
+    let key = library_key(name)?;
+    // Sandboxed programs may not look for files.
+    Ok(LIBRARIES.with(|l| l.borrow().contains_key(&key)) ||
+       stdlib::exports(&key).is_some() ||
+       (sandbox::permit().is_ok() && find_library_file(&key).is_some()))
+}
+
+/// `(define-library name declaration ...)`
+pub fn define_library(list: &[Expr]) -> Result<Expr, &'static str> {
+    if list.len() < 2 { return Err("invalid define-library syntax") }
+
+    let key = library_key(&list[1])?;
+    if let Some(Entry::Loading) = LIBRARIES.with(|l| l.borrow_mut().insert(key.clone(), Entry::Loading)) {
+        return Err("circular library import");
+    }
+
+    let lib = Library { env: Env::new(), exports: Vec::new(), syntax: Vec::new() };
+    match define_library_body(lib, &list[2..]) {
+        Ok(lib) => {
+            LIBRARIES.with(|l| l.borrow_mut().insert(key, Entry::Loaded(Rc::new(lib))));
+            Ok(Expr::Unspecified)
+        },
+        Err(err) => {
+            LIBRARIES.with(|l| l.borrow_mut().remove(&key));
+            Err(err)
+        },
+    }
+}
+
+fn define_library_body(mut lib: Library, decls: &[Expr]) -> Result<Library, &'static str> {
+    for decl in decls {
+        declaration(&mut lib, decl)?;
+    }
+
+    for (_, internal) in lib.exports.iter() {
+        if lib.env.borrow().get(*internal).is_none() {
+            return Err("library exports an undefined identifier");
+        }
+    }
+    Ok(lib)
+}
+
+fn declaration(lib: &mut Library, decl: &Expr) -> Result<(), &'static str> {
+    let (head, rest) = match decl {
+        Expr::List(l) if !l.is_empty() => (l[0].from_var(), &l[1..]),
+        _ => return Err("invalid library declaration"),
+    };
+
+    match head.map(|s| s.name()).as_deref() {
+        Some("export") => {
+            for spec in rest {
+                lib.exports.push(export_spec(spec)?);
+            }
+        },
+        Some("import") => {
+            for set in rest {
+                import_into(set, lib.env.clone())?;
+            }
+        },
+        Some("begin") => {
+            for expr in rest {
+                eval(expr, lib.env.clone())?;
+            }
+        },
+        Some("include") => {
+            for expr in included(rest, false)? {
+                eval(&expr, lib.env.clone())?;
+            }
+        },
+        Some("include-ci") => {
+            for expr in included(rest, true)? {
+                eval(&expr, lib.env.clone())?;
+            }
+        },
+        Some("include-library-declarations") => {
+            for decl in included(rest, false)? {
+                declaration(lib, &decl)?;
+            }
+        },
+        Some("cond-expand") => {
+            for decl in cond_expand_clause(rest)? {
+                declaration(lib, decl)?;
+            }
+        },
+        _ => return Err("invalid library declaration"),
+    }
+    Ok(())
+}
+
+/// Parses `name` or `(rename internal external)`.
+fn export_spec(spec: &Expr) -> Result<(Symbol, Symbol), &'static str> {
+    match spec {
+        Expr::Var(name) => Ok((*name, *name)),
+        Expr::List(l) if l.len() == 3 && l[0].from_var() == Some(Symbol::intern("rename")) => {
+            let internal = l[1].from_var().ok_or("invalid export specification")?;
+            let external = l[2].from_var().ok_or("invalid export specification")?;
+            Ok((external, internal))
+        },
+        _ => Err("invalid export specification"),
+    }
+}
+
+/// Builds an environment holding just the bindings of the import sets, as
+/// for `environment`.
+pub fn environment(sets: &[Expr]) -> Result<Rc<RefCell<Env>>, &'static str> {
+    let env = Env::empty();
+    for set in sets {
+        import_into(set, env.clone())?;
+    }
+    Ok(env)
+}
+
+/// `(import import-set ...)` binds the imported names in `env`.
+pub fn import(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    for set in &list[1..] {
+        import_into(set, env.clone())?;
+    }
+    Ok(Expr::Unspecified)
+}
+
+fn import_into(set: &Expr, env: Rc<RefCell<Env>>) -> Result<(), &'static str> {
+    for (name, variable) in import_set(set)? {
+        if let Some((library, internal)) = variable {
+            env.borrow_mut().import(name, library, internal);
+        }
+    }
+    Ok(())
+}
+
+fn identifiers(exprs: &[Expr]) -> Result<Vec<Symbol>, &'static str> {
+    exprs.iter().map(|e| e.from_var().ok_or("expecting an identifier in import set")).collect()
+}
+
+/// Resolves an import set to the bindings it names. A list headed by
+/// `only`, `except`, `prefix` or `rename` modifies the bindings of the
+/// inner set; any other list is a library name.
+fn import_set(set: &Expr) -> Result<Vec<Binding>, &'static str> {
+    let l = match set {
+        Expr::List(l) if !l.is_empty() => l,
+        _ => return Err("invalid import set"),
+    };
+
+    let modifier = l[0].from_var().map(|s| s.name());
+    match modifier.as_deref() {
+        Some("only") if l.len() >= 2 => {
+            let bindings = import_set(&l[1])?;
+            let mut only = Vec::new();
+            for name in identifiers(&l[2..])? {
+                let binding = bindings.iter().find(|(n, _)| *n == name).ok_or("imported identifier is not exported")?;
+                only.push(binding.clone());
+            }
+            Ok(only)
+        },
+        Some("except") if l.len() >= 2 => {
+            let mut bindings = import_set(&l[1])?;
+            for name in identifiers(&l[2..])? {
+                let i = bindings.iter().position(|(n, _)| *n == name).ok_or("imported identifier is not exported")?;
+                bindings.remove(i);
+            }
+            Ok(bindings)
+        },
+        Some("prefix") if l.len() == 3 => {
+            let prefix = l[2].from_var().ok_or("expecting an identifier in import set")?.name();
+            let bindings = import_set(&l[1])?;
+            // Special forms keep their names, so prefixing leaves them out.
+            Ok(bindings.into_iter()
+                .filter(|(_, value)| value.is_some())
+                .map(|(name, value)| (Symbol::intern(&format!("{}{}", prefix, name.name())), value))
+                .collect())
+        },
+        Some("rename") if l.len() >= 2 => {
+            let mut bindings = import_set(&l[1])?;
+            for pair in &l[2..] {
+                let names = match pair {
+                    Expr::List(p) if p.len() == 2 => identifiers(p)?,
+                    _ => return Err("invalid rename in import set"),
+                };
+                let binding = bindings.iter_mut().find(|(n, _)| *n == names[0]).ok_or("imported identifier is not exported")?;
+                if binding.1.is_none() { return Err("special forms cannot be renamed") }
+                binding.0 = names[1];
+            }
+            Ok(bindings)
+        },
+        _ => {
+            let lib = find_library(set)?;
+            let mut bindings: Vec<Binding> = lib.exports.iter()
+                .map(|(external, internal)| (*external, Some((lib.env.clone(), *internal))))
+                .collect();
+            bindings.extend(lib.syntax.iter().map(|name| (*name, None)));
+            Ok(bindings)
+        },
+    }
+}
+
+/// Reads the files named by an `include`, relative to the including file.
+/// `fold_case` folds identifiers and symbols to lower case, as for
+/// `include-ci`.
+fn included(files: &[Expr], fold_case: bool) -> Result<Vec<Expr>, &'static str> {
+    let mut exprs = Vec::new();
+    for file in files {
+        let file = match file {
+            Expr::Literal(Literal::String(s)) => s,
+            _ => return Err("include expects file names"),
+        };
+        let contents = read_file(&resolve(file))?;
+        if fold_case {
+            exprs.extend(contents.iter().map(|(_, expr)| fold_identifiers(expr)));
+        } else {
+            exprs.extend(contents.into_iter().map(|(_, expr)| expr));
+        }
+    }
+    Ok(exprs)
+}
+
+fn fold_identifiers(expr: &Expr) -> Expr {
+    let fold = |s: &Symbol| Symbol::intern(&s.name().to_lowercase());
+    match expr {
+        Expr::Var(s) => Expr::Var(fold(s)),
+        Expr::Symbol(s) => Expr::Symbol(fold(s)),
+        Expr::List(l) => Expr::List(l.iter().map(fold_identifiers).collect()),
+        Expr::DottedPair(car, cdr) => {
+            Expr::DottedPair(car.iter().map(fold_identifiers).collect(), Box::new(fold_identifiers(cdr)))
+        },
+        _ => expr.clone(),
+    }
+}
+
+/// `(include file ...)` and `(include-ci file ...)` evaluate the contents
+/// of the files in `env`.
+pub fn include(list: &[Expr], env: Rc<RefCell<Env>>, fold_case: bool) -> Result<Expr, &'static str> {
+    let mut result = Expr::Unspecified;
+    for expr in included(&list[1..], fold_case)? {
+        result = eval(&expr, env.clone())?;
+    }
+    Ok(result)
+}
+
+fn feature_requirement(req: &Expr) -> Result<bool, &'static str> {
+    match req {
+        Expr::Var(s) => Ok(features().contains(&&*s.name())),
+        Expr::List(l) if !l.is_empty() => {
+            let op = l[0].from_var().map(|s| s.name());
+            match op.as_deref() {
+                Some("and") => {
+                    for r in &l[1..] {
+                        if !feature_requirement(r)? { return Ok(false) }
+                    }
+                    Ok(true)
+                },
+                Some("or") => {
+                    for r in &l[1..] {
+                        if feature_requirement(r)? { return Ok(true) }
+                    }
+                    Ok(false)
+                },
+                Some("not") if l.len() == 2 => Ok(!feature_requirement(&l[1])?),
+                Some("library") if l.len() == 2 => library_available(&l[1]),
+                _ => Err("invalid cond-expand requirement"),
+            }
+        },
+        _ => Err("invalid cond-expand requirement"),
+    }
+}
+
+/// Returns the body of the first `cond-expand` clause whose requirement
+/// holds, or of the `else` clause.
+fn cond_expand_clause(clauses: &[Expr]) -> Result<&[Expr], &'static str> {
+    for clause in clauses {
+        let clause = match clause {
+            Expr::List(l) if !l.is_empty() => l,
+            _ => return Err("invalid cond-expand clause"),
+        };
+        if clause[0].from_var() == Some(symbol::ELSE) || feature_requirement(&clause[0])? {
+            return Ok(&clause[1..]);
+        }
+    }
+    Ok(&[])
+}
+
+/// `(cond-expand clause ...)` evaluates the body of the chosen clause.
+pub fn cond_expand(list: &[Expr], env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let mut result = Expr::Unspecified;
+    for expr in cond_expand_clause(&list[1..])? {
+        result = eval(expr, env.clone())?;
+    }
+    Ok(result)
+}
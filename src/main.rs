@@ -1,7 +1,6 @@
 use std::env;
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process;
 
 use std::cell::RefCell;
@@ -11,71 +10,119 @@ extern crate rustyline;
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 
+use slippy::ast::Expr;
 use slippy::env::Env;
-use slippy::eval::eval;
+use slippy::eval;
 use slippy::lexer::Lexer;
+use slippy::library;
 use slippy::parser::Parser;
+use slippy::vm;
+
+/// Evaluates top-level forms, either by walking the tree or on the VM.
+type Evaluator = fn(&Expr, Rc<RefCell<Env>>) -> Result<Expr, &'static str>;
+
+fn usage() -> ! {
+    println!("usage: slippy [-I <dir>]... [--vm] [--max-depth <n>] [file]");
+    process::exit(1);
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 2 {
-        println!("usage: slippy <file>");
-        process::exit(1);
-    } else if args.len() == 2 {
-        run_file(&args[1])
-    } else {
-        run_prompt();
+    let mut args = env::args().skip(1);
+    let mut library_path = Vec::new();
+    let mut file = None;
+    let mut evaluator: Evaluator = eval::eval;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Library directories given on the command line are searched
+            // before those in SLIPPY_PATH.
+            "-I" | "--library-path" => match args.next() {
+                Some(dir) => library_path.push(PathBuf::from(dir)),
+                None => usage(),
+            },
+            "--vm" => evaluator = vm::eval,
+            "--max-depth" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(depth) => eval::set_max_depth(depth),
+                None => usage(),
+            },
+            _ if file.is_none() => file = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    library_path.extend(library::default_search_path());
+    library::set_search_path(library_path);
+
+    match file {
+        Some(file) => run_file(&file, evaluator),
+        None => run_prompt(evaluator),
     }
 }
 
-fn run_file(file: &String) {
+fn run_file(file: &String, evaluator: Evaluator) {
     let env = Env::new();
 
     let path = Path::new(file);
-    let mut file = File::open(&path)
-        .expect("Failed to open file");
-
-    let mut source = String::new();
-    file.read_to_string(&mut source)
-        .expect("Failed to read file");
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            println!("{}: {}", file, err);
+            process::exit(1);
+        },
+    };
 
-    run(&source, env);
+    // Files loaded by the program resolve against its directory.
+    library::loading(path, || run(&source, env, evaluator));
 }
 
-fn run_prompt() {
+fn run_prompt(evaluator: Evaluator) {
     let mut rl = DefaultEditor::new().expect("Failed to create readline prompt.");
     let env = Env::new();
+
+    // Ctrl-C while a program runs stops it and comes back to the prompt.
+    // A program only notices between steps, so a second Ctrl-C before it
+    // has, say while it waits for input, exits instead. At the prompt
+    // itself, readline sees it and the line is dropped.
+    ctrlc::set_handler(|| if eval::interrupt() { std::process::exit(130) })
+        .expect("Failed to install interrupt handler.");
+
     loop {
         let readline = rl.readline("slippy> ");
         match readline {
             Ok(line) => {
                 let _ = rl.add_history_entry(line.as_str());
-                run(&line, env.clone());
+                eval::clear_interrupt();
+                run(&line, env.clone(), evaluator);
             },
-            Err(ReadlineError::Interrupted) => break,
+            Err(ReadlineError::Interrupted) => continue,
             Err(ReadlineError::Eof) => break,
             Err(_) => break,
         }
     }
 }
 
-fn run(source: &String, env: Rc<RefCell<Env>>) {
-    let mut lexer = Lexer::new(&source);
+fn run(source: &str, env: Rc<RefCell<Env>>, evaluator: Evaluator) {
+    let mut lexer = Lexer::new(source);
     lexer.scan();
+    if !lexer.errors.is_empty() {
+        for err in lexer.errors.iter() {
+            println!("{}", err);
+        }
+        return
+    }
+
     let mut parser = Parser::new(lexer.tokens);
-    let result = parser.parse();
-    if result.is_ok() {
-        let exprs = result.unwrap();
-        for expr in exprs.iter() {
-            let eval_result = eval(expr, env.clone());
-            if eval_result.is_ok() {
-                println!("{}", eval_result.unwrap());
-            } else {
-                println!("{}", eval_result.unwrap_err());
+    match parser.parse() {
+        Ok(exprs) => {
+            for expr in exprs.iter() {
+                match evaluator(expr, env.clone()) {
+                    Ok(val) => println!("{}", val),
+                    Err(err) => println!("{}", err),
+                }
             }
-        }
-    } else {
-        println!("{}", result.unwrap_err());
-        println!("pos: {}", parser.current);
+        },
+        Err(err) => {
+            println!("{}", err);
+            println!("pos: {}", parser.current);
+        },
     }
 }
@@ -0,0 +1,254 @@
+use std::cmp::Ordering;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Literal};
+use crate::env::Env;
+use crate::eval;
+
+/// An immutable map ordered by a comparator, after SRFI-146.
+///
+/// Mappings are persistent AVL trees: an update copies only the path from
+/// the root to the changed node and shares every other node with the
+/// original, so updates are O(log n) and old versions stay valid. Mappings
+/// created with the default comparator order keys natively; otherwise the
+/// comparator is a Scheme "less than" procedure.
+#[derive(Clone)]
+pub struct Mapping {
+    comparator: Option<Expr>,
+    root: Tree,
+}
+
+type Tree = Option<Rc<Node>>;
+
+struct Node {
+    key: Expr,
+    value: Expr,
+    height: usize,
+    left: Tree,
+    right: Tree,
+}
+
+/// Orders numbers, strings, characters, symbols and booleans among
+/// themselves, and lists lexicographically.
+pub fn default_compare(a: &Expr, b: &Expr) -> Result<Ordering, &'static str> {
+    let ordering = match (a, b) {
+        (Expr::Literal(Literal::Number(a)), Expr::Literal(Literal::Number(b))) => Some(a.cmp(b)),
+        (Expr::Literal(l), Expr::Literal(r)) => {
+            let number = |l: &Literal| match l {
+                Literal::Number(n) => Some(*n as f64),
+                Literal::Float(f) => Some(*f),
+                _ => None,
+            };
+            match (number(l), number(r)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => match (l, r) {
+                    (Literal::String(a), Literal::String(b)) => Some(a.cmp(b)),
+                    (Literal::Char(a), Literal::Char(b)) => Some(a.cmp(b)),
+                    (Literal::Bool(a), Literal::Bool(b)) => Some(a.cmp(b)),
+                    _ => None,
+                },
+            }
+        },
+        (Expr::Symbol(a), Expr::Symbol(b)) => Some(a.name().cmp(&b.name())),
+        (Expr::List(a), Expr::List(b)) => {
+            for (a, b) in a.iter().zip(b.iter()) {
+                match default_compare(a, b)? {
+                    Ordering::Equal => continue,
+                    ordering => return Ok(ordering),
+                }
+            }
+            Some(a.len().cmp(&b.len()))
+        },
+        _ => None,
+    };
+
+    ordering.ok_or("keys cannot be compared by the default comparator")
+}
+
+fn height(tree: &Tree) -> usize {
+    tree.as_ref().map_or(0, |n| n.height)
+}
+
+fn node(key: Expr, value: Expr, left: Tree, right: Tree) -> Tree {
+    let height = 1 + height(&left).max(height(&right));
+    Some(Rc::new(Node { key, value, height, left, right }))
+}
+
+/// Builds a node, rotating once or twice if its subtrees differ in height
+/// by two.
+fn balance(key: Expr, value: Expr, left: Tree, right: Tree) -> Tree {
+    let (hl, hr) = (height(&left), height(&right));
+    if hl > hr + 1 {
+        let l = left.unwrap();
+        if height(&l.left) >= height(&l.right) {
+            node(l.key.clone(), l.value.clone(), l.left.clone(),
+                 node(key, value, l.right.clone(), right))
+        } else {
+            let lr = l.right.clone().unwrap();
+            node(lr.key.clone(), lr.value.clone(),
+                 node(l.key.clone(), l.value.clone(), l.left.clone(), lr.left.clone()),
+                 node(key, value, lr.right.clone(), right))
+        }
+    } else if hr > hl + 1 {
+        let r = right.unwrap();
+        if height(&r.right) >= height(&r.left) {
+            node(r.key.clone(), r.value.clone(),
+                 node(key, value, left, r.left.clone()),
+                 r.right.clone())
+        } else {
+            let rl = r.left.clone().unwrap();
+            node(rl.key.clone(), rl.value.clone(),
+                 node(key, value, left, rl.left.clone()),
+                 node(r.key.clone(), r.value.clone(), rl.right.clone(), r.right.clone()))
+        }
+    } else {
+        node(key, value, left, right)
+    }
+}
+
+type Compare<'a> = &'a dyn Fn(&Expr, &Expr) -> Result<Ordering, &'static str>;
+
+fn insert(tree: &Tree, key: Expr, value: Expr, replace: bool, cmp: Compare) -> Result<Tree, &'static str> {
+    let n = match tree {
+        Some(n) => n,
+        None => return Ok(node(key, value, None, None)),
+    };
+
+    match cmp(&key, &n.key)? {
+        Ordering::Less => {
+            let left = insert(&n.left, key, value, replace, cmp)?;
+            Ok(balance(n.key.clone(), n.value.clone(), left, n.right.clone()))
+        },
+        Ordering::Greater => {
+            let right = insert(&n.right, key, value, replace, cmp)?;
+            Ok(balance(n.key.clone(), n.value.clone(), n.left.clone(), right))
+        },
+        Ordering::Equal if replace => Ok(node(key, value, n.left.clone(), n.right.clone())),
+        Ordering::Equal => Ok(tree.clone()),
+    }
+}
+
+/// Removes the smallest entry, returning it and the remaining tree.
+fn remove_min(n: &Rc<Node>) -> (Expr, Expr, Tree) {
+    match &n.left {
+        Some(left) => {
+            let (key, value, left) = remove_min(left);
+            (key, value, balance(n.key.clone(), n.value.clone(), left, n.right.clone()))
+        },
+        None => (n.key.clone(), n.value.clone(), n.right.clone()),
+    }
+}
+
+fn remove(tree: &Tree, key: &Expr, cmp: Compare) -> Result<Tree, &'static str> {
+    let n = match tree {
+        Some(n) => n,
+        None => return Ok(None),
+    };
+
+    match cmp(key, &n.key)? {
+        Ordering::Less => {
+            let left = remove(&n.left, key, cmp)?;
+            Ok(balance(n.key.clone(), n.value.clone(), left, n.right.clone()))
+        },
+        Ordering::Greater => {
+            let right = remove(&n.right, key, cmp)?;
+            Ok(balance(n.key.clone(), n.value.clone(), n.left.clone(), right))
+        },
+        Ordering::Equal => match (&n.left, &n.right) {
+            (None, right) => Ok(right.clone()),
+            (left, None) => Ok(left.clone()),
+            (left, Some(right)) => {
+                let (key, value, right) = remove_min(right);
+                Ok(balance(key, value, left.clone(), right))
+            },
+        },
+    }
+}
+
+impl Mapping {
+    pub fn new(comparator: Option<Expr>) -> Mapping {
+        Mapping { comparator, root: None }
+    }
+
+    pub fn comparator(&self) -> Option<Expr> {
+        self.comparator.clone()
+    }
+
+    fn compare(&self, a: &Expr, b: &Expr, env: Rc<RefCell<Env>>) -> Result<Ordering, &'static str> {
+        let less = match &self.comparator {
+            Some(less) => less,
+            None => return default_compare(a, b),
+        };
+
+        if !eval::apply(less, vec![a.clone(), b.clone()], env.clone())?.is_false() {
+            Ok(Ordering::Less)
+        } else if !eval::apply(less, vec![b.clone(), a.clone()], env)?.is_false() {
+            Ok(Ordering::Greater)
+        } else {
+            Ok(Ordering::Equal)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        fn count(tree: &Tree) -> usize {
+            tree.as_ref().map_or(0, |n| 1 + count(&n.left) + count(&n.right))
+        }
+        count(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn get(&self, key: &Expr, env: Rc<RefCell<Env>>) -> Result<Option<Expr>, &'static str> {
+        let mut tree = &self.root;
+        while let Some(n) = tree {
+            tree = match self.compare(key, &n.key, env.clone())? {
+                Ordering::Less => &n.left,
+                Ordering::Greater => &n.right,
+                Ordering::Equal => return Ok(Some(n.value.clone())),
+            };
+        }
+        Ok(None)
+    }
+
+    /// Returns a mapping with `key` bound to `value`. An existing binding
+    /// is replaced unless `replace` is false, in which case it is kept.
+    pub fn insert(&self,
+                  key: Expr,
+                  value: Expr,
+                  replace: bool,
+                  env: Rc<RefCell<Env>>) -> Result<Mapping, &'static str> {
+        let cmp = |a: &Expr, b: &Expr| self.compare(a, b, env.clone());
+        let root = insert(&self.root, key, value, replace, &cmp)?;
+        Ok(Mapping { comparator: self.comparator.clone(), root })
+    }
+
+    pub fn remove(&self, key: &Expr, env: Rc<RefCell<Env>>) -> Result<Mapping, &'static str> {
+        let cmp = |a: &Expr, b: &Expr| self.compare(a, b, env.clone());
+        let root = remove(&self.root, key, &cmp)?;
+        Ok(Mapping { comparator: self.comparator.clone(), root })
+    }
+
+    /// The entries in key order.
+    pub fn entries(&self) -> Vec<(Expr, Expr)> {
+        let mut entries = Vec::new();
+        let mut stack = Vec::new();
+        let mut tree = &self.root;
+        loop {
+            while let Some(n) = tree {
+                stack.push(n);
+                tree = &n.left;
+            }
+            match stack.pop() {
+                Some(n) => {
+                    entries.push((n.key.clone(), n.value.clone()));
+                    tree = &n.right;
+                },
+                None => return entries,
+            }
+        }
+    }
+}
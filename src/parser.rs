@@ -1,52 +1,153 @@
-use crate::ast::{Token, TokenType, Expr};
+use std::collections::{HashMap, HashSet};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast::{Token, TokenType, Expr, Literal};
+use crate::gc;
+use crate::symbol;
+use crate::symbol::Symbol;
+
+/// Data nested deeper than this are rejected rather than exhausting the
+/// stack, here and in the evaluators that walk them.
+pub const MAX_DEPTH: usize = 512;
 
 pub struct Parser {
     pub current: usize,
-    tokens: Vec<Token>
+    tokens: Vec<Token>,
+    depth: usize,
+    // Datum labels seen so far; `None` while the labelled datum is still
+    // being read.
+    labels: HashMap<i32, Option<Expr>>,
+    // Stand-ins for references to a label inside its own datum, replaced
+    // once the datum has been read.
+    placeholders: HashMap<i32, Vector>,
 }
 
+type Vector = Rc<RefCell<Vec<Expr>>>;
+
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser { current: 0, tokens }
+        Parser { current: 0, tokens, depth: 0, labels: HashMap::new(), placeholders: HashMap::new() }
     }
 
     pub fn parse(&mut self) -> Result<Vec<Expr>, &'static str> {
+        Ok(self.parse_with_lines()?.into_iter().map(|(_, expr)| expr).collect())
+    }
+
+    /// Parses the program, pairing each top-level expression with the line
+    /// it starts on.
+    pub fn parse_with_lines(&mut self) -> Result<Vec<(u32, Expr)>, &'static str> {
         let mut program = Vec::new();
         loop {
             if self.is_at_end() {
                 break;
             }
 
-            let result = self.quote();
-            if result.is_err() { return Err(result.unwrap_err()) }
-            program.push(result.unwrap());
+            let line = self.peek().line;
+            program.push((line, self.quote()?));
         }
         Ok(program)
     }
 
+    /// The line of the token being parsed, for reporting errors.
+    pub fn line(&self) -> u32 {
+        self.peek().line
+    }
+
     fn quote(&mut self) -> Result<Expr, &'static str> {
+        if self.depth == MAX_DEPTH { return Err("nested too deeply") }
+        self.depth += 1;
+        let datum = self.prefixed();
+        self.depth -= 1;
+        datum
+    }
+
+    /// A datum after any quote or datum label in front of it.
+    fn prefixed(&mut self) -> Result<Expr, &'static str> {
         if self.match_token(vec![TokenType::Quote]) {
-            let datum = self.quote();
-            if datum.is_ok() {
-                Ok(Expr::List(vec![Expr::Var("quote".to_string()),
-                                   datum.unwrap()]))
-            } else {
-                datum
+            let datum = self.quote()?;
+            Ok(Expr::List(vec![Expr::Var(symbol::QUOTE), datum]))
+        } else if self.match_token(vec![TokenType::LabelDef]) {
+            let n = self.label()?;
+            self.labels.insert(n, None);
+            let mut datum = self.quote()?;
+            if let Some(placeholder) = self.placeholders.remove(&n) {
+                let copy = datum.to_datum();
+                patch(&mut datum, &placeholder, &copy, false, &mut HashSet::new())?;
+            }
+            self.labels.insert(n, Some(datum.clone()));
+            Ok(datum)
+        } else if self.match_token(vec![TokenType::LabelRef]) {
+            let n = self.label()?;
+            match self.labels.get(&n) {
+                Some(Some(datum)) => Ok(datum.clone()),
+                Some(None) => {
+                    let placeholder = self.placeholders.entry(n).or_default();
+                    Ok(Expr::Vector(placeholder.clone()))
+                },
+                None => Err("reference to an undefined datum label"),
             }
         } else {
             self.datum()
         }
     }
 
+    fn label(&mut self) -> Result<i32, &'static str> {
+        match self.previous().literal {
+            Some(Literal::Number(n)) => Ok(n),
+            _ => Err("expecting a datum label"),
+        }
+    }
+
     fn datum(&mut self) -> Result<Expr, &'static str> {
         let simple_datum = self.simple_datum();
         if simple_datum.is_ok() {
             return simple_datum;
         }
 
+        if self.check(TokenType::Bytevector) {
+            return self.bytevector();
+        }
+
+        if self.check(TokenType::Vector) {
+            return self.vector();
+        }
+
         self.list()
     }
 
+    fn bytevector(&mut self) -> Result<Expr, &'static str> {
+        self.expect(TokenType::Bytevector, "expecting a bytevector")?;
+
+        let mut bytes = Vec::new();
+        while !self.match_token(vec![TokenType::RParen]) {
+            if self.is_at_end() { return Err("unterminated bytevector") }
+
+            let token = self.expect(TokenType::Number, "bytevector elements must be bytes")?;
+            match token.literal {
+                Some(Literal::Number(n)) if (0..=255).contains(&n) => bytes.push(n as u8),
+                _ => return Err("bytevector elements must be bytes"),
+            }
+        }
+
+        Ok(Expr::Bytevector(Rc::new(RefCell::new(bytes))))
+    }
+
+    /// Vector literals are self-evaluating, so their elements are data.
+    fn vector(&mut self) -> Result<Expr, &'static str> {
+        self.expect(TokenType::Vector, "expecting a vector")?;
+
+        let mut elements = Vec::new();
+        while !self.match_token(vec![TokenType::RParen]) {
+            if self.is_at_end() { return Err("unterminated vector") }
+
+            elements.push(self.quote()?.to_datum());
+        }
+
+        Ok(gc::vector(elements))
+    }
+
     fn list(&mut self) -> Result<Expr, &'static str> {
         if self.match_token(vec![TokenType::LParen]) {
             let mut lexprs = Vec::new();
@@ -58,17 +159,12 @@ impl Parser {
             }
 
             loop {
-                let datum = self.quote();
-                if datum.is_err() { return Err(datum.unwrap_err()); }
-                lexprs.push(datum.unwrap());
+                lexprs.push(self.quote()?);
 
                 // Dotted Pair
                 if self.match_token(vec![TokenType::Dot]) {
-                    let rexpr = self.quote();
-                    let rparen = self.expect(TokenType::RParen, "expecting right paren");
-                    if rexpr.is_err() { return Err(rexpr.unwrap_err()); }
-                    if rparen.is_err() { return Err(rparen.unwrap_err()); }
-                    let rexpr = rexpr.unwrap();
+                    let rexpr = self.quote()?;
+                    self.expect(TokenType::RParen, "expecting right paren")?;
 
                     if rexpr.is_list() {
                         let rexpr = rexpr.to_vec().unwrap();
@@ -92,10 +188,11 @@ impl Parser {
         if self.match_token(vec![TokenType::Number,
                                  TokenType::Float,
                                  TokenType::Bool,
-                                 TokenType::String]) {
+                                 TokenType::String,
+                                 TokenType::Char]) {
             Ok(Expr::Literal(self.previous().literal.unwrap()))
         } else if self.match_token(vec![TokenType::Identifier]) {
-            Ok(Expr::Var(self.previous().lexeme))
+            Ok(Expr::Var(Symbol::intern(&self.previous().lexeme)))
         } else {
             Err("expecting number, float, boolean, or identifier")
         }
@@ -162,3 +259,34 @@ impl Parser {
         self.tokens[self.current - 1].clone()
     }
 }
+
+/// Replaces the placeholder for a label with its datum. Only vectors can
+/// hold a reference back to the datum they are part of; lists are values,
+/// so a list cannot contain itself.
+fn patch(expr: &mut Expr, placeholder: &Vector, datum: &Expr, in_vector: bool,
+         seen: &mut HashSet<*const RefCell<Vec<Expr>>>) -> Result<(), &'static str> {
+    match expr {
+        Expr::Vector(v) if Rc::ptr_eq(v, placeholder) => {
+            if !in_vector { return Err("circular datum labels are only supported through vectors") }
+            *expr = datum.clone();
+        },
+        Expr::Vector(v) if seen.insert(Rc::as_ptr(v)) => {
+            for element in v.clone().borrow_mut().iter_mut() {
+                patch(element, placeholder, datum, true, seen)?;
+            }
+        },
+        Expr::List(l) => {
+            for element in l.iter_mut() {
+                patch(element, placeholder, datum, in_vector, seen)?;
+            }
+        },
+        Expr::DottedPair(car, cdr) => {
+            for element in car.iter_mut() {
+                patch(element, placeholder, datum, in_vector, seen)?;
+            }
+            patch(cdr, placeholder, datum, in_vector, seen)?;
+        },
+        _ => (),
+    }
+    Ok(())
+}
@@ -0,0 +1,326 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter, Cursor};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// An input or output port. Textual ports carry UTF-8 text; binary ports
+/// carry raw bytes. Ports start open and can be closed once, after which
+/// every operation fails.
+pub struct Port {
+    pub input: bool,
+    pub binary: bool,
+    state: RefCell<PortState>,
+}
+
+enum PortState {
+    Input(Source),
+    Output(Sink),
+    Closed,
+}
+
+struct Source {
+    reader: Box<dyn BufRead>,
+    peeked: Option<char>,
+    // Interactive sources may block, so they are only ready once a
+    // character has been peeked.
+    interactive: bool,
+}
+
+enum Sink {
+    Stdout,
+    Stderr,
+    File(BufWriter<File>),
+    Buffer(Vec<u8>),
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Stdout => io::stdout().write(buf),
+            Sink::Stderr => io::stderr().write(buf),
+            Sink::File(f) => f.write(buf),
+            Sink::Buffer(b) => b.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Stdout => io::stdout().flush(),
+            Sink::Stderr => io::stderr().flush(),
+            Sink::File(f) => f.flush(),
+            Sink::Buffer(_) => Ok(()),
+        }
+    }
+}
+
+impl Source {
+    fn read_byte(&mut self) -> Result<Option<u8>, &'static str> {
+        let buf = self.reader.fill_buf().map_err(|_| "error reading from port")?;
+        if buf.is_empty() { return Ok(None) }
+
+        let b = buf[0];
+        self.reader.consume(1);
+        Ok(Some(b))
+    }
+
+    fn peek_byte(&mut self) -> Result<Option<u8>, &'static str> {
+        let buf = self.reader.fill_buf().map_err(|_| "error reading from port")?;
+        Ok(buf.first().copied())
+    }
+
+    fn read_char(&mut self) -> Result<Option<char>, &'static str> {
+        if let Some(c) = self.peeked.take() {
+            return Ok(Some(c));
+        }
+
+        let first = match self.read_byte()? {
+            Some(b) => b,
+            None => return Ok(None),
+        };
+        let width = match first {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Err("invalid utf-8 in input"),
+        };
+
+        let mut bytes = vec![first];
+        for _ in 1..width {
+            bytes.push(self.read_byte()?.ok_or("invalid utf-8 in input")?);
+        }
+        match std::str::from_utf8(&bytes) {
+            Ok(s) => Ok(s.chars().next()),
+            Err(_) => Err("invalid utf-8 in input"),
+        }
+    }
+
+    fn peek_char(&mut self) -> Result<Option<char>, &'static str> {
+        if self.peeked.is_none() {
+            self.peeked = self.read_char()?;
+        }
+        Ok(self.peeked)
+    }
+
+    fn ready(&self) -> bool {
+        self.peeked.is_some() || !self.interactive
+    }
+}
+
+impl Port {
+    fn new(input: bool, binary: bool, state: PortState) -> Port {
+        Port { input, binary, state: RefCell::new(state) }
+    }
+
+    fn from_reader(reader: Box<dyn BufRead>, binary: bool) -> Port {
+        Port::new(true, binary, PortState::Input(Source { reader, peeked: None, interactive: false }))
+    }
+
+    pub fn stdin() -> Port {
+        let source = Source {
+            reader: Box::new(BufReader::new(io::stdin())),
+            peeked: None,
+            interactive: true,
+        };
+        Port::new(true, false, PortState::Input(source))
+    }
+
+    pub fn stdout() -> Port {
+        Port::new(false, false, PortState::Output(Sink::Stdout))
+    }
+
+    pub fn stderr() -> Port {
+        Port::new(false, false, PortState::Output(Sink::Stderr))
+    }
+
+    pub fn open_input_file(path: &str, binary: bool) -> Result<Port, &'static str> {
+        let file = File::open(path).map_err(|_| "unable to open file for input")?;
+        Ok(Port::from_reader(Box::new(BufReader::new(file)), binary))
+    }
+
+    pub fn open_output_file(path: &str, binary: bool) -> Result<Port, &'static str> {
+        let file = File::create(path).map_err(|_| "unable to open file for output")?;
+        Ok(Port::new(false, binary, PortState::Output(Sink::File(BufWriter::new(file)))))
+    }
+
+    /// An input port reading from an in-memory string or bytevector.
+    pub fn open_input_buffer(bytes: Vec<u8>, binary: bool) -> Port {
+        Port::from_reader(Box::new(Cursor::new(bytes)), binary)
+    }
+
+    /// An output port accumulating into memory; see `buffer_contents`.
+    pub fn open_output_buffer(binary: bool) -> Port {
+        Port::new(false, binary, PortState::Output(Sink::Buffer(Vec::new())))
+    }
+
+    /// Everything written so far to a port from `open_output_buffer`.
+    pub fn buffer_contents(&self) -> Result<Vec<u8>, &'static str> {
+        match &*self.state.borrow() {
+            PortState::Output(Sink::Buffer(b)) => Ok(b.clone()),
+            PortState::Closed => Err("port is closed"),
+            _ => Err("expecting a string or bytevector output port"),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !matches!(*self.state.borrow(), PortState::Closed)
+    }
+
+    pub fn close(&self) -> Result<(), &'static str> {
+        let mut state = self.state.borrow_mut();
+        if let PortState::Output(sink) = &mut *state {
+            sink.flush().map_err(|_| "error flushing port")?;
+        }
+        *state = PortState::Closed;
+        Ok(())
+    }
+
+    fn with_source<T>(&self,
+                      binary: bool,
+                      f: impl FnOnce(&mut Source) -> Result<T, &'static str>) -> Result<T, &'static str> {
+        if self.binary != binary {
+            return Err(if binary { "expecting a binary input port" } else { "expecting a textual input port" });
+        }
+        match &mut *self.state.borrow_mut() {
+            PortState::Input(source) => f(source),
+            PortState::Output(_) => Err("expecting an input port"),
+            PortState::Closed => Err("port is closed"),
+        }
+    }
+
+    fn with_sink<T>(&self,
+                    binary: bool,
+                    f: impl FnOnce(&mut Sink) -> io::Result<T>) -> Result<T, &'static str> {
+        if self.binary != binary {
+            return Err(if binary { "expecting a binary output port" } else { "expecting a textual output port" });
+        }
+        match &mut *self.state.borrow_mut() {
+            PortState::Output(sink) => f(sink).map_err(|_| "error writing to port"),
+            PortState::Input(_) => Err("expecting an output port"),
+            PortState::Closed => Err("port is closed"),
+        }
+    }
+
+    pub fn read_char(&self) -> Result<Option<char>, &'static str> {
+        self.with_source(false, |s| s.read_char())
+    }
+
+    pub fn peek_char(&self) -> Result<Option<char>, &'static str> {
+        self.with_source(false, |s| s.peek_char())
+    }
+
+    pub fn char_ready(&self) -> Result<bool, &'static str> {
+        self.with_source(false, |s| Ok(s.ready()))
+    }
+
+    /// Reads up to, and discards, the next newline. Returns `None` at end of
+    /// input.
+    pub fn read_line(&self) -> Result<Option<String>, &'static str> {
+        self.with_source(false, |s| {
+            let mut line = String::new();
+            loop {
+                match s.read_char()? {
+                    Some('\n') => return Ok(Some(line)),
+                    Some(c) => line.push(c),
+                    None if line.is_empty() => return Ok(None),
+                    None => return Ok(Some(line)),
+                }
+            }
+        })
+    }
+
+    /// Reads up to `k` characters. Returns `None` at end of input.
+    pub fn read_string(&self, k: usize) -> Result<Option<String>, &'static str> {
+        self.with_source(false, |s| {
+            let mut string = String::new();
+            for _ in 0..k {
+                match s.read_char()? {
+                    Some(c) => string.push(c),
+                    None => break,
+                }
+            }
+            if string.is_empty() && k > 0 { Ok(None) } else { Ok(Some(string)) }
+        })
+    }
+
+    pub fn read_u8(&self) -> Result<Option<u8>, &'static str> {
+        self.with_source(true, |s| s.read_byte())
+    }
+
+    pub fn peek_u8(&self) -> Result<Option<u8>, &'static str> {
+        self.with_source(true, |s| s.peek_byte())
+    }
+
+    pub fn u8_ready(&self) -> Result<bool, &'static str> {
+        self.with_source(true, |s| Ok(s.ready()))
+    }
+
+    /// Reads up to `k` bytes. Returns `None` at end of input.
+    pub fn read_bytes(&self, k: usize) -> Result<Option<Vec<u8>>, &'static str> {
+        self.with_source(true, |s| {
+            let mut bytes = Vec::new();
+            for _ in 0..k {
+                match s.read_byte()? {
+                    Some(b) => bytes.push(b),
+                    None => break,
+                }
+            }
+            if bytes.is_empty() && k > 0 { Ok(None) } else { Ok(Some(bytes)) }
+        })
+    }
+
+    pub fn write_str(&self, s: &str) -> Result<(), &'static str> {
+        self.with_sink(false, |sink| sink.write_all(s.as_bytes()))
+    }
+
+    pub fn write_bytes(&self, bytes: &[u8]) -> Result<(), &'static str> {
+        self.with_sink(true, |sink| sink.write_all(bytes))
+    }
+
+    pub fn flush(&self) -> Result<(), &'static str> {
+        match &mut *self.state.borrow_mut() {
+            PortState::Output(sink) => sink.flush().map_err(|_| "error flushing port"),
+            PortState::Input(_) => Err("expecting an output port"),
+            PortState::Closed => Err("port is closed"),
+        }
+    }
+}
+
+impl Drop for Port {
+    fn drop(&mut self) {
+        if let PortState::Output(sink) = self.state.get_mut() {
+            let _ = sink.flush();
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT_INPUT: RefCell<Rc<Port>> = RefCell::new(Rc::new(Port::stdin()));
+    static CURRENT_OUTPUT: RefCell<Rc<Port>> = RefCell::new(Rc::new(Port::stdout()));
+    static CURRENT_ERROR: RefCell<Rc<Port>> = RefCell::new(Rc::new(Port::stderr()));
+}
+
+pub fn current_input() -> Rc<Port> {
+    CURRENT_INPUT.with(|p| p.borrow().clone())
+}
+
+pub fn current_output() -> Rc<Port> {
+    CURRENT_OUTPUT.with(|p| p.borrow().clone())
+}
+
+pub fn current_error() -> Rc<Port> {
+    CURRENT_ERROR.with(|p| p.borrow().clone())
+}
+
+/// Installs `port` as the current input port, returning the previous one.
+pub fn set_current_input(port: Rc<Port>) -> Rc<Port> {
+    CURRENT_INPUT.with(|p| p.replace(port))
+}
+
+/// Installs `port` as the current output port, returning the previous one.
+pub fn set_current_output(port: Rc<Port>) -> Rc<Port> {
+    CURRENT_OUTPUT.with(|p| p.replace(port))
+}
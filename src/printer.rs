@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use std::rc::Rc;
+
+use crate::ast::{char_name, Expr, Literal};
+use crate::symbol::Symbol;
+
+/// How an expression is printed.
+///
+/// `Display` prints strings and characters raw. The other modes produce
+/// machine-readable output: `Write` labels only circular structure,
+/// `Shared` labels every vector and record reachable more than once, and
+/// `Simple` never labels, so it does not terminate on circular data. Lists
+/// are never labelled, since they have no identity to share.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Display,
+    Write,
+    Shared,
+    Simple,
+}
+
+pub fn to_string(expr: &Expr, mode: Mode) -> String {
+    let mut printer = Printer {
+        mode,
+        shared: HashSet::new(),
+        labels: HashMap::new(),
+        out: String::new(),
+    };
+
+    if mode != Mode::Simple {
+        let mut seen = HashMap::new();
+        printer.scan(expr, &mut seen);
+    }
+    printer.print(expr);
+    printer.out
+}
+
+/// The identity and contents of heap objects that may be shared. Lists are
+/// values in slippy, so only vectors and records can form cycles.
+fn container(expr: &Expr) -> Option<(usize, Vec<Expr>)> {
+    match expr {
+        Expr::Vector(v) => Some((Rc::as_ptr(v) as usize, v.borrow().clone())),
+        Expr::Record(r) => Some((Rc::as_ptr(r) as usize, r.fields.borrow().clone())),
+        _ => None,
+    }
+}
+
+struct Printer {
+    mode: Mode,
+    shared: HashSet<usize>,
+    labels: HashMap<usize, usize>,
+    out: String,
+}
+
+impl Printer {
+    /// Finds the containers that need datum labels. `seen` maps each visited
+    /// container to whether it is still being traversed, which marks a cycle.
+    fn scan(&mut self, expr: &Expr, seen: &mut HashMap<usize, bool>) {
+        match expr {
+            Expr::List(l) | Expr::Values(l) => {
+                for e in l.iter() { self.scan(e, seen) }
+            },
+            Expr::DottedPair(car, cdr) => {
+                for e in car.iter() { self.scan(e, seen) }
+                self.scan(cdr, seen);
+            },
+            _ => {
+                let (id, children) = match container(expr) {
+                    Some(c) => c,
+                    None => return,
+                };
+                match seen.get(&id) {
+                    Some(in_progress) => {
+                        if *in_progress || self.mode == Mode::Shared {
+                            self.shared.insert(id);
+                        }
+                    },
+                    None => {
+                        seen.insert(id, true);
+                        for e in children.iter() { self.scan(e, seen) }
+                        seen.insert(id, false);
+                    },
+                }
+            },
+        }
+    }
+
+    fn print(&mut self, expr: &Expr) {
+        if let Some((id, _)) = container(expr) && self.shared.contains(&id) {
+            if let Some(n) = self.labels.get(&id) {
+                write!(self.out, "#{}#", n).unwrap();
+                return;
+            }
+            let n = self.labels.len();
+            self.labels.insert(id, n);
+            write!(self.out, "#{}=", n).unwrap();
+        }
+
+        match expr {
+            Expr::DottedPair(car, cdr) => {
+                self.out.push('(');
+                self.print_seq(car);
+                self.out.push_str(" . ");
+                self.print(cdr);
+                self.out.push(')');
+            },
+            Expr::List(l) => {
+                self.out.push('(');
+                self.print_seq(l);
+                self.out.push(')');
+            },
+            Expr::Values(vals) => self.print_seq(vals),
+            Expr::Var(s) | Expr::Symbol(s) => {
+                if self.mode == Mode::Display {
+                    self.out.push_str(&s.name());
+                } else {
+                    self.out.push_str(&write_symbol(*s));
+                }
+            },
+            Expr::Literal(Literal::String(s)) if self.mode == Mode::Display => self.out.push_str(s),
+            Expr::Literal(Literal::Char(c)) if self.mode == Mode::Display => self.out.push(*c),
+            Expr::Literal(l) => write!(self.out, "{}", l).unwrap(),
+            Expr::Bytevector(b) => {
+                self.out.push_str("#u8(");
+                let b = b.borrow();
+                for (i, n) in b.iter().enumerate() {
+                    if i > 0 { self.out.push(' ') }
+                    write!(self.out, "{}", n).unwrap();
+                }
+                self.out.push(')');
+            },
+            Expr::Vector(v) => {
+                let v = v.borrow().clone();
+                self.out.push_str("#(");
+                self.print_seq(&v);
+                self.out.push(')');
+            },
+            Expr::Record(r) => {
+                write!(self.out, "#<record {}", r.rtype.display_name()).unwrap();
+                let fields = r.fields.borrow().clone();
+                for (name, value) in r.rtype.fields.iter().zip(fields.iter()) {
+                    write!(self.out, " {}: ", name).unwrap();
+                    self.print(value);
+                }
+                self.out.push('>');
+            },
+            Expr::RecordType(t) => write!(self.out, "#<record-type {}>", t.display_name()).unwrap(),
+            Expr::HashTable(_) => self.out.push_str("#<hash-table>"),
+            Expr::Mapping(_) => self.out.push_str("#<mapping>"),
+            Expr::CharSet(_) => self.out.push_str("#<char-set>"),
+            Expr::Regexp(_) => self.out.push_str("#<regexp>"),
+            Expr::RegexpMatch(_) => self.out.push_str("#<regexp-match>"),
+            Expr::Port(p) => {
+                if p.input {
+                    self.out.push_str("#<input-port>");
+                } else {
+                    self.out.push_str("#<output-port>");
+                }
+            },
+            Expr::Environment(_) => self.out.push_str("#<environment>"),
+            Expr::Eof => self.out.push_str("#<eof>"),
+            Expr::Lambda(_) | Expr::Closure(_) | Expr::RecordProc(_) => self.out.push_str("#<procedure>"),
+            Expr::Builtin(_) => self.out.push_str("#<built-in procedure>"),
+            Expr::Unspecified => self.out.push_str("#unspecified"),
+        }
+    }
+
+    fn print_seq(&mut self, exprs: &[Expr]) {
+        for (i, e) in exprs.iter().enumerate() {
+            if i > 0 { self.out.push(' ') }
+            self.print(e);
+        }
+    }
+}
+
+/// Writes a string literal with Scheme escapes.
+pub fn write_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\x07' => out.push_str("\\a"),
+            '\x08' => out.push_str("\\b"),
+            c if c.is_control() => write!(out, "\\x{:x};", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn write_char(c: char) -> String {
+    match char_name(c) {
+        Some(name) => format!("#\\{}", name),
+        None if c.is_control() => format!("#\\x{:x}", c as u32),
+        None => format!("#\\{}", c),
+    }
+}
+
+/// Writes a symbol, quoting it with bars when the reader would not read
+/// it back as the same symbol.
+fn write_symbol(sym: Symbol) -> String {
+    let name = sym.name();
+    let plain = !name.is_empty() &&
+        &*name != "." &&
+        !name.starts_with(|c: char| c.is_ascii_digit()) &&
+        name.chars().all(|c| c.is_ascii_alphanumeric() || "!$%&*+-./:<=>?@^_~".contains(c));
+
+    if plain {
+        return name.to_string();
+    }
+
+    let mut out = String::from("|");
+    for c in name.chars() {
+        match c {
+            '|' => out.push_str("\\|"),
+            '\\' => out.push_str("\\\\"),
+            c => out.push(c),
+        }
+    }
+    out.push('|');
+    out
+}
@@ -0,0 +1,154 @@
+use crate::ast::Expr;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::port::Port;
+
+/// Reads the next datum from a textual input port without evaluating it.
+/// Returns the eof object when only whitespace and comments remain.
+///
+/// Characters are consumed one at a time until a complete datum has been
+/// seen, so data may span lines and the rest of the port is left unread.
+pub fn read(port: &Port) -> Result<Expr, &'static str> {
+    let mut reader = Reader { port, text: String::new() };
+    if !reader.skip_atmosphere()? {
+        return Ok(Expr::Eof);
+    }
+    reader.datum()?;
+
+    let mut lexer = Lexer::new(&reader.text);
+    lexer.scan();
+    if let Some(err) = lexer.errors.first() { return Err(err.message) }
+
+    let mut parser = Parser::new(lexer.tokens);
+    match parser.parse()?.first() {
+        Some(expr) => Ok(expr.to_datum()),
+        None => Err("read error"),
+    }
+}
+
+struct Reader<'a> {
+    port: &'a Port,
+    text: String,
+}
+
+impl Reader<'_> {
+    fn peek(&self) -> Result<Option<char>, &'static str> {
+        self.port.peek_char()
+    }
+
+    fn next(&mut self) -> Result<char, &'static str> {
+        match self.port.read_char()? {
+            Some(c) => {
+                self.text.push(c);
+                Ok(c)
+            },
+            None => Err("unexpected end of input in datum"),
+        }
+    }
+
+    fn is_delimiter(c: char) -> bool {
+        c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == ';' || c == '\''
+    }
+
+    /// Skips whitespace and comments. Returns false at end of input.
+    fn skip_atmosphere(&mut self) -> Result<bool, &'static str> {
+        loop {
+            match self.peek()? {
+                None => return Ok(false),
+                Some(';') => {
+                    while let Some(c) = self.port.read_char()? {
+                        if c == '\n' { break }
+                    }
+                    self.text.push('\n');
+                },
+                Some(c) if c.is_whitespace() => {
+                    self.port.read_char()?;
+                    self.text.push(c);
+                },
+                Some(_) => return Ok(true),
+            }
+        }
+    }
+
+    /// Collects the text of one datum.
+    fn datum(&mut self) -> Result<(), &'static str> {
+        match self.next()? {
+            '\'' => self.prefixed(),
+            '(' => self.list(),
+            ')' => Err("unexpected )"),
+            '"' => self.delimited('"'),
+            '|' => self.delimited('|'),
+            '#' => match self.peek()? {
+                Some('\\') => {
+                    self.next()?;
+                    // The first character is taken as is, so #\( works.
+                    self.next()?;
+                    self.atom()
+                },
+                Some('(') => {
+                    self.next()?;
+                    self.list()
+                },
+                Some('u') => {
+                    self.atom()?;
+                    if self.peek()? == Some('(') {
+                        self.next()?;
+                        self.list()
+                    } else {
+                        Ok(())
+                    }
+                },
+                Some(c) if c.is_ascii_digit() => {
+                    while let Some(c) = self.peek()? && c.is_ascii_digit() {
+                        self.next()?;
+                    }
+                    match self.next()? {
+                        '=' => self.prefixed(),
+                        _ => Ok(()),
+                    }
+                },
+                _ => self.atom(),
+            },
+            _ => self.atom(),
+        }
+    }
+
+    /// Collects the datum following a quote or datum label.
+    fn prefixed(&mut self) -> Result<(), &'static str> {
+        if !self.skip_atmosphere()? {
+            return Err("unexpected end of input in datum");
+        }
+        self.datum()
+    }
+
+    /// Collects list elements after the opening paren.
+    fn list(&mut self) -> Result<(), &'static str> {
+        loop {
+            if !self.skip_atmosphere()? {
+                return Err("unexpected end of input in datum");
+            }
+            if self.peek()? == Some(')') {
+                self.next()?;
+                return Ok(());
+            }
+            self.datum()?;
+        }
+    }
+
+    fn delimited(&mut self, quote: char) -> Result<(), &'static str> {
+        loop {
+            match self.next()? {
+                '\\' => { self.next()?; },
+                c if c == quote => return Ok(()),
+                _ => (),
+            }
+        }
+    }
+
+    fn atom(&mut self) -> Result<(), &'static str> {
+        while let Some(c) = self.peek()? && !Reader::is_delimiter(c) {
+            self.next()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Literal};
+use crate::symbol::Symbol;
+
+/// A record type created by `define-record-type`. Types are compared by
+/// identity, so two definitions with the same name are distinct types.
+pub struct RecordType {
+    pub name: Symbol,
+    pub fields: Vec<Symbol>,
+}
+
+pub struct Record {
+    pub rtype: Rc<RecordType>,
+    pub fields: RefCell<Vec<Expr>>,
+}
+
+/// The procedures generated for a record type. Field indices are resolved
+/// when the type is defined, so access is a vector lookup.
+pub enum RecordProc {
+    Constructor(Rc<RecordType>, Vec<usize>),
+    Predicate(Rc<RecordType>),
+    Accessor(Rc<RecordType>, usize),
+    Modifier(Rc<RecordType>, usize),
+}
+
+impl RecordType {
+    /// The type name without the conventional angle brackets.
+    pub fn display_name(&self) -> String {
+        let name = self.name.name();
+        name.trim_start_matches('<').trim_end_matches('>').to_string()
+    }
+
+    pub fn field_index(&self, field: Symbol) -> Option<usize> {
+        self.fields.iter().position(|f| *f == field)
+    }
+}
+
+/// Creates the type a `define-record-type` form describes, returning the
+/// names it defines with their values: the type, then the constructor,
+/// predicate, accessors and modifiers.
+pub fn define(list: &[Expr]) -> Result<Vec<(Symbol, Expr)>, &'static str> {
+    if list.len() < 4 { return Err("invalid define-record-type syntax") }
+
+    let name = list[1].from_var().ok_or("record type name must be an identifier")?;
+
+    let mut fields = Vec::new();
+    let mut specs = Vec::new();
+    for spec in &list[4..list.len()] {
+        let spec = match spec {
+            Expr::List(spec) if !spec.is_empty() && spec.len() <= 3 => spec.clone(),
+            Expr::Var(_) => vec![spec.clone()],
+            _ => return Err("invalid record field specification"),
+        };
+
+        let field = spec[0].from_var().ok_or("record field name must be an identifier")?;
+        if fields.contains(&field) { return Err("duplicate record field name") }
+        fields.push(field);
+        specs.push(spec);
+    }
+
+    let rtype = Rc::new(RecordType { name, fields });
+    let mut bindings = vec![(name, Expr::RecordType(rtype.clone()))];
+
+    match &list[2] {
+        Expr::List(ctor) => {
+            if ctor.is_empty() { return Err("invalid record constructor specification") }
+
+            let ctor_name = ctor[0].from_var().ok_or("record constructor name must be an identifier")?;
+            let mut indices = Vec::new();
+            for field in &ctor[1..ctor.len()] {
+                let field = field.from_var().ok_or("record field name must be an identifier")?;
+                let i = rtype.field_index(field).ok_or("constructor names an unknown record field")?;
+                indices.push(i);
+            }
+            bindings.push((ctor_name, RecordProc::Constructor(rtype.clone(), indices).into()));
+        },
+        Expr::Var(ctor_name) => {
+            let indices = (0..rtype.fields.len()).collect();
+            bindings.push((*ctor_name, RecordProc::Constructor(rtype.clone(), indices).into()));
+        },
+        Expr::Literal(Literal::Bool(false)) => (),
+        _ => return Err("invalid record constructor specification"),
+    }
+
+    match &list[3] {
+        Expr::Var(pred_name) => bindings.push((*pred_name, RecordProc::Predicate(rtype.clone()).into())),
+        Expr::Literal(Literal::Bool(false)) => (),
+        _ => return Err("record predicate name must be an identifier"),
+    }
+
+    for (i, spec) in specs.iter().enumerate() {
+        if let Some(accessor) = spec.get(1) {
+            let accessor = accessor.from_var().ok_or("record accessor name must be an identifier")?;
+            bindings.push((accessor, RecordProc::Accessor(rtype.clone(), i).into()));
+        }
+        if let Some(modifier) = spec.get(2) {
+            let modifier = modifier.from_var().ok_or("record modifier name must be an identifier")?;
+            bindings.push((modifier, RecordProc::Modifier(rtype.clone(), i).into()));
+        }
+    }
+
+    Ok(bindings)
+}
+
+fn record_arg(rtype: &Rc<RecordType>, expr: &Expr) -> Result<Rc<Record>, &'static str> {
+    match expr {
+        Expr::Record(r) if Rc::ptr_eq(&r.rtype, rtype) => Ok(r.clone()),
+        _ => Err("record procedure applied to a value of the wrong type"),
+    }
+}
+
+pub fn apply(proc: &RecordProc, args: Vec<Expr>) -> Result<Expr, &'static str> {
+    match proc {
+        RecordProc::Constructor(rtype, indices) => {
+            if args.len() != indices.len() { return Err("applied to incorrect number of args") }
+
+            let mut fields = vec![Expr::Unspecified; rtype.fields.len()];
+            for (i, arg) in indices.iter().zip(args) {
+                fields[*i] = arg;
+            }
+
+            Ok(Expr::Record(Rc::new(Record { rtype: rtype.clone(), fields: RefCell::new(fields) })))
+        },
+        RecordProc::Predicate(rtype) => {
+            if args.len() != 1 { return Err("applied to incorrect number of args") }
+
+            let is_instance = matches!(&args[0], Expr::Record(r) if Rc::ptr_eq(&r.rtype, rtype));
+            Ok(Expr::Literal(Literal::Bool(is_instance)))
+        },
+        RecordProc::Accessor(rtype, i) => {
+            if args.len() != 1 { return Err("applied to incorrect number of args") }
+
+            let record = record_arg(rtype, &args[0])?;
+            let value = record.fields.borrow()[*i].clone();
+            Ok(value)
+        },
+        RecordProc::Modifier(rtype, i) => {
+            if args.len() != 2 { return Err("applied to incorrect number of args") }
+
+            let record = record_arg(rtype, &args[0])?;
+            record.fields.borrow_mut()[*i] = args[1].clone();
+            Ok(Expr::Unspecified)
+        },
+    }
+}
+
+impl From<RecordProc> for Expr {
+    fn from(proc: RecordProc) -> Expr {
+        Expr::RecordProc(Rc::new(proc))
+    }
+}
@@ -0,0 +1,812 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Literal};
+use crate::charset;
+use crate::charset::CharSet;
+use crate::eval;
+use crate::sandbox;
+use crate::symbol::Symbol;
+
+/// A compiled regular expression, after SRFI-115.
+///
+/// Both SRE s-expressions and string patterns are parsed into the same
+/// `Node` tree and compiled to a small instruction set. Matching is a
+/// backtracking search that records each visited (instruction, position)
+/// pair, so it runs in time proportional to the pattern size times the
+/// input length and picks the leftmost, first-alternative match.
+pub struct Regexp {
+    prog: Vec<Inst>,
+    groups: usize,
+    names: Vec<(Symbol, usize)>,
+}
+
+/// The submatches of a successful match, as character indices into the
+/// searched string. Submatch 0 is the whole match.
+pub struct RegexpMatch {
+    text: Rc<[char]>,
+    spans: Vec<Option<(usize, usize)>>,
+    names: Vec<(Symbol, usize)>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Assertion {
+    StartOfString,
+    EndOfString,
+    StartOfLine,
+    EndOfLine,
+    StartOfWord,
+    EndOfWord,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+enum Node {
+    Empty,
+    Char(char),
+    Set(CharSet),
+    Seq(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Node>, usize, Option<usize>, bool),
+    Group(usize, Box<Node>),
+    Assert(Assertion),
+}
+
+enum Inst {
+    Char(char),
+    Set(CharSet),
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Assert(Assertion),
+    Match,
+}
+
+/// Instructions before this index implement the unanchored search prefix.
+const ANCHORED: usize = 3;
+
+/// Repetition counts beyond this are rejected, since bounded repeats are
+/// compiled by copying the repeated pattern.
+const MAX_REPEAT: usize = 1000;
+
+/// The most instructions a compiled pattern may have. Nested bounded
+/// repeats multiply, so each staying under `MAX_REPEAT` is not enough.
+const MAX_PROGRAM: usize = 100_000;
+
+/// Matches that would need a larger bitset than this, in bits, record the
+/// states they visit in a hash set instead, which only grows with the work
+/// done.
+const MAX_DENSE_VISITED: usize = 1 << 26;
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn class_set(name: &str) -> Option<CharSet> {
+    let set = match name {
+        "any" => CharSet::full(),
+        "nonl" => CharSet::from_chars(['\n']).complement(),
+        "ascii" => (*charset::standard("ascii")).clone(),
+        "lower-case" | "lower" => (*charset::standard("lower-case")).clone(),
+        "upper-case" | "upper" => (*charset::standard("upper-case")).clone(),
+        "title-case" | "title" => (*charset::standard("title-case")).clone(),
+        "alphabetic" | "alpha" => (*charset::standard("letter")).clone(),
+        "numeric" | "num" | "digit" => (*charset::standard("digit")).clone(),
+        "alphanumeric" | "alphanum" | "alnum" => (*charset::standard("letter+digit")).clone(),
+        "punctuation" | "punct" => (*charset::standard("punctuation")).clone(),
+        "symbol" => (*charset::standard("symbol")).clone(),
+        "graphic" | "graph" => (*charset::standard("graphic")).clone(),
+        "whitespace" | "white" | "space" => (*charset::standard("whitespace")).clone(),
+        "printing" | "print" => (*charset::standard("printing")).clone(),
+        "control" | "cntrl" => (*charset::standard("iso-control")).clone(),
+        "hex-digit" | "xdigit" => (*charset::standard("hex-digit")).clone(),
+        _ => return None,
+    };
+    Some(set)
+}
+
+fn char_node(c: char, nocase: bool) -> Node {
+    if nocase && c.is_alphabetic() {
+        Node::Set(CharSet::from_chars([c]).fold_case())
+    } else {
+        Node::Char(c)
+    }
+}
+
+fn count_arg(expr: Option<&Expr>) -> Result<usize, &'static str> {
+    match expr {
+        Some(Expr::Literal(Literal::Number(n))) if *n >= 0 && *n as usize <= MAX_REPEAT => Ok(*n as usize),
+        _ => Err("expecting a repetition count in SRE"),
+    }
+}
+
+/*
+ * SRE syntax
+ */
+
+struct SreParser {
+    groups: usize,
+    names: Vec<(Symbol, usize)>,
+}
+
+impl SreParser {
+    fn seq(&mut self, exprs: &[Expr], nocase: bool) -> Result<Node, &'static str> {
+        let nodes = exprs.iter().map(|e| self.sre(e, nocase)).collect::<Result<Vec<Node>, _>>()?;
+        Ok(Node::Seq(nodes))
+    }
+
+    fn group(&mut self, name: Option<Symbol>, exprs: &[Expr], nocase: bool) -> Result<Node, &'static str> {
+        self.groups += 1;
+        let index = self.groups;
+        if let Some(name) = name {
+            self.names.push((name, index));
+        }
+        Ok(Node::Group(index, Box::new(self.seq(exprs, nocase)?)))
+    }
+
+    fn sre(&mut self, expr: &Expr, nocase: bool) -> Result<Node, &'static str> {
+        match expr {
+            Expr::Literal(Literal::String(s)) => Ok(Node::Seq(s.chars().map(|c| char_node(c, nocase)).collect())),
+            Expr::Literal(Literal::Char(c)) => Ok(char_node(*c, nocase)),
+            Expr::CharSet(set) => Ok(Node::Set(if nocase { set.fold_case() } else { (**set).clone() })),
+            Expr::Symbol(s) => {
+                let name = s.name();
+                let assertion = match &*name {
+                    "bos" => Assertion::StartOfString,
+                    "eos" => Assertion::EndOfString,
+                    "bol" => Assertion::StartOfLine,
+                    "eol" => Assertion::EndOfLine,
+                    "bow" => Assertion::StartOfWord,
+                    "eow" => Assertion::EndOfWord,
+                    "nwb" => Assertion::NotWordBoundary,
+                    "epsilon" => return Ok(Node::Empty),
+                    _ => return Ok(Node::Set(self.cset(expr, nocase)?)),
+                };
+                Ok(Node::Assert(assertion))
+            },
+            Expr::List(l) if !l.is_empty() => {
+                let head = match &l[0] {
+                    Expr::Symbol(s) => s.name(),
+                    Expr::Literal(Literal::String(_)) if l.len() == 1 => return Ok(Node::Set(self.cset(expr, nocase)?)),
+                    _ => return Err("invalid SRE"),
+                };
+                let args = &l[1..];
+                match &*head {
+                    ":" | "seq" => self.seq(args, nocase),
+                    "or" => {
+                        let nodes = args.iter().map(|e| self.sre(e, nocase)).collect::<Result<Vec<Node>, _>>()?;
+                        Ok(Node::Alt(nodes))
+                    },
+                    "*" | "+" | "?" | "*?" | "+?" | "??" => {
+                        let node = Box::new(self.seq(args, nocase)?);
+                        let (min, max) = match &head[..1] {
+                            "*" => (0, None),
+                            "+" => (1, None),
+                            _ => (0, Some(1)),
+                        };
+                        Ok(Node::Repeat(node, min, max, head.len() == 1))
+                    },
+                    "=" => {
+                        let n = count_arg(args.first())?;
+                        Ok(Node::Repeat(Box::new(self.seq(&args[1..], nocase)?), n, Some(n), true))
+                    },
+                    ">=" => {
+                        let n = count_arg(args.first())?;
+                        Ok(Node::Repeat(Box::new(self.seq(&args[1..], nocase)?), n, None, true))
+                    },
+                    "**" | "**?" => {
+                        let min = count_arg(args.first())?;
+                        let max = count_arg(args.get(1))?;
+                        if min > max { return Err("repetition minimum exceeds maximum in SRE") }
+                        let node = Box::new(self.seq(args.get(2..).unwrap_or(&[]), nocase)?);
+                        Ok(Node::Repeat(node, min, Some(max), head.len() == 2))
+                    },
+                    "$" | "submatch" => self.group(None, args, nocase),
+                    "->" | "submatch-named" => {
+                        let name = args.first().and_then(|e| e.to_symbol()).ok_or("expecting a submatch name in SRE")?;
+                        self.group(Some(name), &args[1..], nocase)
+                    },
+                    "w/nocase" => self.seq(args, true),
+                    "w/case" => self.seq(args, false),
+                    _ => Ok(Node::Set(self.cset(expr, nocase)?)),
+                }
+            },
+            _ => Err("invalid SRE"),
+        }
+    }
+
+    /// Evaluates an SRE that must denote a set of characters.
+    fn cset(&mut self, expr: &Expr, nocase: bool) -> Result<CharSet, &'static str> {
+        let set = match expr {
+            Expr::Literal(Literal::Char(c)) => CharSet::from_chars([*c]),
+            Expr::Literal(Literal::String(s)) if s.chars().count() == 1 => CharSet::from_chars(s.chars()),
+            Expr::CharSet(set) => (**set).clone(),
+            Expr::Symbol(s) => class_set(&s.name()).ok_or("unknown SRE class name")?,
+            Expr::List(l) if !l.is_empty() => {
+                let args = &l[1..];
+                let head = match &l[0] {
+                    Expr::Literal(Literal::String(s)) if l.len() == 1 => return Ok(self.fold(CharSet::from_chars(s.chars()), nocase)),
+                    Expr::Symbol(s) => s.name(),
+                    _ => return Err("invalid SRE char-set"),
+                };
+                match &*head {
+                    "char-set" => match args.first() {
+                        Some(Expr::Literal(Literal::String(s))) => CharSet::from_chars(s.chars()),
+                        _ => return Err("invalid SRE char-set"),
+                    },
+                    "/" | "char-range" => {
+                        let mut chars = Vec::new();
+                        for arg in args {
+                            match arg {
+                                Expr::Literal(Literal::String(s)) => chars.extend(s.chars()),
+                                Expr::Literal(Literal::Char(c)) => chars.push(*c),
+                                _ => return Err("invalid SRE char range"),
+                            }
+                        }
+                        if chars.len() % 2 != 0 { return Err("SRE char range needs pairs of characters") }
+                        chars.chunks(2).fold(CharSet::empty(), |set, pair| set.union(&CharSet::range(pair[0], pair[1])))
+                    },
+                    "or" => {
+                        let mut set = CharSet::empty();
+                        for arg in args {
+                            set = set.union(&self.cset(arg, nocase)?);
+                        }
+                        set
+                    },
+                    "&" | "and" => {
+                        let mut set = CharSet::full();
+                        for arg in args {
+                            set = set.intersection(&self.cset(arg, nocase)?);
+                        }
+                        set
+                    },
+                    "-" | "difference" => {
+                        let mut set = self.cset(args.first().ok_or("invalid SRE char-set")?, nocase)?;
+                        for arg in &args[1..] {
+                            set = set.difference(&self.cset(arg, nocase)?);
+                        }
+                        set
+                    },
+                    "~" | "complement" => {
+                        let mut set = CharSet::empty();
+                        for arg in args {
+                            set = set.union(&self.cset(arg, nocase)?);
+                        }
+                        return Ok(set.complement());
+                    },
+                    "w/nocase" => return self.cset(args.first().ok_or("invalid SRE char-set")?, true),
+                    "w/case" => return self.cset(args.first().ok_or("invalid SRE char-set")?, false),
+                    _ => return Err("invalid SRE"),
+                }
+            },
+            _ => return Err("invalid SRE char-set"),
+        };
+        Ok(self.fold(set, nocase))
+    }
+
+    fn fold(&self, set: CharSet, nocase: bool) -> CharSet {
+        if nocase { set.fold_case() } else { set }
+    }
+}
+
+/*
+ * String patterns
+ */
+
+/// Parses the familiar string syntax: `.`, `^`, `$`, `|`, groups with
+/// `(...)`, `(?:...)` and `(?<name>...)`, classes with `[...]` including
+/// POSIX `[:name:]`, the escapes `\d \w \s \b` and their negations, and the
+/// quantifiers `* + ? {n} {n,} {n,m}` with optional lazy `?`.
+struct PatternParser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+    names: Vec<(Symbol, usize)>,
+}
+
+impl PatternParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alt(&mut self) -> Result<Node, &'static str> {
+        let mut alts = vec![self.seq()?];
+        while self.eat('|') {
+            alts.push(self.seq()?);
+        }
+        Ok(if alts.len() == 1 { alts.pop().unwrap() } else { Node::Alt(alts) })
+    }
+
+    fn seq(&mut self) -> Result<Node, &'static str> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' { break }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(Node::Seq(nodes))
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect::<String>().parse().ok()
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Result<Node, &'static str> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let start = self.pos;
+                self.pos += 1;
+                let bounds = match self.number() {
+                    Some(min) if self.eat('}') => Some((min, Some(min))),
+                    Some(min) if self.eat(',') => {
+                        let max = self.number();
+                        if self.eat('}') { Some((min, max)) } else { None }
+                    },
+                    _ => None,
+                };
+                match bounds {
+                    Some((min, max)) => {
+                        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+                            return Err("repetition count too large in pattern");
+                        }
+                        if max.is_some_and(|max| min > max) {
+                            return Err("repetition minimum exceeds maximum in pattern");
+                        }
+                        self.pos -= 1;
+                        (min, max)
+                    },
+                    // Not a quantifier, so the brace is literal.
+                    None => {
+                        self.pos = start;
+                        return Ok(atom);
+                    },
+                }
+            },
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        let greedy = !self.eat('?');
+        Ok(Node::Repeat(Box::new(atom), min, max, greedy))
+    }
+
+    fn atom(&mut self) -> Result<Node, &'static str> {
+        let c = self.next().ok_or("unexpected end of pattern")?;
+        match c {
+            '.' => Ok(Node::Set(class_set("nonl").unwrap())),
+            '^' => Ok(Node::Assert(Assertion::StartOfString)),
+            '$' => Ok(Node::Assert(Assertion::EndOfString)),
+            '(' => {
+                let group = if self.eat('?') {
+                    if self.eat(':') {
+                        None
+                    } else if self.eat('<') {
+                        let start = self.pos;
+                        while self.peek().is_some_and(|c| c != '>') {
+                            self.pos += 1;
+                        }
+                        let name: String = self.chars[start..self.pos].iter().collect();
+                        if !self.eat('>') || name.is_empty() { return Err("invalid group name in pattern") }
+                        Some(Some(Symbol::intern(&name)))
+                    } else {
+                        return Err("unsupported group syntax in pattern");
+                    }
+                } else {
+                    Some(None)
+                };
+
+                let index = match group {
+                    Some(name) => {
+                        self.groups += 1;
+                        if let Some(name) = name {
+                            self.names.push((name, self.groups));
+                        }
+                        Some(self.groups)
+                    },
+                    None => None,
+                };
+                let node = self.alt()?;
+                if !self.eat(')') { return Err("unbalanced parenthesis in pattern") }
+                match index {
+                    Some(i) => Ok(Node::Group(i, Box::new(node))),
+                    None => Ok(node),
+                }
+            },
+            ')' => Err("unbalanced parenthesis in pattern"),
+            '[' => Ok(Node::Set(self.class()?)),
+            '*' | '+' | '?' => Err("quantifier without an operand in pattern"),
+            '\\' => self.escape(),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn escape(&mut self) -> Result<Node, &'static str> {
+        let c = self.next().ok_or("trailing backslash in pattern")?;
+        match c {
+            'b' => Ok(Node::Assert(Assertion::WordBoundary)),
+            'B' => Ok(Node::Assert(Assertion::NotWordBoundary)),
+            'A' => Ok(Node::Assert(Assertion::StartOfString)),
+            'z' => Ok(Node::Assert(Assertion::EndOfString)),
+            _ => match self.escape_set(c)? {
+                Some(set) => Ok(Node::Set(set)),
+                None => Ok(Node::Char(self.escape_char(c)?)),
+            },
+        }
+    }
+
+    /// The class named by `\d`, `\w`, `\s` and their upper-case negations.
+    fn escape_set(&self, c: char) -> Result<Option<CharSet>, &'static str> {
+        let set = match c.to_ascii_lowercase() {
+            'd' => CharSet::range('0', '9'),
+            'w' => (*charset::standard("letter+digit")).union(&CharSet::from_chars(['_'])),
+            's' => (*charset::standard("whitespace")).clone(),
+            _ => return Ok(None),
+        };
+        Ok(Some(if c.is_ascii_uppercase() { set.complement() } else { set }))
+    }
+
+    fn escape_char(&mut self, c: char) -> Result<char, &'static str> {
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            'f' => Ok('\x0c'),
+            'v' => Ok('\x0b'),
+            '0' => Ok('\0'),
+            'x' => {
+                let start = self.pos;
+                self.pos = (self.pos + 2).min(self.chars.len());
+                let hex: String = self.chars[start..self.pos].iter().collect();
+                u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or("invalid \\x escape in pattern")
+            },
+            c if c.is_alphanumeric() => Err("unknown escape in pattern"),
+            c => Ok(c),
+        }
+    }
+
+    fn class(&mut self) -> Result<CharSet, &'static str> {
+        let negated = self.eat('^');
+        let mut set = CharSet::empty();
+        let mut first = true;
+        loop {
+            let c = self.next().ok_or("unterminated character class in pattern")?;
+            if c == ']' && !first { break }
+            first = false;
+
+            let from = match c {
+                '[' if self.eat(':') => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| c != ':') {
+                        self.pos += 1;
+                    }
+                    let name: String = self.chars[start..self.pos].iter().collect();
+                    if !(self.eat(':') && self.eat(']')) { return Err("invalid class name in pattern") }
+                    set = set.union(&class_set(&name).ok_or("unknown class name in pattern")?);
+                    continue;
+                },
+                '\\' => {
+                    let e = self.next().ok_or("trailing backslash in pattern")?;
+                    if let Some(class) = self.escape_set(e)? {
+                        set = set.union(&class);
+                        continue;
+                    }
+                    self.escape_char(e)?
+                },
+                c => c,
+            };
+
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']') {
+                self.pos += 1;
+                let to = match self.next() {
+                    Some('\\') => {
+                        let e = self.next().ok_or("trailing backslash in pattern")?;
+                        self.escape_char(e)?
+                    },
+                    Some(c) => c,
+                    None => return Err("unterminated character class in pattern"),
+                };
+                if from > to { return Err("invalid range in character class") }
+                set = set.union(&CharSet::range(from, to));
+            } else {
+                set = set.union(&CharSet::from_chars([from]));
+            }
+        }
+        Ok(if negated { set.complement() } else { set })
+    }
+}
+
+/*
+ * Compilation and matching
+ */
+
+struct Compiler {
+    prog: Vec<Inst>,
+}
+
+impl Compiler {
+    fn emit(&mut self, node: &Node) -> Result<(), &'static str> {
+        if self.prog.len() > MAX_PROGRAM { return Err("regexp is too large") }
+
+        match node {
+            Node::Empty => (),
+            Node::Char(c) => self.prog.push(Inst::Char(*c)),
+            Node::Set(set) => self.prog.push(Inst::Set(set.clone())),
+            Node::Seq(nodes) => {
+                for n in nodes {
+                    self.emit(n)?;
+                }
+            },
+            Node::Alt(alts) => {
+                if alts.is_empty() {
+                    // An empty alternation never matches.
+                    self.prog.push(Inst::Set(CharSet::empty()));
+                    return Ok(());
+                }
+                let mut jumps = Vec::new();
+                for (i, alt) in alts.iter().enumerate() {
+                    if i + 1 < alts.len() {
+                        let split = self.prog.len();
+                        self.prog.push(Inst::Split(split + 1, 0));
+                        self.emit(alt)?;
+                        jumps.push(self.prog.len());
+                        self.prog.push(Inst::Jmp(0));
+                        let next = self.prog.len();
+                        self.prog[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.emit(alt)?;
+                    }
+                }
+                let end = self.prog.len();
+                for j in jumps {
+                    self.prog[j] = Inst::Jmp(end);
+                }
+            },
+            Node::Repeat(node, min, max, greedy) => {
+                for _ in 0..*min {
+                    self.emit(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.prog.len();
+                        self.prog.push(Inst::Split(0, 0));
+                        self.emit(node)?;
+                        self.prog.push(Inst::Jmp(split));
+                        let end = self.prog.len();
+                        self.prog[split] = self.split(split + 1, end, *greedy);
+                    },
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.prog.len());
+                            self.prog.push(Inst::Split(0, 0));
+                            self.emit(node)?;
+                        }
+                        let end = self.prog.len();
+                        for split in splits {
+                            self.prog[split] = self.split(split + 1, end, *greedy);
+                        }
+                    },
+                }
+            },
+            Node::Group(i, node) => {
+                self.prog.push(Inst::Save(2 * i));
+                self.emit(node)?;
+                self.prog.push(Inst::Save(2 * i + 1));
+            },
+            Node::Assert(a) => self.prog.push(Inst::Assert(*a)),
+        }
+        Ok(())
+    }
+
+    fn split(&self, body: usize, out: usize, greedy: bool) -> Inst {
+        if greedy { Inst::Split(body, out) } else { Inst::Split(out, body) }
+    }
+}
+
+enum Job {
+    Run(usize, usize),
+    Restore(usize, Option<usize>),
+}
+
+/// The (instruction, position) pairs a match has tried, as a bitset, or a
+/// hash set when a bitset would be too large.
+enum Visited {
+    Dense(Vec<u64>),
+    Sparse(HashSet<usize>),
+}
+
+impl Visited {
+    fn new(bits: usize) -> Result<Visited, &'static str> {
+        if bits > MAX_DENSE_VISITED { return Ok(Visited::Sparse(HashSet::new())) }
+
+        sandbox::reserve(bits / 8)?;
+        Ok(Visited::Dense(vec![0; bits.div_ceil(64)]))
+    }
+
+    /// Marks `bit`, returning whether it was not marked before.
+    fn insert(&mut self, bit: usize) -> bool {
+        match self {
+            Visited::Dense(words) => {
+                let new = words[bit / 64] & (1 << (bit % 64)) == 0;
+                words[bit / 64] |= 1 << (bit % 64);
+                new
+            },
+            Visited::Sparse(set) => set.insert(bit),
+        }
+    }
+}
+
+impl Regexp {
+    fn compile(node: Node, groups: usize, names: Vec<(Symbol, usize)>) -> Result<Regexp, &'static str> {
+        // 0: split 3, 1   1: any   2: jmp 0   -- the lazy .*? search prefix
+        let mut compiler = Compiler {
+            prog: vec![Inst::Split(ANCHORED, 1), Inst::Set(CharSet::full()), Inst::Jmp(0), Inst::Save(0)],
+        };
+        compiler.emit(&node)?;
+        compiler.prog.push(Inst::Save(1));
+        compiler.prog.push(Inst::Match);
+        Ok(Regexp { prog: compiler.prog, groups, names })
+    }
+
+    pub fn from_sre(sre: &Expr) -> Result<Regexp, &'static str> {
+        let mut parser = SreParser { groups: 0, names: Vec::new() };
+        let node = parser.sre(sre, false)?;
+        Regexp::compile(node, parser.groups, parser.names)
+    }
+
+    pub fn from_pattern(pattern: &str) -> Result<Regexp, &'static str> {
+        let mut parser = PatternParser { chars: pattern.chars().collect(), pos: 0, groups: 0, names: Vec::new() };
+        let node = parser.alt()?;
+        if parser.pos < parser.chars.len() { return Err("unbalanced parenthesis in pattern") }
+        Regexp::compile(node, parser.groups, parser.names)
+    }
+
+    fn assert(a: Assertion, text: &[char], pos: usize, start: usize, end: usize) -> bool {
+        let before = if pos > start { Some(text[pos - 1]) } else { None };
+        let after = if pos < end { Some(text[pos]) } else { None };
+        let word_before = before.is_some_and(is_word);
+        let word_after = after.is_some_and(is_word);
+        match a {
+            Assertion::StartOfString => pos == start,
+            Assertion::EndOfString => pos == end,
+            Assertion::StartOfLine => before.is_none_or(|c| c == '\n'),
+            Assertion::EndOfLine => after.is_none_or(|c| c == '\n'),
+            Assertion::StartOfWord => !word_before && word_after,
+            Assertion::EndOfWord => word_before && !word_after,
+            Assertion::WordBoundary => word_before != word_after,
+            Assertion::NotWordBoundary => word_before == word_after,
+        }
+    }
+
+    /// Runs the program over `text[start..end]` beginning at `from`. A
+    /// search may begin matching anywhere after `from`; otherwise the match
+    /// must begin at `from`, and if `whole` is set it must reach `end`.
+    fn run(&self,
+           text: &[char],
+           start: usize,
+           end: usize,
+           from: usize,
+           search: bool,
+           whole: bool) -> Result<Option<Vec<Option<usize>>>, &'static str> {
+        let width = end - from + 1;
+        let mut visited = Visited::new(self.prog.len().saturating_mul(width))?;
+        let mut slots = vec![None; 2 * (self.groups + 1)];
+        let mut stack = vec![Job::Run(if search { 0 } else { ANCHORED }, from)];
+        let mut steps = 0usize;
+
+        while let Some(job) = stack.pop() {
+            let (mut pc, mut pos) = match job {
+                Job::Run(pc, pos) => (pc, pos),
+                Job::Restore(slot, old) => {
+                    slots[slot] = old;
+                    continue;
+                },
+            };
+
+            loop {
+                steps += 1;
+                if steps.is_multiple_of(eval::POLL_INTERVAL) { eval::poll()? }
+                if !visited.insert(pc * width + (pos - from)) { break }
+
+                match &self.prog[pc] {
+                    Inst::Char(c) => {
+                        if pos < end && text[pos] == *c { pc += 1; pos += 1 } else { break }
+                    },
+                    Inst::Set(set) => {
+                        if pos < end && set.contains(text[pos]) { pc += 1; pos += 1 } else { break }
+                    },
+                    Inst::Split(first, second) => {
+                        stack.push(Job::Run(*second, pos));
+                        pc = *first;
+                    },
+                    Inst::Jmp(target) => pc = *target,
+                    Inst::Save(slot) => {
+                        stack.push(Job::Restore(*slot, slots[*slot]));
+                        slots[*slot] = Some(pos);
+                        pc += 1;
+                    },
+                    Inst::Assert(a) => {
+                        if Regexp::assert(*a, text, pos, start, end) { pc += 1 } else { break }
+                    },
+                    Inst::Match => {
+                        if whole && pos != end { break }
+                        return Ok(Some(slots));
+                    },
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn to_match(&self, text: &Rc<[char]>, slots: Vec<Option<usize>>) -> RegexpMatch {
+        let spans = slots.chunks(2).map(|s| match (s[0], s[1]) {
+            (Some(from), Some(to)) => Some((from, to)),
+            _ => None,
+        }).collect();
+        RegexpMatch { text: text.clone(), spans, names: self.names.clone() }
+    }
+
+    /// Finds the leftmost match in `text[start..end]` beginning at or after
+    /// `from`.
+    pub fn search(&self,
+                  text: &Rc<[char]>,
+                  start: usize,
+                  end: usize,
+                  from: usize) -> Result<Option<RegexpMatch>, &'static str> {
+        Ok(self.run(text, start, end, from, true, false)?.map(|slots| self.to_match(text, slots)))
+    }
+
+    /// Matches the whole of `text[start..end]`.
+    pub fn matches(&self, text: &Rc<[char]>, start: usize, end: usize) -> Result<Option<RegexpMatch>, &'static str> {
+        Ok(self.run(text, start, end, start, false, true)?.map(|slots| self.to_match(text, slots)))
+    }
+}
+
+impl RegexpMatch {
+    /// The number of submatches, not counting the whole match.
+    pub fn count(&self) -> usize {
+        self.spans.len() - 1
+    }
+
+    /// Resolves a submatch given by index or by name.
+    pub fn index(&self, field: &Expr) -> Result<usize, &'static str> {
+        let i = match field {
+            Expr::Literal(Literal::Number(n)) if *n >= 0 => *n as usize,
+            Expr::Symbol(s) => {
+                self.names.iter().find(|(name, _)| name == s).map(|(_, i)| *i).ok_or("unknown submatch name")?
+            },
+            _ => return Err("expecting a submatch index or name"),
+        };
+        if i < self.spans.len() { Ok(i) } else { Err("submatch index out of range") }
+    }
+
+    pub fn span(&self, i: usize) -> Option<(usize, usize)> {
+        self.spans[i]
+    }
+
+    pub fn substring(&self, i: usize) -> Option<String> {
+        self.spans[i].map(|(from, to)| self.text[from..to].iter().collect())
+    }
+}
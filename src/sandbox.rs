@@ -0,0 +1,297 @@
+use crate::ast::Expr;
+use crate::env::Env;
+use crate::eval;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Builtins left out of the default sandbox: those that load code, touch
+/// files, or reach the process and operating system.
+pub const DENIED: &[&str] = &[
+    "load",
+    "load-once",
+    "require",
+    "environment",
+    "scheme-report-environment",
+    "command-line",
+    "exit",
+    "emergency-exit",
+    "get-environment-variable",
+    "get-environment-variables",
+    "file-exists?",
+    "delete-file",
+    "open-input-file",
+    "open-binary-input-file",
+    "open-output-file",
+    "open-binary-output-file",
+    "call-with-input-file",
+    "call-with-output-file",
+    "with-input-from-file",
+    "with-output-to-file",
+];
+
+/// Limits on a single call to `Sandbox::eval`. Fuel is spent one unit per
+/// evaluation step, or per instruction on the VM, and long-running builtins
+/// spend it as they go.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    pub fuel: Option<u64>,
+    /// Bytes allocated beyond what was in use when evaluation started.
+    /// This needs `Allocator` as the global allocator.
+    pub memory: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    OutOfFuel,
+    OutOfMemory,
+    Timeout,
+    /// A memory limit was asked for without `Allocator` installed to
+    /// count allocations.
+    NoAllocator,
+    /// The program failed on its own, with a syntax or evaluation error.
+    Eval(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OutOfFuel => write!(f, "out of fuel"),
+            Error::OutOfMemory => write!(f, "out of memory"),
+            Error::Timeout => write!(f, "timed out"),
+            Error::NoAllocator => write!(f, "memory limits need sandbox::Allocator as the global allocator"),
+            Error::Eval(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An environment to run untrusted programs in. Only the builtins it was
+/// given are bound, and the library forms, which read files and can bind
+/// any builtin, are refused.
+pub struct Sandbox {
+    env: Rc<RefCell<Env>>,
+    limits: Limits,
+}
+
+struct Budget {
+    fuel: Option<u64>,
+    memory: Option<isize>,
+    deadline: Option<Instant>,
+    steps: u32,
+    exceeded: Option<Error>,
+}
+
+/// How many steps run between checks of the clock.
+const CLOCK_INTERVAL: u32 = 1024;
+
+thread_local! {
+    static BUDGET: RefCell<Option<Budget>> = const { RefCell::new(None) };
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+    /// The running sandbox's memory limit as a total for `ALLOCATED`, and
+    /// whether an allocation has gone past it since the sandbox started.
+    static MEMORY_LIMIT: Cell<isize> = const { Cell::new(isize::MAX) };
+    static OVER_LIMIT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A global allocator that counts the bytes allocated on each thread, so
+/// sandboxes can limit memory. Embedders that want memory limits install
+/// it with `#[global_allocator]`.
+pub struct Allocator;
+
+fn count(bytes: isize) {
+    let _ = ALLOCATED.try_with(|allocated| {
+        let total = allocated.get().wrapping_add(bytes);
+        allocated.set(total);
+        if bytes > 0 && total > MEMORY_LIMIT.get() {
+            OVER_LIMIT.set(true);
+        }
+    });
+}
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size() as isize);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size() as isize);
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count(-(layout.size() as isize));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size as isize - layout.size() as isize);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+fn allocated() -> isize {
+    ALLOCATED.with(|allocated| allocated.get())
+}
+
+/// Whether `Allocator` is the global allocator, found by seeing if an
+/// allocation is counted.
+fn counting() -> bool {
+    let before = allocated();
+    let probe = std::hint::black_box(Box::new(0u64));
+    let counted = allocated() != before;
+    drop(probe);
+    counted
+}
+
+/// Makes `memory` the limit the allocator checks against.
+fn limit_memory(memory: Option<isize>) {
+    MEMORY_LIMIT.with(|limit| limit.set(memory.unwrap_or(isize::MAX)));
+    OVER_LIMIT.with(|over| over.set(false));
+}
+
+impl Budget {
+    fn new(limits: &Limits) -> Budget {
+        Budget {
+            fuel: limits.fuel,
+            memory: limits.memory.map(|memory| allocated().saturating_add(memory as isize)),
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            steps: 0,
+            exceeded: None,
+        }
+    }
+
+    fn spend(&mut self) -> Option<Error> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 { return Some(Error::OutOfFuel) }
+            *fuel -= 1;
+        }
+        if let Some(memory) = self.memory && (OVER_LIMIT.with(|over| over.get()) || allocated() > memory) {
+            return Some(Error::OutOfMemory)
+        }
+
+        self.steps = self.steps.wrapping_add(1);
+        if let Some(deadline) = self.deadline && self.steps.is_multiple_of(CLOCK_INTERVAL) && Instant::now() > deadline {
+            return Some(Error::Timeout)
+        }
+        None
+    }
+}
+
+/// Spends a step of the running sandbox's budget. Outside a sandbox it
+/// always succeeds. Once a limit is exceeded every later step fails, so the
+/// program stops even where errors would otherwise be ignored.
+pub fn check() -> Result<(), &'static str> {
+    BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        let budget = match budget.as_mut() {
+            Some(budget) => budget,
+            None => return Ok(()),
+        };
+
+        if budget.exceeded.is_none() {
+            budget.exceeded = budget.spend();
+        }
+        match budget.exceeded {
+            Some(Error::OutOfFuel) => Err("out of fuel"),
+            Some(Error::OutOfMemory) => Err("out of memory"),
+            Some(Error::Timeout) => Err("timed out"),
+            _ => Ok(()),
+        }
+    })
+}
+
+/// Fails, and stops the running sandbox, if allocating `bytes` more would
+/// go over its memory limit. Builtins that allocate as much as they are
+/// asked for call this first, so that the allocation is never made.
+pub fn reserve(bytes: usize) -> Result<(), &'static str> {
+    BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        let budget = match budget.as_mut() {
+            Some(budget) => budget,
+            None => return Ok(()),
+        };
+
+        match budget.memory {
+            Some(memory) if allocated().saturating_add(bytes.min(isize::MAX as usize) as isize) > memory => {
+                budget.exceeded.get_or_insert(Error::OutOfMemory);
+                Err("out of memory")
+            },
+            _ => Ok(()),
+        }
+    })
+}
+
+/// Fails for operations a sandboxed program may not perform.
+pub fn permit() -> Result<(), &'static str> {
+    if BUDGET.with(|budget| budget.borrow().is_some()) {
+        Err("not permitted in the sandbox")
+    } else {
+        Ok(())
+    }
+}
+
+impl Sandbox {
+    /// A sandbox with every builtin except those in `DENIED`.
+    pub fn new(limits: Limits) -> Result<Sandbox, Error> {
+        let names = Env::new().borrow().names();
+        let allowed: Vec<String> = names.iter()
+            .map(|name| name.name().to_string())
+            .filter(|name| !DENIED.contains(&name.as_str()))
+            .collect();
+        let allowed: Vec<&str> = allowed.iter().map(|name| name.as_str()).collect();
+        Sandbox::from_env(Env::restricted(&allowed), limits)
+    }
+
+    /// A sandbox over `env`, which is usually made with `Env::restricted`
+    /// from an explicit list of builtins. A memory limit is refused unless
+    /// `Allocator` is installed, since it could not be enforced.
+    pub fn from_env(env: Rc<RefCell<Env>>, limits: Limits) -> Result<Sandbox, Error> {
+        if limits.memory.is_some() && !counting() { return Err(Error::NoAllocator) }
+
+        Ok(Sandbox { env, limits })
+    }
+
+    pub fn env(&self) -> Rc<RefCell<Env>> {
+        self.env.clone()
+    }
+
+    /// Runs a program within the limits, returning the value of its last
+    /// expression. Definitions are kept for later calls. A panic while it
+    /// runs is returned as an error rather than unwinding into the caller.
+    pub fn eval(&self, source: &str) -> Result<Expr, Error> {
+        let mut lexer = Lexer::new(source);
+        lexer.scan();
+        if let Some(err) = lexer.errors.first() { return Err(Error::Eval(err.message)) }
+        let program = Parser::new(lexer.tokens).parse().map_err(Error::Eval)?;
+
+        let budget = Budget::new(&self.limits);
+        limit_memory(budget.memory);
+        let previous = BUDGET.with(|current| current.replace(Some(budget)));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut result = Ok(Expr::Unspecified);
+            for expr in program.iter() {
+                result = eval::eval(expr, self.env.clone());
+                if result.is_err() { break }
+            }
+            result
+        }));
+        limit_memory(previous.as_ref().and_then(|budget| budget.memory));
+        let budget = BUDGET.with(|budget| budget.replace(previous));
+        let result = result.unwrap_or(Err("evaluation panicked"));
+
+        match budget.and_then(|budget| budget.exceeded) {
+            Some(err) => Err(err),
+            None => result.map_err(Error::Eval),
+        }
+    }
+}
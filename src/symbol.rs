@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// An interned symbol. Symbols are small ids into a per-thread table, so
+/// cloning is free and `eq?` is an integer comparison.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+struct Interner {
+    names: Vec<Rc<str>>,
+    ids: HashMap<Rc<str>, Symbol>,
+    gensym_count: u32,
+}
+
+/// Special form names, interned first so the evaluator can match on them
+/// as constants.
+const PREDEFINED: &[&str] = &[
+    "quote",
+    "lambda",
+    "define",
+    "if",
+    "set!",
+    "begin",
+    "let",
+    "cond",
+    "else",
+    "define-record-type",
+    "define-library",
+    "import",
+    "include",
+    "include-ci",
+    "cond-expand",
+];
+
+pub const QUOTE: Symbol = Symbol(0);
+pub const LAMBDA: Symbol = Symbol(1);
+pub const DEFINE: Symbol = Symbol(2);
+pub const IF: Symbol = Symbol(3);
+pub const SET: Symbol = Symbol(4);
+pub const BEGIN: Symbol = Symbol(5);
+pub const LET: Symbol = Symbol(6);
+pub const COND: Symbol = Symbol(7);
+pub const ELSE: Symbol = Symbol(8);
+pub const DEFINE_RECORD_TYPE: Symbol = Symbol(9);
+pub const DEFINE_LIBRARY: Symbol = Symbol(10);
+pub const IMPORT: Symbol = Symbol(11);
+pub const INCLUDE: Symbol = Symbol(12);
+pub const INCLUDE_CI: Symbol = Symbol(13);
+pub const COND_EXPAND: Symbol = Symbol(14);
+
+impl Interner {
+    fn new() -> Interner {
+        let mut interner = Interner { names: Vec::new(), ids: HashMap::new(), gensym_count: 0 };
+        for name in PREDEFINED {
+            interner.intern(name);
+        }
+        interner
+    }
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(sym) = self.ids.get(name) {
+            return *sym;
+        }
+
+        let sym = Symbol(self.names.len() as u32);
+        let name: Rc<str> = Rc::from(name);
+        self.names.push(name.clone());
+        self.ids.insert(name, sym);
+        sym
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|i| i.borrow_mut().intern(name))
+    }
+
+    /// Creates a fresh symbol that is never returned by `intern`, even for
+    /// the same name.
+    pub fn uninterned(prefix: &str) -> Symbol {
+        INTERNER.with(|i| {
+            let mut i = i.borrow_mut();
+            i.gensym_count += 1;
+            let sym = Symbol(i.names.len() as u32);
+            let name = format!("{}{}", prefix, i.gensym_count);
+            i.names.push(Rc::from(name.as_str()));
+            sym
+        })
+    }
+
+    pub fn name(&self) -> Rc<str> {
+        INTERNER.with(|i| i.borrow().names[self.0 as usize].clone())
+    }
+
+    /// Whether this names a special form, or the `else` keyword, which the
+    /// evaluator recognizes in every environment.
+    pub fn is_syntax(&self) -> bool {
+        (self.0 as usize) < PREDEFINED.len()
+    }
+
+    pub fn is_interned(&self) -> bool {
+        INTERNER.with(|i| {
+            let i = i.borrow();
+            i.ids.get(&i.names[self.0 as usize]) == Some(self)
+        })
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
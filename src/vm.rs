@@ -0,0 +1,261 @@
+use crate::ast::Expr;
+use crate::compiler;
+use crate::compiler::{Code, Op};
+use crate::env::Env;
+use crate::eval;
+use crate::gc;
+use crate::gc::{Trace, Tracer};
+use crate::record;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The slots for one activation of a compiled procedure: its arguments
+/// followed by its internal definitions.
+pub struct Frame {
+    slots: RefCell<Vec<Expr>>,
+    parent: Option<Rc<Frame>>,
+}
+
+/// A procedure compiled to bytecode. Globals live in the `Env` it was
+/// compiled against, which is also what builtins it calls are given.
+pub struct Closure {
+    pub code: Rc<Code>,
+    frame: Option<Rc<Frame>>,
+    globals: Rc<RefCell<Env>>,
+}
+
+struct Activation {
+    code: Rc<Code>,
+    frame: Option<Rc<Frame>>,
+    globals: Rc<RefCell<Env>>,
+    pc: usize,
+    base: usize,
+}
+
+impl Frame {
+    fn lookup(frame: &Option<Rc<Frame>>, depth: usize) -> &Rc<Frame> {
+        let mut frame = frame.as_ref().unwrap();
+        for _ in 0..depth {
+            frame = frame.parent.as_ref().unwrap();
+        }
+        frame
+    }
+
+    /// Empties the slots, for the collector to break a cycle.
+    pub fn clear(&self) -> Vec<Expr> {
+        match self.slots.try_borrow_mut() {
+            Ok(mut slots) => std::mem::take(&mut *slots),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl Trace for Frame {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Some(parent) = &self.parent {
+            tracer.frame(parent);
+        }
+        for slot in self.slots.borrow().iter() {
+            tracer.expr(slot);
+        }
+    }
+}
+
+impl Trace for Closure {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Some(frame) = &self.frame {
+            tracer.frame(frame);
+        }
+        tracer.env(&self.globals);
+    }
+}
+
+/// Compiles and runs a top-level form against `env`.
+pub fn eval(program: &Expr, env: Rc<RefCell<Env>>) -> Result<Expr, &'static str> {
+    let code = compiler::compile(program)?;
+    run(Activation { code, frame: None, globals: env, pc: 0, base: 0 }, Vec::new())
+}
+
+/// Calls a compiled procedure from outside the VM, as builtins like `map`
+/// do.
+pub fn apply(closure: &Closure, args: Vec<Expr>) -> Result<Expr, &'static str> {
+    let frame = enter(closure, args)?;
+    run(Activation { code: closure.code.clone(), frame: Some(frame), globals: closure.globals.clone(), pc: 0, base: 0 },
+        Vec::new())
+}
+
+fn enter(closure: &Closure, mut args: Vec<Expr>) -> Result<Rc<Frame>, &'static str> {
+    if closure.code.arity != args.len() { return Err("applied to incorrect number of args") }
+
+    args.resize(closure.code.frame_size, Expr::Unspecified);
+    Ok(gc::frame(Frame { slots: RefCell::new(args), parent: closure.frame.clone() }))
+}
+
+fn run(act: Activation, stack: Vec<Expr>) -> Result<Expr, &'static str> {
+    eval::nested(|depth| execute(act, stack, depth))
+}
+
+/// Where to resume when an error occurs in a body expression other than
+/// the last. As in the tree-walker, the error is dropped and the body goes
+/// on with its next expression.
+struct Handler {
+    calls: usize,
+    stack: usize,
+    pc: usize,
+}
+
+struct Machine {
+    act: Activation,
+    stack: Vec<Expr>,
+    calls: Vec<Activation>,
+    handlers: Vec<Handler>,
+    /// How deep the evaluations this one is nested in had gone.
+    depth: usize,
+    max_depth: usize,
+}
+
+fn execute(act: Activation, stack: Vec<Expr>, depth: usize) -> Result<Expr, &'static str> {
+    let max_depth = eval::max_depth().saturating_sub(depth);
+    let mut machine = Machine { act, stack, calls: Vec::new(), handlers: Vec::new(), depth, max_depth };
+    loop {
+        match machine.step() {
+            Ok(None) => (),
+            Ok(Some(value)) => return Ok(value),
+            Err(err) => machine.recover(err)?,
+        }
+    }
+}
+
+impl Machine {
+    /// Unwinds to the innermost handler, failing if there is none.
+    fn recover(&mut self, err: &'static str) -> Result<(), &'static str> {
+        let handler = self.handlers.pop().ok_or(err)?;
+        if self.calls.len() > handler.calls {
+            self.act = self.calls.drain(handler.calls..).next().unwrap();
+        }
+        self.stack.truncate(handler.stack);
+        self.stack.push(Expr::Unspecified);
+        self.act.pc = handler.pc;
+        Ok(())
+    }
+
+    /// Runs one instruction, returning the result once the outermost
+    /// activation returns.
+    fn step(&mut self) -> Result<Option<Expr>, &'static str> {
+        let Machine { act, stack, calls, handlers, depth, max_depth } = self;
+        eval::poll()?;
+        let op = act.code.ops[act.pc];
+        act.pc += 1;
+
+        match op {
+            Op::Const(i) => stack.push(act.code.consts[i].clone()),
+            Op::LocalRef(depth, slot) => {
+                let value = Frame::lookup(&act.frame, depth).slots.borrow()[slot].clone();
+                stack.push(value);
+            },
+            Op::LocalSet(depth, slot) => {
+                let value = stack.pop().unwrap();
+                Frame::lookup(&act.frame, depth).slots.borrow_mut()[slot] = value;
+                stack.push(Expr::Unspecified);
+            },
+            Op::LocalDefine(slot) => {
+                let value = stack.pop().unwrap();
+                if value.is_unspecified() {
+                    return Err("unspecified value cannot be used as an expression")
+                }
+                Frame::lookup(&act.frame, 0).slots.borrow_mut()[slot] = value;
+                stack.push(Expr::Unspecified);
+            },
+            Op::GlobalRef(name) => {
+                let value = act.globals.borrow().get(name).ok_or("undefined variable")?;
+                stack.push(value);
+            },
+            Op::GlobalSet(name) => {
+                let value = stack.pop().unwrap();
+                act.globals.borrow_mut().set(name, value)?;
+                stack.push(Expr::Unspecified);
+            },
+            Op::GlobalDefine(name) => {
+                let value = stack.pop().unwrap();
+                if value.is_unspecified() {
+                    return Err("unspecified value cannot be used as an expression")
+                }
+                act.globals.borrow_mut().insert(name, value);
+                stack.push(Expr::Unspecified);
+            },
+            Op::Closure(i) => {
+                let closure = Closure {
+                    code: act.code.codes[i].clone(),
+                    frame: act.frame.clone(),
+                    globals: act.globals.clone(),
+                };
+                stack.push(gc::closure(closure));
+            },
+            Op::Jump(target) => act.pc = target,
+            Op::JumpIfFalse(target) => {
+                if stack.pop().unwrap().is_false() { act.pc = target }
+            },
+            Op::JumpUnlessTrue(target) => {
+                if !stack.pop().unwrap().is_true() { act.pc = target }
+            },
+            Op::Pop => { stack.pop(); },
+            Op::Catch(target) => handlers.push(Handler { calls: calls.len(), stack: stack.len(), pc: target }),
+            Op::Uncatch => { handlers.pop(); },
+            Op::Call(argc) | Op::TailCall(argc) => {
+                let args = stack.split_off(stack.len() - argc);
+                let proc = stack.pop().unwrap();
+
+                if let Expr::Closure(closure) = &proc {
+                    let next = Activation {
+                        code: closure.code.clone(),
+                        frame: Some(enter(closure, args)?),
+                        globals: closure.globals.clone(),
+                        pc: 0,
+                        base: stack.len(),
+                    };
+                    // A tail call reuses the caller's place on the stack,
+                    // so loops written as recursion run in constant space.
+                    if let Op::TailCall(_) = op {
+                        stack.truncate(act.base);
+                        *act = Activation { base: act.base, ..next };
+                    } else {
+                        if calls.len() >= *max_depth { return Err("recursion too deep") }
+                        calls.push(std::mem::replace(act, next));
+                    }
+                    return Ok(None);
+                }
+
+                eval::reached(*depth + calls.len());
+                let value = eval::apply(&proc, args, act.globals.clone())?;
+                stack.push(value);
+                if let Op::TailCall(_) = op {
+                    act.pc = act.code.ops.len() - 1;
+                }
+            },
+            Op::Return => {
+                let value = stack.pop().unwrap();
+                stack.truncate(act.base);
+                match calls.pop() {
+                    Some(caller) => {
+                        *act = caller;
+                        stack.push(value);
+                    },
+                    None => return Ok(Some(value)),
+                }
+            },
+            Op::Record(i) => {
+                let list = act.code.consts[i].to_vec().unwrap();
+                for (_, value) in record::define(&list)? {
+                    stack.push(value);
+                }
+            },
+            Op::Eval(i) => {
+                eval::reached(*depth + calls.len());
+                let value = eval::eval(&act.code.consts[i], act.globals.clone())?;
+                stack.push(value);
+            },
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,93 @@
+//! Runs the same programs on the tree-walker and the bytecode VM, which
+//! must agree on every result and error.
+
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+const CASES: &[(&str, Result<&str, &str>)] = &[
+    ("(+ 1 2 3)", Ok("6")),
+    ("(define x 10) (* x x)", Ok("100")),
+    ("'(1 (2 . 3) \"s\" #\\a sym)", Ok("(1 (2 . 3) \"s\" #\\a sym)")),
+    ("(if #f 1)", Ok("#unspecified")),
+    ("(if '() 'yes 'no)", Ok("yes")),
+    ("(cond ((= 1 2) 'a) ((= 1 1) 'b) (else 'c))", Ok("b")),
+    ("(cond (1 'a) (else 'b))", Ok("b")),
+    ("(cond ((= 1 2) 'a))", Ok("#unspecified")),
+    ("(begin 1 2 3)", Ok("3")),
+    ("(let ((x 1) (y 2)) (+ x y))", Ok("3")),
+    ("(let ((x 1)) (let ((x 2) (y x)) (list x y)))", Ok("(2 1)")),
+    ("((lambda (x y) (- x y)) 5 3)", Ok("2")),
+    ("(define (fact n) (if (= n 0) 1 (* n (fact (- n 1))))) (fact 10)", Ok("3628800")),
+    ("(define (loop n acc) (if (= n 0) acc (loop (- n 1) (+ acc 1)))) (loop 100000 0)", Ok("100000")),
+    ("(define (make-counter)
+        (let ((n 0))
+          (lambda () (set! n (+ n 1)) n)))
+      (define c (make-counter))
+      (c) (c)
+      (c)", Ok("3")),
+    ("(define (f)
+        (define a 1)
+        (define (g) (+ a 1))
+        (set! a 5)
+        (g))
+      (f)", Ok("6")),
+    ("(define g 1) (define (f) (set! g 2)) (f) g", Ok("2")),
+    ("(define (f x) (lambda (y) (lambda (z) (list x y z)))) (((f 1) 2) 3)", Ok("(1 2 3)")),
+    ("(map (lambda (x) (* x x)) '(1 2 3))", Ok("(1 4 9)")),
+    ("(apply + 1 2 '(3 4))", Ok("10")),
+    ("(call-with-values (lambda () (values 1 2)) (lambda (a b) (+ a b)))", Ok("3")),
+    ("(define v (vector 1 2 3)) (vector-set! v 0 'a) v", Ok("#(a 2 3)")),
+    ("(sort '(3 1 2) <)", Ok("(1 2 3)")),
+    ("(define h (make-hash-table)) (hash-table-set! h 'a 1) (hash-table-ref/default h 'a 0)", Ok("1")),
+    ("(string-append \"ab\" \"cd\")", Ok("\"abcd\"")),
+    ("(eval '(+ 1 2) (interaction-environment))", Ok("3")),
+    ("(define-record-type point (make-point x y) point? (x point-x) (y point-y set-point-y!))
+      (define p (make-point 1 2))
+      (set-point-y! p 5)
+      (list (point? p) (point-x p) (point-y p))", Ok("(#t 1 5)")),
+    ("(define (f)
+        (define-record-type box (make-box v) box? (v unbox))
+        (unbox (make-box 7)))
+      (f)", Ok("7")),
+    ("(define (f x)
+        (define-record-type cell (make-cell v) cell? (v cell-v set-cell-v!))
+        (define c (make-cell x))
+        (set-cell-v! c (+ x 1))
+        (list (cell? c) (cell-v c)))
+      (f 1)", Ok("(#t 2)")),
+    ("(let ((n 3))
+        (define-record-type pair (kons a b) kons? (a kar) (b kdr))
+        (kar (kons n 4)))", Ok("3")),
+
+    // An error in a body expression other than the last is ignored.
+    ("(define (f) (car '()) 5) (f)", Ok("5")),
+    ("(begin (undefined-thing) 7)", Ok("7")),
+    ("(let ((x 1)) (car x) (+ x 1))", Ok("2")),
+    ("(define (g) (car '())) (define (f) (g) 'after) (f)", Ok("after")),
+    ("(define (f) (map car '(1 2)) 'after) (f)", Ok("after")),
+    ("(define n 0) (define (f) (car '()) (set! n 1)) (f) n", Ok("1")),
+
+    ("(car '())", Err("called with incorrect type ()")),
+    ("(undefined-thing)", Err("undefined variable")),
+    ("(define (f) 1 (car '())) (f)", Err("called with incorrect type ()")),
+    ("((lambda (x) x))", Err("applied to incorrect number of args")),
+    ("(1 2)", Err("not applicable")),
+    ("(set! nowhere 1)", Err("variable is not bound")),
+    ("(define x (if #f #f))", Err("unspecified value cannot be used as an expression")),
+];
+
+#[test]
+fn backends_agree() {
+    let mut failures = Vec::new();
+    for (source, expected) in CASES {
+        let expected = expected.map(str::to_string);
+        for (name, evaluator) in EVALUATORS {
+            let result = run_with(source, *evaluator);
+            if result != expected {
+                failures.push(format!("{}: {}\n  expected {:?}\n  got      {:?}", name, source, expected, result));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+#[test]
+fn literals_and_constructors() {
+    on_both("#u8(1 2 255)", Ok("#u8(1 2 255)"));
+    on_both("(bytevector 1 2 3)", Ok("#u8(1 2 3)"));
+    on_both("(make-bytevector 3 7)", Ok("#u8(7 7 7)"));
+    on_both("(equal? #u8(1 2) (bytevector 1 2))", Ok("#t"));
+    on_both("(bytevector-append #u8(1) #u8() #u8(2 3))", Ok("#u8(1 2 3)"));
+}
+
+#[test]
+fn literals_must_hold_bytes() {
+    on_both("#u8(256)", Err("bytevector elements must be bytes"));
+    on_both("#u8(1 a)", Err("bytevector elements must be bytes"));
+}
+
+#[test]
+fn elements_must_be_bytes() {
+    on_both("(bytevector 256)", Err("expecting a byte"));
+    on_both("(make-bytevector 2 300)", Err("expecting a byte"));
+    on_both("(make-bytevector -1 0)", Err("expecting a non-negative integer"));
+    on_both("(bytevector-u8-set! (bytevector 1) 0 256)", Err("expecting a byte"));
+}
+
+#[test]
+fn indexing() {
+    on_both("(let ((b (bytevector 1 2 3))) (bytevector-u8-set! b 0 9) b)", Ok("#u8(9 2 3)"));
+    on_both("(bytevector-u8-ref #u8(1 2) 2)", Err("index out of range"));
+    on_both("(bytevector-u8-ref #u8(1) -1)", Err("expecting a non-negative integer"));
+    on_both("(bytevector-length 5)", Err("expecting a bytevector"));
+    on_both("(bytevector-copy #u8(1 2 3 4) 1 3)", Ok("#u8(2 3)"));
+    on_both("(bytevector-copy #u8(1 2 3) 2 1)", Err("index out of range"));
+    on_both("(let ((b (make-bytevector 4 0))) (bytevector-copy! b 1 #u8(7 8)) b)", Ok("#u8(0 7 8 0)"));
+}
+
+#[test]
+fn utf8_conversion() {
+    on_both("(utf8->string #u8(104 105))", Ok("\"hi\""));
+    on_both("(string->utf8 \"λ\")", Ok("#u8(206 187)"));
+    on_both("(utf8->string #u8(255))", Err("bytevector is not valid utf-8"));
+}
+
+#[test]
+fn multi_byte_access_respects_endianness() {
+    on_both("(bytevector-u16-ref #u8(1 2) 0 'little)", Ok("513"));
+    on_both("(bytevector-u16-ref #u8(1 2) 0 'big)", Ok("258"));
+    on_both("(bytevector-s8-ref #u8(255) 0)", Ok("-1"));
+    on_both("(let ((b (make-bytevector 4 0))) (bytevector-s32-set! b 0 -2 'big) b)",
+            Ok("#u8(255 255 255 254)"));
+    on_both("(let ((b (make-bytevector 8 0)))
+               (bytevector-ieee-double-set! b 0 1.5 'little)
+               (bytevector-ieee-double-ref b 0 'little))",
+            Ok("1.5"));
+    on_both("(bytevector-u32-ref #u8(1 2 3) 0 'big)", Err("index out of range"));
+    on_both("(bytevector-u16-ref #u8(1 2) 0 'middle)", Err("endianness must be big or little"));
+}
@@ -0,0 +1,35 @@
+#![allow(dead_code)]
+
+use slippy::ast::Expr;
+use slippy::env::Env;
+use slippy::lexer::Lexer;
+use slippy::parser::Parser;
+use slippy::printer;
+use slippy::{eval, vm};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub type Evaluator = fn(&Expr, Rc<RefCell<Env>>) -> Result<Expr, &'static str>;
+
+/// Both backends, with the name each is reported under.
+pub const EVALUATORS: &[(&str, Evaluator)] = &[("eval", eval::eval), ("vm", vm::eval)];
+
+/// Evaluates a program in a fresh environment and returns what `write`
+/// prints for the value of its last form, or the first error.
+pub fn run_with(source: &str, evaluator: Evaluator) -> Result<String, &'static str> {
+    let mut lexer = Lexer::new(source);
+    lexer.scan();
+    if let Some(err) = lexer.errors.first() { panic!("{}", err) }
+
+    let env = Env::new();
+    let mut value = Expr::Unspecified;
+    for expr in Parser::new(lexer.tokens).parse()? {
+        value = evaluator(&expr, env.clone())?;
+    }
+    Ok(printer::to_string(&value, printer::Mode::Write))
+}
+
+pub fn run(source: &str) -> Result<String, &'static str> {
+    run_with(source, eval::eval)
+}
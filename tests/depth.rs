@@ -0,0 +1,46 @@
+//! Recursion that goes through builtins, which re-enter the evaluator from
+//! Rust, is limited only by the depth limit and not the Rust stack.
+
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}", name);
+    }
+}
+
+#[test]
+fn deep_recursion_through_map() {
+    on_both("(define (deep n) (if (= n 0) 0 (+ 1 (car (map deep (list (- n 1)))))))
+             (deep 12000)",
+            Ok("12000"));
+}
+
+#[test]
+fn deep_recursion_through_apply() {
+    on_both("(define (deep n) (if (= n 0) 0 (+ 1 (apply deep (list (- n 1))))))
+             (deep 12000)",
+            Ok("12000"));
+}
+
+#[test]
+fn runaway_recursion_through_map_fails() {
+    on_both("(define (forever n) (+ 1 (car (map forever (list n))))) (forever 1)",
+            Err("recursion too deep"));
+}
+
+#[test]
+fn nesting_is_limited() {
+    let nested = |n| format!("(length '{}{})", "(".repeat(n), ")".repeat(n));
+    on_both(&nested(500), Ok("1"));
+    on_both(&nested(100_000), Err("nested too deeply"));
+}
+
+#[test]
+fn nesting_of_constructed_code_is_limited() {
+    on_both("(define (nest n) (if (= n 0) 0 (list 'car (list 'list (nest (- n 1))))))
+             (eval (nest 400) (interaction-environment))",
+            Err("nested too deeply"));
+}
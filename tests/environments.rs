@@ -0,0 +1,56 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+#[test]
+fn eval_in_the_report_environments() {
+    on_both("(eval '(+ 1 2) (scheme-report-environment 5))", Ok("3"));
+    on_both("(eval '(if #t 1 2) (null-environment 5))", Ok("1"));
+    on_both("(eval '(+ 1 2) (null-environment 5))", Err("undefined variable"));
+    on_both("(scheme-report-environment 6)", Err("only version 5 of the report is supported"));
+}
+
+#[test]
+fn eval_in_the_interaction_environment() {
+    on_both("(define x 1) (eval 'x (interaction-environment))", Ok("1"));
+    on_both("(eval '(define y 2) (interaction-environment)) y", Ok("2"));
+    on_both("(eval 1)", Ok("1"));
+}
+
+#[test]
+fn environments_from_import_sets() {
+    on_both("(eval '(car '(1)) (environment '(only (scheme base) car)))", Ok("1"));
+    on_both("(let ((names (environment-bound-names (environment '(only (scheme base) car cdr)))))
+               (list (length names) (if (memq 'car names) #t #f) (if (memq 'cdr names) #t #f)))",
+            Ok("(2 #t #t)"));
+    on_both("(environment '(nonexistent lib))", Err("library not found"));
+}
+
+#[test]
+fn definitions_stay_in_their_environment() {
+    on_both("(define e (environment '(scheme base)))
+             (eval '(define z 3) e)
+             (list (eval 'z e) (environment-bound? e 'z) (environment-bound? (interaction-environment) 'z))",
+            Ok("(3 #t #f)"));
+    on_both("(define e (environment '(scheme base))) (eval '(define car 1) e) (car '(5))", Ok("5"));
+}
+
+#[test]
+fn assignment() {
+    on_both("(define e (environment '(scheme base))) (eval '(define q 0) e) (environment-assign! e 'q 5) (eval 'q e)",
+            Ok("5"));
+    on_both("(define e (environment '(scheme base))) (environment-assign! e 'q 1)", Err("variable is not bound"));
+    on_both("(define e (environment '(scheme base))) (eval '(set! car 1) e)", Err("imported variables cannot be set"));
+}
+
+#[test]
+fn arguments_are_checked() {
+    on_both("(eval '(+ 1 2) 5)", Err("expecting an environment"));
+    on_both("(list (environment? 5) (environment? (interaction-environment)))", Ok("(#f #t)"));
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::run;
+
+const CIRCULAR: &str = r#"
+    (define (circular x)
+      (let ((v (vector #f x)))
+        (vector-set! v 0 v)
+        v))
+    (define-record-type node (make-node next val) node?
+      (next node-next set-node-next!) (val node-val))
+    (define (circular-node x)
+      (let ((n (make-node #f x)))
+        (set-node-next! n n)
+        n))
+"#;
+
+#[test]
+fn equal_terminates_on_circular_vectors() {
+    assert_eq!(run(&format!("{} (list (equal? (circular 1) (circular 1)) (equal? (circular 1) (circular 2)))", CIRCULAR)),
+               Ok("(#t #f)".to_string()));
+}
+
+#[test]
+fn equal_terminates_on_circular_records() {
+    assert_eq!(run(&format!("{} (list (equal? (circular-node 1) (circular-node 1)) (equal? (circular-node 1) (circular-node 2)))", CIRCULAR)),
+               Ok("(#t #f)".to_string()));
+}
+
+#[test]
+fn circular_data_can_be_hash_table_keys() {
+    let source = format!("{}
+        (define h (make-hash-table equal?))
+        (hash-table-set! h (circular 1) 'vector)
+        (hash-table-set! h (circular-node 1) 'record)
+        (list (hash-table-ref/default h (circular 1) #f)
+              (hash-table-ref/default h (circular-node 1) #f)
+              (hash-table-ref/default h (circular 2) #f))", CIRCULAR);
+    assert_eq!(run(&source), Ok("(vector record #f)".to_string()));
+}
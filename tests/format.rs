@@ -0,0 +1,16 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn pads_fields_to_their_width() {
+    assert_eq!(run(r#"(format #f "~5a|~5d|~8,3f|~,2f|~3a|" "ab" 42 3.14159 1 "αβ")"#),
+               Ok(r#""ab   |   42|   3.142|1.00|αβ |""#.to_string()));
+}
+
+#[test]
+fn rejects_oversized_fields() {
+    for source in [r#"(format #f "~70000a" 1)"#, r#"(format #f "~,99999f" 1.5)"#, r#"(format #f "~99999999999d" 1)"#] {
+        assert_eq!(run(source), Err("format width or precision is too large"));
+    }
+}
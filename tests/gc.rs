@@ -0,0 +1,42 @@
+mod common;
+
+use common::EVALUATORS;
+use slippy::ast::Expr;
+use slippy::env::Env;
+use slippy::gc;
+use slippy::lexer::Lexer;
+use slippy::parser::Parser;
+
+/// How many objects the collector may be tracking at once while the loop
+/// below runs: a few times its starting threshold, whatever the number of
+/// closures made.
+const MAX_TRACKED: usize = 50_000;
+
+fn parse(source: &str) -> Vec<Expr> {
+    let mut lexer = Lexer::new(source);
+    lexer.scan();
+    Parser::new(lexer.tokens).parse().unwrap()
+}
+
+#[test]
+fn churning_self_referencing_closures_stays_bounded() {
+    let program = parse("
+        (define (churn n)
+          (if (> n 0)
+              (begin
+                (let () (define (self) self) self)
+                (churn (- n 1)))))");
+    let call = parse("(churn 10000)");
+
+    for (name, evaluator) in EVALUATORS {
+        let env = Env::new();
+        for expr in &program {
+            evaluator(expr, env.clone()).unwrap();
+        }
+        for _ in 0..100 {
+            evaluator(&call[0], env.clone()).unwrap();
+            let tracked = gc::stats().1;
+            assert!(tracked < MAX_TRACKED, "{}: {} objects tracked", name, tracked);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: &str) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), Ok(expected.to_string()), "{}", name);
+    }
+}
+
+#[test]
+fn identity_tables_find_keys_changed_after_storing() {
+    for equiv in ["eq?", "eqv?"] {
+        on_both(&format!("
+            (define h (make-hash-table {}))
+            (define v (vector 1))
+            (define b (bytevector 1))
+            (hash-table-set! h v 'vector)
+            (hash-table-set! h b 'bytevector)
+            (vector-set! v 0 2)
+            (bytevector-u8-set! b 0 2)
+            (list (hash-table-ref/default h v #f)
+                  (hash-table-ref/default h b #f)
+                  (hash-table-ref/default h (vector 2) #f))", equiv),
+                "(vector bytevector #f)");
+    }
+}
+
+#[test]
+fn identity_tables_find_changed_records() {
+    on_both("
+        (define-record-type box (make-box v) box? (v box-v set-box-v!))
+        (define h (make-hash-table eq?))
+        (define b (make-box 1))
+        (hash-table-set! h b 'found)
+        (set-box-v! b 2)
+        (hash-table-ref/default h b #f)",
+        "found");
+}
+
+#[test]
+fn procedures_are_equal_to_themselves() {
+    on_both("(define (f) 1) (list (equal? f f) (equal? f (lambda () 1)) (equal? car car) (equal? car cdr))",
+            "(#t #f #t #f)");
+}
+
+#[test]
+fn procedures_can_be_keys_of_equal_tables() {
+    on_both("
+        (define (f) 1)
+        (define h (make-hash-table))
+        (hash-table-set! h f 'lambda)
+        (hash-table-set! h car 'builtin)
+        (list (hash-table-ref/default h f #f)
+              (hash-table-ref/default h car #f)
+              (hash-table-ref/default h cdr #f))",
+        "(lambda builtin #f)");
+}
@@ -0,0 +1,30 @@
+//! The interrupt flag is shared by every thread, so everything about it is
+//! checked in one test.
+
+mod common;
+
+use common::{run_with, EVALUATORS};
+use slippy::eval;
+
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn interrupts_stop_evaluation() {
+    for (name, evaluator) in EVALUATORS {
+        assert!(!eval::interrupt(), "{}", name);
+        assert_eq!(run_with("(+ 1 2)", *evaluator), Err("user interrupt"), "{}", name);
+        assert!(eval::interrupt(), "{}: the first interrupt is still pending", name);
+        eval::clear_interrupt();
+        assert_eq!(run_with("(+ 1 2)", *evaluator), Ok("3".to_string()), "{}", name);
+
+        let interrupter = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(100));
+            eval::interrupt();
+        });
+        assert_eq!(run_with("(length (delete-duplicates (iota 100000)))", *evaluator),
+                   Err("user interrupt"), "{}", name);
+        interrupter.join().unwrap();
+        eval::clear_interrupt();
+    }
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn errors_give_line_and_column() {
+    assert_eq!(run(r#"(json-read "[1, 2,\n  3 x]")"#),
+               Err("invalid JSON at line 2, column 5: expected ',' or ']' in array"));
+    assert_eq!(run(r#"(json-read "{\"a\": tru}")"#),
+               Err("invalid JSON at line 1, column 10: unknown literal"));
+}
+
+#[test]
+fn string_sources_hold_one_value() {
+    assert_eq!(run(r#"(json-read "1 2")"#), Err("invalid JSON at line 1, column 3: unexpected text after value"));
+    assert_eq!(run(r#"(json-read " [1, null] \n")"#), Ok("#(1 null)".to_string()));
+}
+
+#[test]
+fn ports_are_read_a_value_at_a_time() {
+    assert_eq!(run(r#"(define p (open-input-string "1 2")) (list (json-read p) (json-read p) (eof-object? (json-read p)))"#),
+               Ok("(1 2 #t)".to_string()));
+}
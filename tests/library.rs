@@ -0,0 +1,75 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+const COUNTER: &str = "
+    (define-library (counter)
+      (export counter bump!)
+      (import (scheme base))
+      (begin
+        (define counter 0)
+        (define (bump!) (set! counter (+ counter 1)))))
+";
+
+#[test]
+fn imports_see_changes_the_library_makes() {
+    on_both(&format!("{} (import (counter)) (bump!) (bump!) counter", COUNTER), Ok("2"));
+    on_both(&format!("{} (import (prefix (counter) c:)) (c:bump!) c:counter", COUNTER), Ok("1"));
+}
+
+#[test]
+fn imported_variables_cannot_be_set() {
+    on_both(&format!("{} (import (counter)) (set! counter 10)", COUNTER), Err("imported variables cannot be set"));
+}
+
+#[test]
+fn definitions_shadow_imports() {
+    on_both(&format!("{} (import (counter)) (define counter 'mine) (bump!) counter", COUNTER), Ok("mine"));
+}
+
+const SHAPES: &str = "
+    (define-library (shapes)
+      (export area (rename secret hidden-name))
+      (import (scheme base))
+      (begin
+        (define (area r) (* r r))
+        (define secret 42)
+        (define private 1)))
+";
+
+#[test]
+fn only_exported_names_are_imported() {
+    on_both(&format!("{} (import (shapes)) (list (area 3) hidden-name)", SHAPES), Ok("(9 42)"));
+    on_both(&format!("{} (import (shapes)) private", SHAPES), Err("undefined variable"));
+    on_both(&format!("{} (import (shapes)) secret", SHAPES), Err("undefined variable"));
+}
+
+#[test]
+fn import_sets_select_and_rename() {
+    on_both(&format!("{} (import (only (shapes) area)) hidden-name", SHAPES), Err("undefined variable"));
+    on_both(&format!("{} (import (except (shapes) area)) area", SHAPES), Err("undefined variable"));
+    on_both(&format!("{} (import (rename (shapes) (area square))) (square 4)", SHAPES), Ok("16"));
+    on_both(&format!("{} (import (only (shapes) nope))", SHAPES), Err("imported identifier is not exported"));
+}
+
+#[test]
+fn libraries_can_import_each_other() {
+    on_both("(define-library (a) (export x) (import (scheme base)) (begin (define x 1)))
+             (define-library (b) (export y) (import (scheme base) (a)) (begin (define y (+ x 1))))
+             (import (b))
+             y",
+            Ok("2"));
+    on_both(&format!("{} (import (shapes)) (import (shapes)) (area 2)", SHAPES), Ok("4"));
+}
+
+#[test]
+fn broken_libraries_are_reported() {
+    on_both("(import (no such lib))", Err("library not found"));
+    on_both("(define-library (bad) (export missing) (begin 1))", Err("library exports an undefined identifier"));
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+#[test]
+fn iota_stops_at_the_integer_range() {
+    on_both("(iota 3 2147483645)", Ok("(2147483645 2147483646 2147483647)"));
+    on_both("(iota 3 0 -1073741824)", Ok("(0 -1073741824 -2147483648)"));
+    on_both("(iota 3 2147483647)", Err("integer out of range"));
+    on_both("(iota 4 0 -1073741824)", Err("integer out of range"));
+}
+
+#[test]
+fn iota_counts_up_from_start_by_step() {
+    on_both("(iota 5)", Ok("(0 1 2 3 4)"));
+    on_both("(iota 3 1.5 0.5)", Ok("(1.5 2.0 2.5)"));
+    on_both("(iota -1)", Err("expecting a non-negative integer"));
+}
+
+#[test]
+fn folds_and_reductions() {
+    on_both("(fold cons '() '(1 2 3))", Ok("(3 2 1)"));
+    on_both("(fold + 0 '(1 2) '(10 20 30))", Ok("33"));
+    on_both("(fold-right cons '() '(1 2 3))", Ok("(1 2 3)"));
+    on_both("(reduce + 0 '())", Ok("0"));
+    on_both("(reduce max 0 '(3 9 2))", Ok("9"));
+}
+
+#[test]
+fn filtering_and_partitioning() {
+    on_both("(filter odd? '(1 2 3 4 5))", Ok("(1 3 5)"));
+    on_both("(remove odd? '(1 2 3 4 5))", Ok("(2 4)"));
+    on_both("(call-with-values (lambda () (partition odd? '(1 2 3 4))) list)", Ok("((1 3) (2 4))"));
+    on_both("(filter-map (lambda (x) (if (odd? x) (* x x) #f)) '(1 2 3))", Ok("(1 9)"));
+    on_both("(append-map (lambda (x) (list x x)) '(1 2))", Ok("(1 1 2 2)"));
+    on_both("(filter 5 '(1))", Err("unable to apply"));
+}
+
+#[test]
+fn searching() {
+    on_both("(find even? '(1 3 4 5))", Ok("4"));
+    on_both("(find even? '(1 3))", Ok("#f"));
+    on_both("(find-tail even? '(1 4 5))", Ok("(4 5)"));
+    on_both("(any odd? '(2 4 5))", Ok("#t"));
+    on_both("(any + '(1) '(2))", Ok("3"));
+    on_both("(every odd? '(1 3))", Ok("#t"));
+    on_both("(every odd? '())", Ok("#t"));
+    on_both("(count even? '(1 2 4))", Ok("2"));
+    on_both("(list-index even? '(1 3 4))", Ok("2"));
+}
+
+#[test]
+fn deletion() {
+    on_both("(delete 2 '(1 2 3 2))", Ok("(1 3)"));
+    on_both("(delete 5 '(3 5 7) <)", Ok("(3 5)"));
+    on_both("(delete-duplicates '(a b a c b))", Ok("(a b c)"));
+    on_both("(alist-delete 'a '((a . 1) (b . 2) (a . 3)))", Ok("((b . 2))"));
+}
+
+#[test]
+fn taking_apart() {
+    on_both("(last '(1 2 3))", Ok("3"));
+    on_both("(last '())", Err("expecting a pair"));
+    on_both("(take '(1 2 3) 2)", Ok("(1 2)"));
+    on_both("(drop '(1 2 3) 2)", Ok("(3)"));
+    on_both("(take '(1 2) 3)", Err("index out of range"));
+    on_both("(call-with-values (lambda () (split-at '(1 2 3) 1)) list)", Ok("((1) (2 3))"));
+}
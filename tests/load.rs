@@ -0,0 +1,88 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+use std::path::{Path, PathBuf};
+
+/// Makes a directory of its own in the temporary directory holding `files`.
+fn directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("slippy-load-{}-{}", std::process::id(), name));
+    for (file, contents) in files {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+/// Runs `source` on both evaluators with `{dir}` standing for `dir`, and
+/// `{dir}` in an expected error standing for it too.
+fn in_directory(dir: &Path, source: &str, expected: Result<&str, &str>) {
+    let dir = dir.to_str().unwrap();
+    let source = source.replace("{dir}", dir);
+    let expected = expected.map(str::to_string).map_err(|err| err.replace("{dir}", dir));
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(&source, *evaluator).map_err(str::to_string), expected, "{}: {}", name, source);
+    }
+}
+
+#[test]
+fn relative_paths_resolve_against_the_loading_file() {
+    let dir = directory("relative", &[("main.scm", "(define a 1) (load \"sub/b.scm\")"),
+                                      ("sub/b.scm", "(load \"c.scm\") (define b (+ a c))"),
+                                      ("sub/c.scm", "(define c 10)")]);
+    in_directory(&dir, "(load \"{dir}/main.scm\") b", Ok("11"));
+}
+
+#[test]
+fn errors_name_the_file_and_line() {
+    let dir = directory("errors", &[("bad.scm", "(define c 1)\n(car '())"),
+                                    ("syntax.scm", "(define x 1"),
+                                    ("outer.scm", "\n\n(load \"bad.scm\")")]);
+    in_directory(&dir, "(load \"{dir}/missing.scm\")", Err("{dir}/missing.scm: no such file"));
+    in_directory(&dir, "(load \"{dir}/bad.scm\")", Err("{dir}/bad.scm:2: called with incorrect type ()"));
+    in_directory(&dir, "(load \"{dir}/syntax.scm\")", Err("{dir}/syntax.scm:1: expecting a list"));
+    in_directory(&dir, "(load \"{dir}/outer.scm\")",
+                 Err("{dir}/outer.scm:3: {dir}/bad.scm:2: called with incorrect type ()"));
+    in_directory(&dir, "(load 5)", Err("expecting a string"));
+}
+
+#[test]
+fn load_once_skips_files_already_loaded() {
+    let dir = directory("once", &[("count.scm", "(define n (+ n 1))")]);
+    in_directory(&dir, "(define n 0) (load \"{dir}/count.scm\") (load \"{dir}/count.scm\") n", Ok("2"));
+    in_directory(&dir, "(define n 0) (load \"{dir}/count.scm\") (load-once \"{dir}/count.scm\") (require \"{dir}/count.scm\") n",
+                 Ok("1"));
+}
+
+#[test]
+fn load_once_loads_a_new_file() {
+    // Files loaded are remembered for the whole thread, so each evaluator
+    // needs a file it has not seen.
+    for (name, evaluator) in EVALUATORS {
+        let file = format!("{}.scm", name);
+        let dir = directory("new", &[(&file, "(define n (+ n 1))")]);
+        let source = format!("(define n 0) (load-once {:?}) (load-once {:?}) n",
+                             dir.join(&file).to_str().unwrap(), dir.join(&file).to_str().unwrap());
+        assert_eq!(run_with(&source, *evaluator), Ok("1".to_string()), "{}", name);
+    }
+}
+
+#[test]
+fn circular_loads_are_errors() {
+    let dir = directory("circular", &[("self.scm", "(load \"self.scm\")"),
+                                      ("a.scm", "(load \"b.scm\")"),
+                                      ("b.scm", "(load \"a.scm\")")]);
+    in_directory(&dir, "(load \"{dir}/self.scm\")", Err("{dir}/self.scm:1: {dir}/self.scm: circular load"));
+    in_directory(&dir, "(load \"{dir}/a.scm\")",
+                 Err("{dir}/a.scm:1: {dir}/b.scm:1: {dir}/a.scm: circular load"));
+}
+
+#[test]
+fn files_can_be_loaded_into_an_environment() {
+    let dir = directory("environment", &[("def.scm", "(define loaded 'yes)")]);
+    in_directory(&dir, "(define e (environment '(scheme base)))
+                        (load \"{dir}/def.scm\" e)
+                        (list (eval 'loaded e) (environment-bound? (interaction-environment) 'loaded))",
+                 Ok("(yes #f)"));
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+/// Runs `source` after defining `c` as the default comparator.
+fn with_comparator(source: &str, expected: Result<&str, &str>) {
+    on_both(&format!("(define c (make-default-comparator)) {}", source), expected);
+}
+
+#[test]
+fn mappings_keep_keys_in_order() {
+    with_comparator("(mapping->alist (mapping c 'b 2 'a 1 'c 3))", Ok("((a . 1) (b . 2) (c . 3))"));
+    with_comparator("(mapping->alist (alist->mapping c '((b . 2) (a . 1) (b . 3))))", Ok("((a . 1) (b . 2))"));
+    with_comparator("(list (mapping-keys (mapping c 3 'c 1 'a)) (mapping-values (mapping c 3 'c 1 'a)))",
+                    Ok("((1 3) (a c))"));
+    on_both("(mapping->alist (mapping (lambda (a b) (> a b)) 1 'a 2 'b))", Ok("((2 . b) (1 . a))"));
+}
+
+#[test]
+fn looking_up_keys() {
+    with_comparator("(let ((m (mapping c 'a 1)))
+                       (list (mapping-ref m 'a) (mapping-ref/default m 'z 0) (mapping-contains? m 'z)
+                             (mapping-size m) (mapping-empty? (mapping c))))",
+                    Ok("(1 0 #f 1 #t)"));
+    with_comparator("(mapping-ref (mapping c 'a 1) 'z)", Err("key not found in mapping"));
+    with_comparator("(mapping-ref (mapping c 'a 1) 'z (lambda () 'none))", Ok("none"));
+    with_comparator("(mapping-ref (mapping c 'a 1) 'a (lambda () 'none) (lambda (v) (* v 10)))", Ok("10"));
+}
+
+#[test]
+fn updates_make_new_mappings() {
+    with_comparator("(let ((m (mapping c 'a 1)))
+                       (list (mapping->alist (mapping-set m 'a 5 'b 2))
+                             (mapping->alist (mapping-adjoin m 'a 5 'b 2))
+                             (mapping->alist m)))",
+                    Ok("(((a . 5) (b . 2)) ((a . 1) (b . 2)) ((a . 1)))"));
+    with_comparator("(mapping->alist (mapping-delete (mapping c 1 'x 2 'y 3 'z) 1 3))", Ok("((2 . y))"));
+}
+
+#[test]
+fn traversal() {
+    with_comparator("(mapping-fold (lambda (k v acc) (cons k acc)) '() (mapping c 'a 1 'b 2))", Ok("(b a)"));
+    with_comparator("(with-output-to-string (lambda () (mapping-for-each (lambda (k v) (display k)) (mapping c 2 'x 1 'y))))",
+                    Ok("\"12\""));
+}
+
+#[test]
+fn arguments_are_checked() {
+    with_comparator("(mapping c 'a)", Err("expecting keys and values in pairs"));
+    with_comparator("(mapping c 1 'a \"x\" 'b)", Err("keys cannot be compared by the default comparator"));
+    on_both("(mapping 5 'a 1)", Err("expecting a comparator"));
+    with_comparator("(eq? c (mapping-comparator (mapping c)))", Ok("#t"));
+}
+
+#[test]
+fn association_lists() {
+    on_both("(assq 'b '((a 1) (b 2)))", Ok("(b 2)"));
+    on_both("(assv 2 '((1 . a) (2 . b)))", Ok("(2 . b)"));
+    on_both("(assoc \"b\" '((\"a\" . 1) (\"b\" . 2)))", Ok("(\"b\" . 2)"));
+    on_both("(assq 'c '((a 1)))", Ok("#f"));
+    on_both("(alist-cons 'a 1 '((b . 2)))", Ok("((a . 1) (b . 2))"));
+    on_both("(alist-copy '((a . 1)))", Ok("((a . 1))"));
+}
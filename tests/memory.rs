@@ -0,0 +1,39 @@
+//! Memory limits, which need `sandbox::Allocator` as the global allocator.
+
+use slippy::sandbox::{Allocator, Error, Limits, Sandbox};
+
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator;
+
+fn sandbox() -> Sandbox {
+    Sandbox::new(Limits { memory: Some(16 << 20), ..Default::default() }).unwrap()
+}
+
+#[test]
+fn small_programs_fit() {
+    assert!(sandbox().eval("(length (make-list 1000 'x))").is_ok());
+}
+
+#[test]
+fn large_allocations_by_builtins_are_refused() {
+    for source in ["(make-string 1000000000 #\\a)",
+                   "(make-vector 1000000000 0)",
+                   "(make-bytevector 1000000000 0)",
+                   "(make-list 1000000000 0)",
+                   "(iota 1000000000)"] {
+        assert_eq!(sandbox().eval(source).err(), Some(Error::OutOfMemory), "{}", source);
+    }
+}
+
+#[test]
+fn growing_data_runs_out() {
+    let source = "(define (grow acc) (grow (cons (make-string 1000 #\\a) acc))) (grow '())";
+    assert_eq!(sandbox().eval(source).err(), Some(Error::OutOfMemory));
+}
+
+#[test]
+fn going_over_inside_a_builtin_is_caught() {
+    // The string is freed before the next step, but still went over.
+    let source = "(string-length (make-string 10000000 #\\a)) (string-length (make-string 10000000 #\\a))";
+    assert_eq!(sandbox().eval(source).err(), Some(Error::OutOfMemory));
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+#[test]
+fn integers_past_the_fixnum_range_are_read_as_inexact() {
+    on_both("(list 2147483647 -2147483648)", Ok("(2147483647 -2147483648)"));
+    on_both("(list 2147483648 -2147483649 99999999999)", Ok("(2147483648.0 -2147483649.0 99999999999.0)"));
+    on_both("(exact? 2147483648)", Ok("#f"));
+}
+
+#[test]
+fn string_to_number_reads_large_integers_in_any_radix() {
+    on_both(r##"(list (string->number "2147483647") (string->number "2147483648") (string->number "99999999999"))"##,
+            Ok("(2147483647 2147483648.0 99999999999.0)"));
+    on_both(r##"(list (string->number "#x7fffffff") (string->number "#xffffffff") (string->number "-80000001" 16))"##,
+            Ok("(2147483647 4294967295.0 -2147483649.0)"));
+}
+
+#[test]
+fn exact_numerals_past_the_fixnum_range_are_not_numbers() {
+    on_both(r##"(list (string->number "#e2147483647") (string->number "#e2147483648"))"##, Ok("(2147483647 #f)"));
+}
+
+#[test]
+fn prefixes_set_the_radix_and_exactness() {
+    on_both("(list #xff #b-101 #o17 #d10)", Ok("(255 -5 15 10)"));
+    on_both(r##"(list (string->number "#x-1A") (string->number "ff" 16) (string->number "#b101" 10))"##,
+            Ok("(-26 255 5)"));
+    on_both(r##"(list (string->number "#i3") (string->number "#x#e10") (string->number "#e#x10"))"##,
+            Ok("(3.0 16 16)"));
+    on_both(r##"(string->number "#e1.5")"##, Ok("#f"));
+}
+
+#[test]
+fn each_prefix_may_only_appear_once() {
+    on_both(r##"(list (string->number "#e#e1") (string->number "#x#b1") (string->number "#i#e1"))"##,
+            Ok("(#f #f #f)"));
+}
+
+#[test]
+fn decimals_and_special_values() {
+    on_both(r##"(list (string->number "1e3") (string->number ".5") (string->number "+5"))"##,
+            Ok("(1000.0 0.5 5)"));
+    on_both(r##"(list (string->number "+inf.0") (string->number "-inf.0") (string->number "+nan.0"))"##,
+            Ok("(+inf.0 -inf.0 +nan.0)"));
+}
+
+#[test]
+fn malformed_numerals_are_not_numbers() {
+    on_both(r##"(map string->number '("abc" "" "-" "1.2.3" "#b102" "1/2" "#q1"))"##,
+            Ok("(#f #f #f #f #f #f #f)"));
+}
+
+#[test]
+fn only_the_usual_radixes_are_supported() {
+    on_both(r##"(string->number "12" 7)"##, Err("radix must be 2, 8, 10 or 16"));
+    on_both("(number->string 12 37)", Err("radix must be 2, 8, 10 or 16"));
+}
+
+#[test]
+fn number_to_string_writes_in_a_radix() {
+    on_both("(list (number->string 255 16) (number->string -10 2) (number->string 255 8) (number->string 0 2))",
+            Ok("(\"ff\" \"-1010\" \"377\" \"0\")"));
+    on_both("(number->string 1.5)", Ok("\"1.5\""));
+    on_both("(number->string 1.5 2)", Err("inexact numbers can only be written in radix 10"));
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+/// Runs `source` on both evaluators with `{path}` standing for a file of
+/// its own in the temporary directory, which is removed afterwards.
+fn with_file(file: &str, source: &str, expected: Result<&str, &str>) {
+    let path = std::env::temp_dir().join(format!("slippy-{}-{}", std::process::id(), file));
+    let source = source.replace("{path}", &format!("{:?}", path.to_str().unwrap()));
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(&source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+#[test]
+fn textual_files_round_trip() {
+    with_file("text", "(call-with-output-file {path}
+                         (lambda (p) (write '(a \"b\") p) (newline p) (write-string \"second\" p)))
+                       (call-with-input-file {path}
+                         (lambda (p)
+                           (let ((datum (read p)))
+                             (read-char p)
+                             (let ((line (read-line p)))
+                               (list datum line (eof-object? (read-line p)))))))",
+              Ok("((a \"b\") \"second\" #t)"));
+    with_file("redirect", "(with-output-to-file {path} (lambda () (display \"hi\")))
+                           (with-input-from-file {path} read-line)",
+              Ok("\"hi\""));
+}
+
+#[test]
+fn reading_characters() {
+    with_file("chars", "(call-with-output-file {path} (lambda (p) (write-string \"abcdef\" p)))
+                        (let ((p (open-input-file {path})))
+                          (list (read-char p) (peek-char p) (read-string 3 p) (read-string 9 p)
+                                (eof-object? (read-char p))))",
+              Ok("(#\\a #\\b \"bcd\" \"ef\" #t)"));
+}
+
+#[test]
+fn binary_files_round_trip() {
+    with_file("binary", "(let ((p (open-binary-output-file {path})))
+                           (write-u8 1 p) (write-bytevector #u8(2 3) p) (close-port p))
+                         (let ((p (open-binary-input-file {path})))
+                           (list (read-u8 p) (peek-u8 p) (read-bytevector 5 p) (eof-object? (read-u8 p))))",
+              Ok("(1 2 #u8(2 3) #t)"));
+}
+
+#[test]
+fn port_kinds_are_checked() {
+    with_file("kinds", "(call-with-output-file {path} (lambda (p) #t))
+                        (let ((p (open-input-file {path})))
+                          (list (port? p) (input-port? p) (output-port? p) (textual-port? p) (binary-port? p)))",
+              Ok("(#t #t #f #t #f)"));
+    with_file("textual", "(call-with-output-file {path} (lambda (p) #t))
+                          (read-char (open-binary-input-file {path}))",
+              Err("expecting a textual input port"));
+    with_file("output", "(call-with-output-file {path} (lambda (p) #t))
+                         (write-char #\\a (open-input-file {path}))",
+              Err("expecting an output port"));
+}
+
+#[test]
+fn closed_ports_cannot_be_used() {
+    with_file("closed", "(call-with-output-file {path} (lambda (p) #t))
+                         (define p (open-input-file {path}))
+                         (close-port p)
+                         (input-port-open? p)",
+              Ok("#f"));
+    with_file("closed-read", "(call-with-output-file {path} (lambda (p) #t))
+                              (define p (open-input-file {path}))
+                              (close-port p)
+                              (read-char p)",
+              Err("port is closed"));
+}
+
+#[test]
+fn files_can_be_checked_and_deleted() {
+    with_file("delete", "(call-with-output-file {path} (lambda (p) #t))
+                         (define before (file-exists? {path}))
+                         (delete-file {path})
+                         (list before (file-exists? {path}))",
+              Ok("(#t #f)"));
+    with_file("missing", "(open-input-file {path})", Err("unable to open file for input"));
+}
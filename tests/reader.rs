@@ -0,0 +1,57 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn circular_vector_round_trips() {
+    let source = r##"
+        (define v (vector 1 2))
+        (vector-set! v 0 v)
+        (define out (open-output-string))
+        (write v out)
+        (define r (read (open-input-string (get-output-string out))))
+        (list (get-output-string out) (eq? r (vector-ref r 0)) (vector-ref r 1))
+    "##;
+    assert_eq!(run(source), Ok(r##"("#0=#(#0# 2)" #t 2)"##.to_string()));
+}
+
+#[test]
+fn labels_refer_back_through_nested_data() {
+    let source = r##"
+        (define l (read (open-input-string "#0=(a #(b #0#))")))
+        (define v (cadr l))
+        (list (car l) (eq? v (cadr (vector-ref v 1))))
+    "##;
+    assert_eq!(run(source), Ok("(a #t)".to_string()));
+
+    let source = r##"
+        (define v (read (open-input-string "#0=#(#1=(p q) #1# #0#)")))
+        (list (vector-ref v 0) (eq? v (vector-ref v 2)))
+    "##;
+    assert_eq!(run(source), Ok("((p q) #t)".to_string()));
+}
+
+#[test]
+fn lists_cannot_contain_themselves() {
+    assert_eq!(run(r##"(read (open-input-string "#0=(a #0#)"))"##),
+               Err("circular datum labels are only supported through vectors"));
+    assert_eq!(run(r##"(read (open-input-string "#0=(a . #0#)"))"##),
+               Err("circular datum labels are only supported through vectors"));
+}
+
+#[test]
+fn write_shared_labels_vectors_but_not_lists() {
+    let source = r##"
+        (define (shared x)
+          (let ((out (open-output-string)))
+            (write-shared (list x x) out)
+            (get-output-string out)))
+        (list (shared (list 1 2)) (shared (vector 1 2)))
+    "##;
+    assert_eq!(run(source), Ok(r##"("((1 2) (1 2))" "(#0=#(1 2) #0#)")"##.to_string()));
+}
+
+#[test]
+fn malformed_datum_reports_the_lexer_error() {
+    assert_eq!(run(r##"(read (open-input-string "#\\bogus"))"##), Err("unknown character name"));
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+const POINT: &str = "(define-record-type point (make-point x y) point?
+                       (x point-x set-point-x!)
+                       (y point-y))";
+
+#[test]
+fn constructor_predicate_and_accessors() {
+    on_both(&format!("{} (define p (make-point 1 2)) (list (point? p) (point? 5) (point-x p) (point-y p))", POINT),
+            Ok("(#t #f 1 2)"));
+    on_both(&format!("{} (make-point 1 2)", POINT), Ok("#<record point x: 1 y: 2>"));
+}
+
+#[test]
+fn modifiers_change_the_record() {
+    on_both(&format!("{} (define p (make-point 1 2)) (set-point-x! p 10) (point-x p)", POINT), Ok("10"));
+}
+
+#[test]
+fn fields_the_constructor_leaves_out_are_unspecified() {
+    on_both("(define-record-type point (make-point x) point? (x point-x) (y point-y))
+             (point-y (make-point 1))",
+            Ok("#unspecified"));
+}
+
+#[test]
+fn record_types_are_distinct() {
+    on_both(&format!("{} (define-record-type other (make-other a) other? (a other-a))
+                       (list (point? (make-other 1)) (other? (make-point 1 2)))", POINT),
+            Ok("(#f #f)"));
+    on_both(&format!("{} (define-record-type other (make-other a) other? (a other-a))
+                       (point-x (make-other 1))", POINT),
+            Err("record procedure applied to a value of the wrong type"));
+}
+
+#[test]
+fn definitions_are_checked() {
+    on_both(&format!("{} (make-point 1)", POINT), Err("applied to incorrect number of args"));
+    on_both("(define-record-type point (make-point z) point? (x point-x))",
+            Err("constructor names an unknown record field"));
+}
+
+#[test]
+fn record_types_can_be_defined_in_bodies() {
+    on_both("(define (f) (define-record-type box (make-box v) box? (v unbox)) (unbox (make-box 3))) (f)",
+            Ok("3"));
+}
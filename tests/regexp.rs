@@ -0,0 +1,63 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+#[test]
+fn nested_repeats_cannot_make_a_huge_program() {
+    on_both(r#"(string->regexp "((a{1000}){1000}){1000}")"#, Err("regexp is too large"));
+    on_both("(regexp '(= 1000 (= 1000 (= 1000 \"a\"))))", Err("regexp is too large"));
+    on_both("(valid-sre? '(= 1000 (= 1000 \"a\")))", Ok("#f"));
+}
+
+#[test]
+fn long_texts_are_searched_without_a_huge_bitset() {
+    on_both(r#"(regexp-match-submatch (regexp-search (string->regexp "b{1000}") (string-append (make-string 100000 #\a) (make-string 1000 #\b))) 0)"#,
+            Ok(&format!("\"{}\"", "b".repeat(1000))));
+}
+
+#[test]
+fn whole_string_matching() {
+    on_both(r#"(regexp-matches? (string->regexp "a+b") "aaab")"#, Ok("#t"));
+    on_both(r#"(regexp-matches? (string->regexp "a+b") "aaabc")"#, Ok("#f"));
+    on_both(r#"(regexp-matches? (string->regexp "a|b") "b")"#, Ok("#t"));
+    on_both(r#"(regexp-matches? (string->regexp "[^a-c]") "d")"#, Ok("#t"));
+    on_both(r#"(regexp-matches? (regexp '(: (+ digit) "x")) "12x")"#, Ok("#t"));
+    on_both(r#"(regexp-matches? (regexp '(w/nocase "abc")) "AbC")"#, Ok("#t"));
+}
+
+#[test]
+fn searching_reports_submatches() {
+    on_both(r#"(regexp-match->list (regexp-search (string->regexp "([0-9]+)-([0-9]+)") "tel 12-34 x"))"#,
+            Ok(r#"("12-34" "12" "34")"#));
+    on_both(r#"(let ((m (regexp-search (string->regexp "b+") "aabbb")))
+                 (list (regexp-match-submatch-start m 0) (regexp-match-submatch-end m 0)))"#,
+            Ok("(2 5)"));
+    on_both(r#"(let ((m (regexp-search (string->regexp "(a)(b)?") "a")))
+                 (list (regexp-match-count m) (regexp-match-submatch m 2)))"#,
+            Ok("(2 #f)"));
+    on_both(r#"(regexp-search (string->regexp "z") "abc")"#, Ok("#f"));
+    on_both(r#"(regexp-match-submatch (regexp-search (string->regexp "a") "a") 5)"#,
+            Err("submatch index out of range"));
+}
+
+#[test]
+fn replacing_splitting_and_extracting() {
+    on_both(r#"(regexp-replace (string->regexp "o") "foo boo" "0")"#, Ok(r#""f0o boo""#));
+    on_both(r#"(regexp-replace-all (string->regexp "o") "foo boo" "0")"#, Ok(r#""f00 b00""#));
+    on_both(r#"(regexp-split (string->regexp ",") "a,b,,c")"#, Ok(r#"("a" "b" "" "c")"#));
+    on_both(r#"(regexp-extract (string->regexp "[0-9]+") "a1b22c333")"#, Ok(r#"("1" "22" "333")"#));
+}
+
+#[test]
+fn malformed_patterns_are_rejected() {
+    on_both(r#"(string->regexp "(a")"#, Err("unbalanced parenthesis in pattern"));
+    on_both(r#"(string->regexp "(?i)abc")"#, Err("unsupported group syntax in pattern"));
+    on_both("(regexp '(bogus))", Err("invalid SRE"));
+    on_both("(list (valid-sre? '(* \"a\")) (valid-sre? '(bogus)))", Ok("(#t #f)"));
+}
@@ -0,0 +1,80 @@
+//! Sandboxes in a program without `sandbox::Allocator`, which cannot limit
+//! memory.
+
+use slippy::sandbox::{Error, Limits, Sandbox};
+
+use std::time::{Duration, Instant};
+
+fn with_fuel(fuel: u64) -> Sandbox {
+    Sandbox::new(Limits { fuel: Some(fuel), ..Default::default() }).unwrap()
+}
+
+#[test]
+fn memory_limit_needs_the_allocator() {
+    let limits = Limits { memory: Some(1 << 20), ..Default::default() };
+    assert!(matches!(Sandbox::new(limits), Err(Error::NoAllocator)));
+}
+
+#[test]
+fn fuel_runs_out() {
+    assert_eq!(with_fuel(10_000).eval("(define (loop) (loop)) (loop)").err(), Some(Error::OutOfFuel));
+}
+
+#[test]
+fn denied_builtins_are_unbound() {
+    let sandbox = Sandbox::new(Limits::default()).unwrap();
+    assert_eq!(sandbox.eval("(open-input-file \"/etc/passwd\")").err(), Some(Error::Eval("undefined variable")));
+}
+
+#[test]
+fn timeout_stops_delete_duplicates() {
+    let sandbox = Sandbox::new(Limits { timeout: Some(Duration::from_millis(500)), ..Default::default() }).unwrap();
+    let start = Instant::now();
+    assert_eq!(sandbox.eval("(length (delete-duplicates (iota 100000)))").err(), Some(Error::Timeout));
+    assert!(start.elapsed() < Duration::from_secs(5), "took {:?}", start.elapsed());
+}
+
+// The programs below would finish within their fuel if only evaluation
+// steps spent it, so running out shows the builtin spending it as it goes.
+
+#[test]
+fn delete_duplicates_spends_fuel() {
+    assert_eq!(with_fuel(1000).eval("(delete-duplicates (iota 1000))").err(), Some(Error::OutOfFuel));
+}
+
+#[test]
+fn sorting_spends_fuel() {
+    for source in ["(sort (iota 10000) <)",
+                   "(list-sort < (iota 10000))",
+                   "(vector-sort! (list->vector (iota 10000)) <)"] {
+        assert_eq!(with_fuel(1000).eval(source).err(), Some(Error::OutOfFuel), "{}", source);
+    }
+}
+
+#[test]
+fn regexp_matching_spends_fuel() {
+    let source = r#"(regexp-search (string->regexp "(a|aa)*c") (make-string 100000 #\a))"#;
+    assert_eq!(with_fuel(100).eval(source).err(), Some(Error::OutOfFuel));
+}
+
+#[test]
+fn building_large_data_spends_fuel() {
+    for source in ["(make-list 1000000 0)",
+                   "(make-vector 1000000 0)",
+                   "(make-string 1000000 #\\a)",
+                   "(make-bytevector 1000000 0)",
+                   "(iota 1000000)"] {
+        assert_eq!(with_fuel(100).eval(source).err(), Some(Error::OutOfFuel), "{}", source);
+    }
+}
+
+#[test]
+fn deeply_nested_data_is_refused() {
+    let sandbox = Sandbox::new(Limits::default()).unwrap();
+    let list = format!("(quote {}{})", "(".repeat(200_000), ")".repeat(200_000));
+    assert_eq!(sandbox.eval(&list).err(), Some(Error::Eval("nested too deeply")));
+    let vector = format!("{}{}", "#(".repeat(300_000), ")".repeat(300_000));
+    assert_eq!(sandbox.eval(&vector).err(), Some(Error::Eval("nested too deeply")));
+    let quotes = format!("{}x", "'".repeat(200_000));
+    assert_eq!(sandbox.eval(&quotes).err(), Some(Error::Eval("nested too deeply")));
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+#[test]
+fn standard_libraries_are_built_in() {
+    on_both("(import (scheme base)) (car '(1))", Ok("1"));
+    on_both("(import (scheme write)) (with-output-to-string (lambda () (write \"a\")))", Ok("\"\\\"a\\\"\""));
+    on_both("(import (scheme char)) (char-upcase #\\a)", Ok("#\\A"));
+    on_both("(import (scheme cxr)) (caddr '(1 2 3))", Ok("3"));
+    on_both("(import (scheme read)) (read (open-input-string \"(1 2)\"))", Ok("(1 2)"));
+}
+
+#[test]
+fn standard_libraries_export_only_their_own_names() {
+    on_both("(import (only (scheme base) car)) (car '(1 2))", Ok("1"));
+    on_both("(import (only (scheme base) display))", Err("imported identifier is not exported"));
+    on_both("(import (scheme nonexistent))", Err("library not found"));
+}
+
+#[test]
+fn environments_can_be_made_from_standard_libraries() {
+    on_both("(eval '(car '(1)) (environment '(only (scheme base) car)))", Ok("1"));
+    on_both("(eval '(cdr '(1)) (environment '(only (scheme base) car)))", Err("undefined variable"));
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+#[test]
+fn output_strings_collect_what_is_written() {
+    on_both("(let ((p (open-output-string))) (write 'a p) (display \" \" p) (write \"s\" p) (get-output-string p))",
+            Ok("\"a \\\"s\\\"\""));
+    on_both("(call-with-output-string (lambda (p) (write '(1 . 2) p)))", Ok("\"(1 . 2)\""));
+    on_both("(let ((p (open-output-bytevector))) (write-u8 7 p) (get-output-bytevector p))", Ok("#u8(7)"));
+}
+
+#[test]
+fn input_strings_can_be_read() {
+    on_both("(let ((p (open-input-string \"hello (1 2)\"))) (list (read p) (read p) (eof-object? (read p))))",
+            Ok("(hello (1 2) #t)"));
+    on_both("(let ((p (open-input-string \"ab\ncd\"))) (list (read-line p) (read-char p) (char-ready? p)))",
+            Ok("(\"ab\" #\\c #t)"));
+    on_both("(read-u8 (open-input-bytevector #u8(9)))", Ok("9"));
+}
+
+#[test]
+fn with_output_to_string_captures_the_current_output() {
+    on_both("(with-output-to-string (lambda () (display 1) (write-char #\\x)))", Ok("\"1x\""));
+    on_both("(with-output-to-string (lambda () 1))", Ok("\"\""));
+    on_both("(with-output-to-string 5)", Err("unable to apply"));
+}
+
+#[test]
+fn with_output_to_string_restores_the_output_after_an_error() {
+    on_both("(define out (current-output-port))
+             (define (f) (with-output-to-string (lambda () (car '()))) (eq? out (current-output-port)))
+             (f)",
+            Ok("#t"));
+}
+
+#[test]
+fn only_output_string_ports_have_contents() {
+    on_both("(get-output-string (open-input-string \"x\"))", Err("expecting a string or bytevector output port"));
+    on_both("(get-output-string (open-output-bytevector))", Err("expecting a string output port"));
+    on_both("(define p (open-output-string)) (close-port p) (get-output-string p)", Err("port is closed"));
+}
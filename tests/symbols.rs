@@ -0,0 +1,43 @@
+mod common;
+
+use common::{run_with, EVALUATORS};
+
+fn on_both(source: &str, expected: Result<&str, &str>) {
+    for (name, evaluator) in EVALUATORS {
+        assert_eq!(run_with(source, *evaluator), expected.map(str::to_string), "{}: {}", name, source);
+    }
+}
+
+#[test]
+fn quoted_symbols_are_data() {
+    on_both("(define x 5) (list (car '(x)) (symbol? (car '(x))))", Ok("(x #t)"));
+    on_both("(define (f) 'undefined-name) (f)", Ok("undefined-name"));
+    on_both("''a", Ok("(quote a)"));
+    on_both("(define x 5) (eval 'x (interaction-environment))", Ok("5"));
+}
+
+#[test]
+fn symbols_are_interned() {
+    on_both("(eq? 'abc (string->symbol \"abc\"))", Ok("#t"));
+    on_both("(eq? 'Abc 'abc)", Ok("#f"));
+    on_both("(symbol=? 'a 'a 'b)", Ok("#f"));
+    on_both("(symbol-interned? 'a)", Ok("#t"));
+}
+
+#[test]
+fn uninterned_symbols_are_unique() {
+    on_both("(symbol-interned? (generate-uninterned-symbol))", Ok("#f"));
+    on_both("(eq? (gensym) (gensym))", Ok("#f"));
+    on_both("(let ((s (generate-uninterned-symbol))) (eq? s (string->symbol (symbol->string s))))", Ok("#f"));
+}
+
+#[test]
+fn conversion_to_and_from_strings() {
+    on_both("(symbol->string 'abc)", Ok("\"abc\""));
+    on_both("(string->symbol \"hello world\")", Ok("|hello world|"));
+    on_both("'|hello world|", Ok("|hello world|"));
+    on_both("(string->symbol \"\")", Ok("||"));
+    on_both("(symbol->string 5)", Err("expecting a symbol"));
+    on_both("(string->symbol 'a)", Err("expecting a string"));
+    on_both("(symbol=? 'a 5)", Err("expecting a symbol"));
+}